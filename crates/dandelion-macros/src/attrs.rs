@@ -0,0 +1,127 @@
+use proc_macro2::Ident;
+use syn::{Attribute, LitInt, LitStr, Result};
+
+const UNSIGNED_REPRS: [&str; 4] = ["u8", "u16", "u32", "u64"];
+
+/// `#[wire(..)]` on a struct.
+#[derive(Default)]
+pub struct Container {
+    /// `fixed`: implement `FixedSizeSerializable` even if not every field is a primitive.
+    pub fixed: bool,
+}
+
+/// `#[wire(..)]` and `#[print(..)]` on a field.
+#[derive(Default)]
+pub struct Field {
+    /// `wire(nested)`: frame the field with a length, as `util::nested_write` does.
+    pub nested: bool,
+    /// `print(redact)`: print `<redacted>` instead of the value.
+    pub redact: bool,
+}
+
+/// `#[wire(..)]` on an enum variant.
+#[derive(Default)]
+pub struct Variant {
+    pub code: Option<LitInt>,
+    pub name: Option<LitStr>,
+    pub kind: VariantKind,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum VariantKind {
+    /// A single field, framed with a length.
+    #[default]
+    Nested,
+    /// `padding`: a `usize` count of zero bytes.
+    Padding,
+    /// `other`: `{ code: u16, payload: Bytes }`, for codes no other variant claims.
+    Other,
+    /// `dynamic`: a single field that supplies its own code, name and payload.
+    Dynamic,
+}
+
+pub fn container(attrs: &[Attribute]) -> Result<Container> {
+    let mut result = Container::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("wire")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("fixed") {
+                result.fixed = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown wire attribute"))
+            }
+        })?;
+    }
+    Ok(result)
+}
+
+pub fn field(attrs: &[Attribute]) -> Result<Field> {
+    let mut result = Field::default();
+    for attr in attrs {
+        if attr.path().is_ident("wire") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("nested") {
+                    result.nested = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown wire attribute"))
+                }
+            })?;
+        } else if attr.path().is_ident("print") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("redact") {
+                    result.redact = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown print attribute"))
+                }
+            })?;
+        }
+    }
+    Ok(result)
+}
+
+pub fn variant(attrs: &[Attribute]) -> Result<Variant> {
+    let mut result = Variant::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("wire")) {
+        attr.parse_nested_meta(|meta| {
+            let kind = if meta.path.is_ident("code") {
+                result.code = Some(meta.value()?.parse()?);
+                return Ok(());
+            } else if meta.path.is_ident("name") {
+                result.name = Some(meta.value()?.parse()?);
+                return Ok(());
+            } else if meta.path.is_ident("padding") {
+                VariantKind::Padding
+            } else if meta.path.is_ident("other") {
+                VariantKind::Other
+            } else if meta.path.is_ident("dynamic") {
+                VariantKind::Dynamic
+            } else {
+                return Err(meta.error("unknown wire attribute"));
+            };
+            if result.kind != VariantKind::Nested {
+                return Err(meta.error("conflicting wire attributes"));
+            }
+            result.kind = kind;
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
+
+/// The unsigned integer named by `#[repr(..)]`, if any.
+pub fn repr(attrs: &[Attribute]) -> Result<Option<Ident>> {
+    let mut result = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if let Some(ident) = meta.path.get_ident() {
+                if UNSIGNED_REPRS.contains(&ident.to_string().as_str()) {
+                    result = Some(ident.clone());
+                }
+            }
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
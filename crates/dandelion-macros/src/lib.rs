@@ -0,0 +1,129 @@
+//! Derives for `dandelion_wire::Serializable` and `dandelion_wire::Printable`.  Use them through
+//! the re-exports in `dandelion_wire`; the generated code refers to that crate by name.
+//!
+//! The wire format is the same as the `impl_serializable_for_struct!` family of macros produces.
+
+use proc_macro::TokenStream;
+use proc_macro2::Ident;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Type};
+
+mod attrs;
+mod printable;
+mod serializable;
+
+/// Implements `BaseSerializable` plus either `FixedSizeSerializable` or `Serializable`.
+///
+/// - A struct with named fields writes its fields in order.  Decode errors record the field as
+///   `Type.field`.  It is fixed size if every field is a primitive or `[u8; N]`, or if marked
+///   `#[wire(fixed)]`.  A field marked `#[wire(nested)]` is framed with a length.
+/// - A struct with one unnamed field is written as that field.
+/// - An enum without fields must have `#[repr(u8)]` (or another unsigned integer) and an explicit
+///   discriminant for every variant, which is written as its code.  This also generates associated
+///   `CODE_*` and `NAME_*` constants, `ALL_CODES`, `from_code`, `code` and `name`, and conversions
+///   to and from the code.
+///   Use `#[wire(name = "..")]` to override a variant's name.
+/// - An enum with fields writes a `u16` code then its variant's field, framed with a length.  Each
+///   variant has one field and a `#[wire(code = ..)]`, except for these:
+///   - `#[wire(code = .., padding)]`: a length-prefixed run of zero bytes.
+///   - `#[wire(other)]`: `{ code: u16, payload: Bytes }`, which keeps unknown codes instead of
+///     failing on them.
+///   - `#[wire(dynamic)]`: a field with `code`, `name`, `payload_size(format)` and
+///     `write_payload(buffer, format)` methods.  It is written but never read.
+///
+///   This also generates associated `CODE_*` and `NAME_*` constants, `ALL_CODES`, `code` and
+///   `name`.
+#[proc_macro_derive(Serializable, attributes(wire, print))]
+pub fn derive_serializable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    serializable::derive(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// Implements `Printable`, plus `Debug` and `Display` in terms of it.
+///
+/// Structs print as `{field: value, ..}`, or `<redacted>` for a field marked `#[print(redact)]`.
+/// Wrappers print their field.  Enums print their name, followed by their field in parentheses
+/// if they have one; they need `#[derive(Serializable)]` for `name`.
+#[proc_macro_derive(Printable, attributes(wire, print))]
+pub fn derive_printable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    printable::derive(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+enum Shape<'a> {
+    Struct(&'a syn::FieldsNamed),
+    Wrapper(&'a syn::Field),
+    CodeEnum(&'a syn::DataEnum),
+    MessageEnum(&'a syn::DataEnum),
+}
+
+fn shape(input: &DeriveInput) -> syn::Result<Shape<'_>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(Shape::Struct(fields)),
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                Ok(Shape::Wrapper(&fields.unnamed[0]))
+            },
+            _ => Err(syn::Error::new_spanned(
+                &input.ident,
+                "expected named fields or exactly one unnamed field",
+            )),
+        },
+        Data::Enum(data) if data.variants.iter().all(|v| v.fields.is_empty()) => {
+            Ok(Shape::CodeEnum(data))
+        },
+        Data::Enum(data) => Ok(Shape::MessageEnum(data)),
+        Data::Union(_) => Err(syn::Error::new_spanned(&input.ident, "unions are not supported")),
+    }
+}
+
+/// `StemEnvelope` becomes `STEM_ENVELOPE`, `NoiseXX` becomes `NOISE_XX`.
+fn screaming_snake(ident: &Ident) -> Ident {
+    let chars: Vec<char> = ident.to_string().chars().collect();
+    let mut result = String::new();
+    for (index, &ch) in chars.iter().enumerate() {
+        if index > 0 && ch.is_uppercase() {
+            let prev = chars[index - 1];
+            let next_is_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            if !prev.is_uppercase() || next_is_lower {
+                result.push('_');
+            }
+        }
+        result.push(ch.to_ascii_uppercase());
+    }
+    Ident::new(&result, ident.span())
+}
+
+/// Whether `ty` is known to implement `FixedSizeSerializable`.  Anything else needs
+/// `#[wire(fixed)]`, since a derive cannot look up other types.
+fn is_fixed_type(ty: &Type) -> bool {
+    const PRIMITIVES: [&str; 13] = [
+        "u8", "u16", "u32", "u64", "u128", "i8", "i16", "i32", "i64", "i128", "f32", "f64", "bool",
+    ];
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path
+            .path
+            .get_ident()
+            .is_some_and(|ident| PRIMITIVES.contains(&ident.to_string().as_str())),
+        Type::Array(array) => {
+            matches!(&*array.elem, Type::Path(elem) if elem.path.is_ident("u8"))
+        },
+        Type::Tuple(tuple) => tuple.elems.is_empty(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screaming_snake_case() {
+        let convert = |name: &str| {
+            screaming_snake(&Ident::new(name, proc_macro2::Span::call_site())).to_string()
+        };
+        assert_eq!("STEM_ENVELOPE", convert("StemEnvelope"));
+        assert_eq!("NOISE_XX", convert("NoiseXX"));
+        assert_eq!("HTTP_SERVER", convert("HTTPServer"));
+        assert_eq!("HIGH", convert("High"));
+    }
+}
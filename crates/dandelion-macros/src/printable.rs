@@ -0,0 +1,102 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DataEnum, DeriveInput, FieldsNamed, Result};
+
+use crate::attrs::{self, VariantKind};
+use crate::{shape, Shape};
+
+pub fn derive(input: &DeriveInput) -> Result<TokenStream> {
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let (body, debug) = match shape(input)? {
+        Shape::Struct(fields) => (print_struct(fields)?, None),
+        Shape::Wrapper(_) => (quote!(dandelion_wire::Printable::print(&self.0, writer)), None),
+        Shape::CodeEnum(_) => {
+            let prefix = format!("{ty}::");
+            let debug = quote! {
+                fmt.write_str(#prefix)?;
+                fmt.write_str(self.name())
+            };
+            (quote!(writer.write_str(self.name())), Some(debug))
+        },
+        Shape::MessageEnum(data) => (print_message_enum(data)?, None),
+    };
+    let debug = debug.unwrap_or_else(|| {
+        quote! {
+            fmt.write_str(::core::any::type_name::<Self>())?;
+            fmt.write_str("(")?;
+            dandelion_wire::Printable::print(self, fmt)?;
+            fmt.write_str(")")
+        }
+    });
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics dandelion_wire::Printable for #ty #ty_generics #where_clause {
+            fn print(&self, writer: &mut dyn ::core::fmt::Write) -> ::core::fmt::Result {
+                #body
+            }
+        }
+
+        impl #impl_generics ::core::fmt::Debug for #ty #ty_generics #where_clause {
+            fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                #debug
+            }
+        }
+
+        impl #impl_generics ::core::fmt::Display for #ty #ty_generics #where_clause {
+            fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                dandelion_wire::Printable::print(self, fmt)
+            }
+        }
+    })
+}
+
+fn print_struct(fields: &FieldsNamed) -> Result<TokenStream> {
+    let mut parts = Vec::new();
+    for (index, field) in fields.named.iter().enumerate() {
+        let name = field.ident.as_ref().unwrap();
+        let label = format!("{}{name}: ", if index == 0 { "{" } else { ", " });
+        parts.push(quote!(writer.write_str(#label)?;));
+        parts.push(if attrs::field(&field.attrs)?.redact {
+            quote!(dandelion_wire::printable::print_secret_bytes(writer)?;)
+        } else {
+            quote!(dandelion_wire::Printable::print(&self.#name, writer)?;)
+        });
+    }
+    let close = if parts.is_empty() { "{}" } else { "}" };
+    Ok(quote! {
+        #( #parts )*
+        writer.write_str(#close)
+    })
+}
+
+fn print_message_enum(data: &DataEnum) -> Result<TokenStream> {
+    let mut arms = Vec::new();
+    for variant in &data.variants {
+        let ident = &variant.ident;
+        arms.push(match attrs::variant(&variant.attrs)?.kind {
+            VariantKind::Nested => {
+                quote!(Self::#ident(value) => dandelion_wire::Printable::print(value, writer)?,)
+            },
+            VariantKind::Padding => quote!(Self::#ident(len) => write!(writer, "{}", len)?,),
+            VariantKind::Other => quote! {
+                Self::#ident { code, payload } => {
+                    write!(writer, "{{code: {}, payload: ", code)?;
+                    dandelion_wire::printable::print_public_bytes(writer, payload.as_ref())?;
+                    writer.write_str("}")?;
+                },
+            },
+            VariantKind::Dynamic => {
+                quote!(Self::#ident(value) => dandelion_wire::Printable::print(&**value, writer)?,)
+            },
+        });
+    }
+    Ok(quote! {
+        writer.write_str(self.name())?;
+        writer.write_str("(")?;
+        match self {
+            #( #arms )*
+        }
+        writer.write_str(")")
+    })
+}
@@ -0,0 +1,473 @@
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::{DataEnum, DeriveInput, Error, Fields, FieldsNamed, Result};
+
+use crate::attrs::{self, VariantKind};
+use crate::{is_fixed_type, screaming_snake, shape, Shape};
+
+pub fn derive(input: &DeriveInput) -> Result<TokenStream> {
+    match shape(input)? {
+        Shape::Struct(fields) => derive_struct(input, fields),
+        Shape::Wrapper(field) => derive_wrapper(input, field),
+        Shape::CodeEnum(data) => derive_code_enum(input, data),
+        Shape::MessageEnum(data) => derive_message_enum(input, data),
+    }
+}
+
+fn derive_struct(input: &DeriveInput, fields: &FieldsNamed) -> Result<TokenStream> {
+    let ty = &input.ident;
+    let container = attrs::container(&input.attrs)?;
+    let mut all_fixed = true;
+    let (mut writes, mut reads, mut skips, mut sizes, mut fixed_sizes) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for field in &fields.named {
+        let name = field.ident.as_ref().unwrap();
+        let field_ty = &field.ty;
+        let context = format!("{ty}.{name}");
+        let in_field = quote!(.map_err(|err| err.in_field(#context))?);
+        if attrs::field(&field.attrs)?.nested {
+            if container.fixed {
+                return Err(Error::new_spanned(field, "a nested field is never fixed size"));
+            }
+            all_fixed = false;
+            writes.push(quote!(dandelion_wire::util::nested_write(buffer, format, &self.#name);));
+            reads.push(
+                quote!(#name: dandelion_wire::util::nested_read::<#field_ty>(buffer, context) #in_field,),
+            );
+            skips.push(quote!(dandelion_wire::util::varlen_skip(buffer, context) #in_field;));
+            sizes.push(quote!(dandelion_wire::util::nested_wire_size(format, &self.#name)));
+        } else {
+            all_fixed &= is_fixed_type(field_ty);
+            writes.push(
+                quote!(<#field_ty as dandelion_wire::BaseSerializable>::wire_write(&self.#name, buffer, format);),
+            );
+            reads.push(quote!(
+                #name: <#field_ty as dandelion_wire::BaseSerializable>::wire_read(buffer, context) #in_field,
+            ));
+            skips.push(quote!(
+                <#field_ty as dandelion_wire::BaseSerializable>::wire_skip(buffer, context) #in_field;
+            ));
+            sizes.push(
+                quote!(<#field_ty as dandelion_wire::Serializable>::wire_size(&self.#name, format)),
+            );
+            fixed_sizes
+                .push(quote!(<#field_ty as dandelion_wire::FixedSizeSerializable>::WIRE_SIZE));
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let size_impl = if container.fixed || all_fixed {
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics dandelion_wire::FixedSizeSerializable for #ty #ty_generics #where_clause {
+                const WIRE_SIZE: usize = 0usize #( .strict_add(#fixed_sizes) )*;
+            }
+        }
+    } else {
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics dandelion_wire::Serializable for #ty #ty_generics #where_clause {
+                fn wire_size(&self, format: dandelion_wire::WireFormat) -> usize {
+                    0usize #( .strict_add(#sizes) )*
+                }
+            }
+        }
+    };
+    Ok(quote! {
+        #[automatically_derived]
+        #[allow(unused_variables)]
+        impl #impl_generics dandelion_wire::BaseSerializable for #ty #ty_generics #where_clause {
+            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut, format: dandelion_wire::WireFormat) {
+                #( #writes )*
+            }
+            fn wire_read(
+                buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
+            ) -> dandelion_wire::Result<Self> {
+                ::core::result::Result::Ok(Self { #( #reads )* })
+            }
+            fn wire_skip(
+                buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
+            ) -> dandelion_wire::Result<()> {
+                #( #skips )*
+                ::core::result::Result::Ok(())
+            }
+        }
+        #size_impl
+    })
+}
+
+fn derive_wrapper(input: &DeriveInput, field: &syn::Field) -> Result<TokenStream> {
+    let ty = &input.ident;
+    let inner = &field.ty;
+    if attrs::field(&field.attrs)?.nested {
+        return Err(Error::new_spanned(field, "a wrapper cannot be nested"));
+    }
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let size_impl = if attrs::container(&input.attrs)?.fixed || is_fixed_type(inner) {
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics dandelion_wire::FixedSizeSerializable for #ty #ty_generics #where_clause {
+                const WIRE_SIZE: usize = <#inner as dandelion_wire::FixedSizeSerializable>::WIRE_SIZE;
+            }
+        }
+    } else {
+        quote! {
+            #[automatically_derived]
+            impl #impl_generics dandelion_wire::Serializable for #ty #ty_generics #where_clause {
+                fn wire_size(&self, format: dandelion_wire::WireFormat) -> usize {
+                    <#inner as dandelion_wire::Serializable>::wire_size(&self.0, format)
+                }
+            }
+        }
+    };
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics dandelion_wire::BaseSerializable for #ty #ty_generics #where_clause {
+            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut, format: dandelion_wire::WireFormat) {
+                <#inner as dandelion_wire::BaseSerializable>::wire_write(&self.0, buffer, format)
+            }
+            fn wire_read(
+                buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
+            ) -> dandelion_wire::Result<Self> {
+                ::core::result::Result::Ok(Self(
+                    <#inner as dandelion_wire::BaseSerializable>::wire_read(buffer, context)?,
+                ))
+            }
+            fn wire_skip(
+                buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
+            ) -> dandelion_wire::Result<()> {
+                <#inner as dandelion_wire::BaseSerializable>::wire_skip(buffer, context)
+            }
+        }
+        #size_impl
+    })
+}
+
+fn derive_code_enum(input: &DeriveInput, data: &DataEnum) -> Result<TokenStream> {
+    let ty = &input.ident;
+    let type_name = ty.to_string();
+    let repr = attrs::repr(&input.attrs)?.ok_or_else(|| {
+        Error::new_spanned(ty, "expected #[repr(u8)], #[repr(u16)], #[repr(u32)] or #[repr(u64)]")
+    })?;
+    let (mut variants, mut code_consts, mut name_consts, mut names) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for variant in &data.variants {
+        if variant.discriminant.is_none() {
+            return Err(Error::new_spanned(variant, "expected an explicit discriminant"));
+        }
+        let attrs = attrs::variant(&variant.attrs)?;
+        if attrs.code.is_some() || attrs.kind != VariantKind::Nested {
+            return Err(Error::new_spanned(variant, "only #[wire(name = ..)] applies here"));
+        }
+        variants.push(&variant.ident);
+        let konst = screaming_snake(&variant.ident);
+        code_consts.push(format_ident!("CODE_{}", konst));
+        name_consts.push(format_ident!("NAME_{}", konst));
+        names.push(attrs.name.map_or_else(|| variant.ident.to_string(), |name| name.value()));
+    }
+
+    let count = variants.len();
+    Ok(quote! {
+        impl #ty {
+            #( pub const #code_consts: #repr = Self::#variants as #repr; )*
+            #( pub const #name_consts: &'static str = #names; )*
+            /// Every variant's code.
+            pub const ALL_CODES: [#repr; #count] = [ #( Self::#code_consts ),* ];
+
+            pub fn from_code(code: #repr) -> dandelion_wire::Result<Self> {
+                match code {
+                    #( Self::#code_consts => ::core::result::Result::Ok(Self::#variants), )*
+                    _ => ::core::result::Result::Err(
+                        dandelion_wire::ErrorKind::UnknownCode {
+                            type_name: #type_name,
+                            code: ::core::convert::From::from(code),
+                        }
+                        .into(),
+                    ),
+                }
+            }
+            pub fn code(self) -> #repr {
+                match self {
+                    #( Self::#variants => Self::#code_consts, )*
+                }
+            }
+            pub fn name(self) -> &'static str {
+                match self {
+                    #( Self::#variants => Self::#name_consts, )*
+                }
+            }
+        }
+
+        #[automatically_derived]
+        impl dandelion_wire::BaseSerializable for #ty {
+            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut, format: dandelion_wire::WireFormat) {
+                <#repr as dandelion_wire::BaseSerializable>::wire_write(&self.code(), buffer, format)
+            }
+            fn wire_read(
+                buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
+            ) -> dandelion_wire::Result<Self> {
+                Self::from_code(<#repr as dandelion_wire::BaseSerializable>::wire_read(buffer, context)?)
+            }
+            fn wire_skip(
+                buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
+            ) -> dandelion_wire::Result<()> {
+                <#repr as dandelion_wire::BaseSerializable>::wire_skip(buffer, context)
+            }
+        }
+
+        #[automatically_derived]
+        impl dandelion_wire::FixedSizeSerializable for #ty {
+            const WIRE_SIZE: usize = <#repr as dandelion_wire::FixedSizeSerializable>::WIRE_SIZE;
+        }
+
+        impl ::core::convert::TryFrom<#repr> for #ty {
+            type Error = dandelion_wire::Error;
+            fn try_from(code: #repr) -> dandelion_wire::Result<Self> {
+                Self::from_code(code)
+            }
+        }
+
+        impl ::core::convert::From<#ty> for #repr {
+            fn from(value: #ty) -> Self {
+                value.code()
+            }
+        }
+
+        impl ::core::convert::From<#ty> for &'static str {
+            fn from(value: #ty) -> &'static str {
+                value.name()
+            }
+        }
+    })
+}
+
+struct MessageVariant<'a> {
+    ident: &'a Ident,
+    konst: Ident,
+    name: String,
+    code: Option<syn::LitInt>,
+    kind: VariantKind,
+    field_ty: Option<&'a syn::Type>,
+}
+
+fn message_variants(data: &DataEnum) -> Result<Vec<MessageVariant<'_>>> {
+    let mut result = Vec::new();
+    let mut seen_other = false;
+    for variant in &data.variants {
+        let attrs = attrs::variant(&variant.attrs)?;
+        let field_ty = match (&variant.fields, attrs.kind) {
+            (
+                Fields::Unnamed(fields),
+                VariantKind::Nested | VariantKind::Padding | VariantKind::Dynamic,
+            ) if fields.unnamed.len() == 1 => Some(&fields.unnamed[0].ty),
+            (Fields::Named(fields), VariantKind::Other)
+                if fields.named.len() == 2
+                    && fields.named.iter().any(|f| f.ident.as_ref().unwrap() == "code")
+                    && fields.named.iter().any(|f| f.ident.as_ref().unwrap() == "payload") =>
+            {
+                None
+            },
+            (_, VariantKind::Other) => {
+                return Err(Error::new_spanned(variant, "expected `{ code: u16, payload: Bytes }`"))
+            },
+            _ => return Err(Error::new_spanned(variant, "expected exactly one unnamed field")),
+        };
+        let needs_code = matches!(attrs.kind, VariantKind::Nested | VariantKind::Padding);
+        if needs_code != attrs.code.is_some() {
+            let message = if needs_code {
+                "expected #[wire(code = ..)]"
+            } else {
+                "this variant takes its code from its value"
+            };
+            return Err(Error::new_spanned(variant, message));
+        }
+        if attrs.kind == VariantKind::Other {
+            if seen_other {
+                return Err(Error::new_spanned(variant, "only one variant can be #[wire(other)]"));
+            }
+            seen_other = true;
+        }
+        result.push(MessageVariant {
+            ident: &variant.ident,
+            konst: screaming_snake(&variant.ident),
+            name: attrs.name.map_or_else(|| variant.ident.to_string(), |name| name.value()),
+            code: attrs.code,
+            kind: attrs.kind,
+            field_ty,
+        });
+    }
+    Ok(result)
+}
+
+fn derive_message_enum(input: &DeriveInput, data: &DataEnum) -> Result<TokenStream> {
+    let ty = &input.ident;
+    let type_name = ty.to_string();
+    let variants = message_variants(data)?;
+    let coded: Vec<_> = variants.iter().filter(|v| v.code.is_some()).collect();
+    let named: Vec<_> = variants.iter().filter(|v| v.kind != VariantKind::Dynamic).collect();
+    let coded_consts: Vec<_> = coded.iter().map(|v| format_ident!("CODE_{}", v.konst)).collect();
+    let coded_values = coded.iter().map(|v| v.code.as_ref().unwrap());
+    let count = coded.len();
+    let named_consts = named.iter().map(|v| format_ident!("NAME_{}", v.konst));
+    let named_values = named.iter().map(|v| &v.name);
+
+    let (mut code_arms, mut name_arms) = (Vec::new(), Vec::new());
+    let (mut write_arms, mut read_arms, mut size_arms) = (Vec::new(), Vec::new(), Vec::new());
+    let mut fallback = quote! {
+        code => ::core::result::Result::Err(
+            dandelion_wire::ErrorKind::UnknownCode {
+                type_name: #type_name,
+                code: ::core::convert::From::from(code),
+            }
+            .into(),
+        ),
+    };
+    for variant in &variants {
+        let (ident, literal) = (variant.ident, &variant.code);
+        let (code, name) =
+            (format_ident!("CODE_{}", variant.konst), format_ident!("NAME_{}", variant.konst));
+        let write_code = quote!(<u16 as dandelion_wire::BaseSerializable>::wire_write);
+        match variant.kind {
+            VariantKind::Nested => {
+                let field_ty = variant.field_ty.unwrap();
+                let context = format!("{ty}.{ident}");
+                code_arms.push(quote!(Self::#ident(_) => Self::#code,));
+                name_arms.push(quote!(Self::#ident(_) => Self::#name,));
+                write_arms.push(quote! {
+                    Self::#ident(value) => {
+                        #write_code(&Self::#code, buffer, format);
+                        dandelion_wire::util::nested_write(buffer, format, value);
+                    },
+                });
+                read_arms.push(quote! {
+                    #literal => ::core::result::Result::Ok(Self::#ident(
+                        dandelion_wire::util::nested_read::<#field_ty>(buffer, context)
+                            .map_err(|err| err.in_field(#context))?,
+                    )),
+                });
+                size_arms.push(
+                    quote!(Self::#ident(value) => dandelion_wire::util::nested_wire_size(format, value),),
+                );
+            },
+            VariantKind::Padding => {
+                code_arms.push(quote!(Self::#ident(_) => Self::#code,));
+                name_arms.push(quote!(Self::#ident(_) => Self::#name,));
+                write_arms.push(quote! {
+                    Self::#ident(len) => {
+                        #write_code(&Self::#code, buffer, format);
+                        dandelion_wire::util::varlen_fill(buffer, format, 0, *len);
+                    },
+                });
+                read_arms.push(quote! {
+                    #literal => ::core::result::Result::Ok(Self::#ident(
+                        dandelion_wire::util::varlen_skip(buffer, context)?,
+                    )),
+                });
+                size_arms.push(
+                    quote!(Self::#ident(len) => dandelion_wire::util::varlen_wire_size(format, *len),),
+                );
+            },
+            VariantKind::Other => {
+                code_arms.push(quote!(Self::#ident { code, .. } => *code,));
+                name_arms.push(quote!(Self::#ident { .. } => Self::#name,));
+                write_arms.push(quote! {
+                    Self::#ident { code, payload } => {
+                        #write_code(code, buffer, format);
+                        dandelion_wire::util::varlen_write(buffer, format, payload.as_ref());
+                    },
+                });
+                fallback = quote! {
+                    code => ::core::result::Result::Ok(Self::#ident {
+                        code,
+                        payload: dandelion_wire::util::varlen_read(buffer, context)?,
+                    }),
+                };
+                size_arms.push(quote! {
+                    Self::#ident { payload, .. } => {
+                        dandelion_wire::util::varlen_wire_size(format, payload.len())
+                    },
+                });
+            },
+            VariantKind::Dynamic => {
+                code_arms.push(quote!(Self::#ident(value) => value.code(),));
+                name_arms.push(quote!(Self::#ident(value) => value.name(),));
+                write_arms.push(quote! {
+                    Self::#ident(value) => {
+                        #write_code(&value.code(), buffer, format);
+                        <usize as dandelion_wire::BaseSerializable>::wire_write(
+                            &value.payload_size(format),
+                            buffer,
+                            format,
+                        );
+                        value.write_payload(buffer, format);
+                    },
+                });
+                size_arms.push(quote! {
+                    Self::#ident(value) => dandelion_wire::util::varlen_wire_size(format, value.payload_size(format)),
+                });
+            },
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #ty #ty_generics #where_clause {
+            #( pub const #coded_consts: u16 = #coded_values; )*
+            #( pub const #named_consts: &'static str = #named_values; )*
+            /// Every code with a variant of its own.
+            pub const ALL_CODES: [u16; #count] = [ #( Self::#coded_consts ),* ];
+
+            pub fn code(&self) -> u16 {
+                match self {
+                    #( #code_arms )*
+                }
+            }
+            pub fn name(&self) -> &'static str {
+                match self {
+                    #( #name_arms )*
+                }
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics dandelion_wire::BaseSerializable for #ty #ty_generics #where_clause {
+            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut, format: dandelion_wire::WireFormat) {
+                match self {
+                    #( #write_arms )*
+                }
+            }
+            fn wire_read(
+                buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
+            ) -> dandelion_wire::Result<Self> {
+                match <u16 as dandelion_wire::BaseSerializable>::wire_read(buffer, context)? {
+                    #( #read_arms )*
+                    #fallback
+                }
+            }
+            fn wire_skip(
+                buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
+            ) -> dandelion_wire::Result<()> {
+                <u16 as dandelion_wire::BaseSerializable>::wire_skip(buffer, context)?;
+                dandelion_wire::util::varlen_skip(buffer, context)?;
+                ::core::result::Result::Ok(())
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics dandelion_wire::Serializable for #ty #ty_generics #where_clause {
+            fn wire_size(&self, format: dandelion_wire::WireFormat) -> usize {
+                <u16 as dandelion_wire::FixedSizeSerializable>::WIRE_SIZE.strict_add(match self {
+                    #( #size_arms )*
+                })
+            }
+        }
+    })
+}
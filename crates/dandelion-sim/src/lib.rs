@@ -0,0 +1,405 @@
+//! A deterministic, in-process network of virtual Dandelion nodes.
+//!
+//! Time is virtual: the simulator jumps straight to the next scheduled event, so minutes of
+//! simulated traffic take milliseconds.  Every random choice (link jitter and loss, and each
+//! node's own stream) is drawn from a generator seeded by [`Simulator::new`], so a run can be
+//! replayed bit-for-bit from its seed and compared through [`Simulator::log`].
+
+use std::collections::BTreeMap;
+
+use dandelion::{Duration, Entity, Instant, Message};
+use dandelion_wire::{Error, ErrorKind, Result, Serializable, WireFormat};
+
+pub mod link;
+pub mod node;
+pub mod rng;
+
+use link::Link;
+pub use link::{Fate, LinkConfig};
+pub use node::{Context, Node, StandardNode};
+pub use rng::SimRng;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+/// One message handed to a link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub sent: Instant,
+    /// When the message arrives, or `sent` if it never does.
+    pub arrives: Instant,
+    pub from: NodeId,
+    pub to: NodeId,
+    pub code: u16,
+    /// The message's wire size in [`WireFormat::V1`].
+    pub size: usize,
+    pub fate: Fate,
+}
+
+/// A message a node failed to handle.  The message is dropped and the simulation goes on, as it
+/// would for a real node receiving garbage from a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Failure {
+    pub at: Instant,
+    pub from: NodeId,
+    pub to: NodeId,
+    pub error: Error,
+}
+
+pub struct Simulator<N: Node> {
+    now: Instant,
+    rng: SimRng,
+    nodes: Vec<Slot<N>>,
+    links: BTreeMap<(NodeId, NodeId), Link>,
+    queue: BTreeMap<(Instant, u64), Event>,
+    sequence: u64,
+    log: Vec<Record>,
+    failures: Vec<Failure>,
+}
+
+struct Slot<N> {
+    node: N,
+    entity: Entity,
+    rng: SimRng,
+    /// The time of the pending wake-up event, if any.
+    wake: Option<Instant>,
+}
+
+enum Event {
+    Deliver { from: NodeId, to: NodeId, message: Message },
+    Wake { node: NodeId },
+}
+
+impl<N: Node> Simulator<N> {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            now: Instant::ZERO,
+            rng: SimRng::new(seed),
+            nodes: Vec::new(),
+            links: BTreeMap::new(),
+            queue: BTreeMap::new(),
+            sequence: 0,
+            log: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn now(&self) -> Instant {
+        self.now
+    }
+
+    /// Adds a node built by `make`, which may draw keys from the simulation's generator.
+    pub fn add_node(&mut self, make: impl FnOnce(&mut SimRng) -> N) -> NodeId {
+        let node = make(&mut self.rng);
+        let rng = self.rng.fork();
+        let entity = node.entity();
+        self.nodes.push(Slot { node, entity, rng, wake: None });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, id: NodeId) -> &N {
+        &self.nodes[id.0].node
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut N {
+        &mut self.nodes[id.0].node
+    }
+
+    pub fn entity(&self, id: NodeId) -> Entity {
+        self.nodes[id.0].entity
+    }
+
+    /// Runs `f` as if the node were acting on its own at the current time, e.g. to originate
+    /// traffic, and delivers whatever it sends.
+    pub fn act<R>(&mut self, id: NodeId, f: impl FnOnce(&mut N, &mut Context) -> R) -> R {
+        let slot = &mut self.nodes[id.0];
+        let mut ctx = Context::new(self.now, &mut slot.rng);
+        let result = f(&mut slot.node, &mut ctx);
+        let outbox = ctx.into_outbox();
+        self.dispatch(id, outbox);
+        result
+    }
+
+    /// Links `a` and `b` in both directions.
+    pub fn connect(&mut self, a: NodeId, b: NodeId, config: LinkConfig) -> Result<()> {
+        self.links.insert((a, b), Link { config, partitioned: false });
+        self.links.insert((b, a), Link { config, partitioned: false });
+        let (entity_a, entity_b) = (self.entity(a), self.entity(b));
+        self.act(a, |node, ctx| node.connect(entity_b, ctx))?;
+        self.act(b, |node, ctx| node.connect(entity_a, ctx))
+    }
+
+    /// Changes one direction of an existing link.
+    pub fn set_link(&mut self, from: NodeId, to: NodeId, config: LinkConfig) -> Result<()> {
+        self.links.get_mut(&(from, to)).ok_or(ErrorKind::NotFound)?.config = config;
+        Ok(())
+    }
+
+    /// Cuts every link between `side` and the rest of the network.  Messages already in flight
+    /// still arrive.
+    pub fn partition(&mut self, side: &[NodeId]) {
+        for ((from, to), link) in &mut self.links {
+            if side.contains(from) != side.contains(to) {
+                link.partitioned = true;
+            }
+        }
+    }
+
+    pub fn heal(&mut self) {
+        for link in self.links.values_mut() {
+            link.partitioned = false;
+        }
+    }
+
+    /// Every message sent so far, in the order it was sent.
+    pub fn log(&self) -> &[Record] {
+        &self.log
+    }
+
+    /// Every message a node rejected so far, in the order it arrived.
+    pub fn failures(&self) -> &[Failure] {
+        &self.failures
+    }
+
+    /// Processes the next event, if it is due no later than `deadline`.  Returns whether there
+    /// was one.
+    pub fn step(&mut self, deadline: Instant) -> Result<bool> {
+        let Some(entry) = self.queue.first_entry() else {
+            return Ok(false);
+        };
+        let (at, _) = *entry.key();
+        if at > deadline {
+            return Ok(false);
+        }
+        let event = entry.remove();
+        self.now = at;
+        match event {
+            Event::Deliver { from, to, message } => {
+                let sender = self.entity(from);
+                let slot = &mut self.nodes[to.0];
+                let mut ctx = Context::new(self.now, &mut slot.rng);
+                if let Err(error) = slot.node.receive(&sender, message, &mut ctx) {
+                    self.failures.push(Failure { at, from, to, error });
+                }
+                let outbox = ctx.into_outbox();
+                self.dispatch(to, outbox);
+            },
+            Event::Wake { node } => {
+                let slot = &mut self.nodes[node.0];
+                if slot.wake != Some(at) {
+                    return Ok(true);
+                }
+                slot.wake = None;
+                let mut ctx = Context::new(self.now, &mut slot.rng);
+                slot.node.tick(&mut ctx)?;
+                let outbox = ctx.into_outbox();
+                self.dispatch(node, outbox);
+            },
+        }
+        Ok(true)
+    }
+
+    /// Processes every event due no later than `deadline`, then advances the clock to it.
+    pub fn run_until(&mut self, deadline: Instant) -> Result<()> {
+        while self.step(deadline)? {}
+        self.now = self.now.max(deadline);
+        Ok(())
+    }
+
+    pub fn run_for(&mut self, duration: Duration) -> Result<()> {
+        self.run_until(self.now + duration)
+    }
+
+    /// Sends a node's messages over its links and reschedules its wake-up.  Messages for
+    /// entities outside the simulation are dropped.
+    fn dispatch(&mut self, from: NodeId, outbox: Vec<(Entity, Message)>) {
+        for (entity, message) in outbox {
+            let Some(to) = self.nodes.iter().position(|slot| slot.entity == entity) else {
+                continue;
+            };
+            let to = NodeId(to);
+            let (fate, arrives) = match self.links.get(&(from, to)) {
+                Some(link) => link.transmit(self.now, &mut self.rng),
+                None => (Fate::Unlinked, self.now),
+            };
+            let (code, size) = (message.code(), message.wire_size(WireFormat::V1));
+            self.log.push(Record { sent: self.now, arrives, from, to, code, size, fate });
+            if fate == Fate::Delivered {
+                self.schedule(arrives, Event::Deliver { from, to, message });
+            }
+        }
+        let slot = &mut self.nodes[from.0];
+        let wake = slot.node.next_deadline().map(|at| at.max(self.now));
+        if wake != slot.wake {
+            slot.wake = wake;
+            if let Some(at) = wake {
+                self.schedule(at, Event::Wake { node: from });
+            }
+        }
+    }
+
+    fn schedule(&mut self, at: Instant, event: Event) {
+        self.queue.insert((at, self.sequence), event);
+        self.sequence += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dandelion::message::DesireBlockID;
+    use dandelion::{
+        Attestation,
+        Block,
+        BlockStore,
+        Capabilities,
+        Claims,
+        EntityType,
+        Envelope,
+        Priority,
+    };
+    use dandelion_wire::rand_core::RngCore;
+    use dandelion_wire::{Encrypted, PublicBytes};
+
+    use super::*;
+
+    const NODES: usize = 8;
+
+    /// A ring with chords, so that every node has four peers.
+    fn network(seed: u64, config: LinkConfig) -> (Simulator<StandardNode>, Vec<NodeId>) {
+        let mut sim = Simulator::new(seed);
+        let ids: Vec<NodeId> = (0..NODES)
+            .map(|_| sim.add_node(|rng| StandardNode::new(rng.identity(EntityType::Node))))
+            .collect();
+        for index in 0..NODES {
+            sim.connect(ids[index], ids[(index + 1) % NODES], config).unwrap();
+            sim.connect(ids[index], ids[(index + 3) % NODES], config).unwrap();
+        }
+        (sim, ids)
+    }
+
+    /// An envelope with an opaque payload; the simulator never opens it.
+    fn envelope(sim: &mut Simulator<StandardNode>, from: NodeId, to: NodeId) -> Envelope {
+        let mut nonce = [0u8; 24];
+        sim.act(from, |_, ctx| ctx.rng().fill_bytes(&mut nonce));
+        Envelope {
+            sender: sim.entity(from),
+            recipient: sim.entity(to),
+            payload: Encrypted {
+                nonce: PublicBytes::from_exact(nonce),
+                ciphertext: Default::default(),
+                tag: Default::default(),
+            },
+        }
+    }
+
+    fn attestation(sim: &Simulator<StandardNode>, from: NodeId) -> Attestation {
+        Attestation { attestor: sim.entity(from), time: sim.now(), claims: Claims(Vec::new()) }
+    }
+
+    fn lossy() -> LinkConfig {
+        LinkConfig {
+            latency: Duration::from_milliseconds(20),
+            jitter: Duration::from_milliseconds(80),
+            loss_percent: 5,
+        }
+    }
+
+    #[test]
+    fn sim_routes_envelopes() {
+        let (mut sim, ids) = network(1, LinkConfig::default());
+        for (from, to) in [(0, 5), (3, 4), (6, 1)] {
+            let envelope = envelope(&mut sim, ids[from], ids[to]);
+            sim.act(ids[from], |node, ctx| node.send_envelope(envelope, ctx)).unwrap();
+        }
+        sim.run_for(Duration::from_minutes(2)).unwrap();
+        for to in [5, 4, 1] {
+            assert_eq!(1, sim.node(ids[to]).inbox.len());
+        }
+        assert!(sim.log().iter().any(|record| record.code == Message::CODE_STEM_ENVELOPE));
+        let greeted = sim
+            .log()
+            .iter()
+            .find(|r| r.code == Message::CODE_HELLO && r.fate == Fate::Delivered)
+            .unwrap();
+        let (from, to) = (greeted.from, greeted.to);
+        assert!(sim.node(to).agreed(&sim.entity(from)).is_some());
+    }
+
+    #[test]
+    fn sim_drops_rejected_messages() {
+        let (mut sim, ids) = network(4, LinkConfig::default());
+        let to = sim.entity(ids[1]);
+        let hello = Capabilities { min_version: u16::MAX, ..Capabilities::local() };
+        sim.act(ids[0], |_, ctx| ctx.send(to, Message::Hello(hello)));
+        sim.run_for(Duration::from_seconds(1)).unwrap();
+        let [failure] = sim.failures() else { panic!("{:?}", sim.failures()) };
+        assert_eq!((ids[0], ids[1]), (failure.from, failure.to));
+        assert_eq!(ErrorKind::Incompatible, failure.error.kind());
+
+        let envelope = envelope(&mut sim, ids[0], ids[5]);
+        sim.act(ids[0], |node, ctx| node.send_envelope(envelope, ctx)).unwrap();
+        sim.run_for(Duration::from_minutes(2)).unwrap();
+        assert_eq!(1, sim.node(ids[5]).inbox.len());
+    }
+
+    #[test]
+    fn sim_propagates_attestations_across_partitions() {
+        let (mut sim, ids) = network(2, LinkConfig::default());
+        sim.partition(&ids[..4]);
+        let first = attestation(&sim, ids[0]);
+        sim.act(ids[0], |node, ctx| node.publish(first, ctx)).unwrap();
+        sim.run_for(Duration::from_seconds(10)).unwrap();
+        let counts: Vec<usize> = ids.iter().map(|id| sim.node(*id).attestations.len()).collect();
+        assert_eq!(Vec::from([1, 1, 1, 1, 0, 0, 0, 0]), counts);
+        assert!(sim.log().iter().any(|record| record.fate == Fate::Partitioned));
+
+        sim.heal();
+        let second = attestation(&sim, ids[7]);
+        sim.act(ids[7], |node, ctx| node.publish(second, ctx)).unwrap();
+        sim.run_for(Duration::from_seconds(10)).unwrap();
+        let counts: Vec<usize> = ids.iter().map(|id| sim.node(*id).attestations.len()).collect();
+        assert_eq!(Vec::from([2, 2, 2, 2, 1, 1, 1, 1]), counts);
+    }
+
+    #[test]
+    fn sim_exchanges_blocks() {
+        let (mut sim, ids) = network(3, LinkConfig::default());
+        let block = Block::from_slice(b"simulated").unwrap();
+        let block_id = sim.act(ids[1], |node, _| node.store.put(&block)).unwrap();
+        let desire = DesireBlockID { block_id, priority: Priority::High };
+        sim.act(ids[0], |node, ctx| node.with_exchange(ctx, |exchange, _| exchange.want(desire)))
+            .unwrap();
+        sim.run_for(Duration::from_seconds(1)).unwrap();
+        assert!(sim.node(ids[0]).store.contains(&block_id).unwrap());
+        assert!(sim.node(ids[0]).exchange.wants().is_empty());
+    }
+
+    fn lossy_run(seed: u64) -> Vec<Record> {
+        let (mut sim, ids) = network(seed, lossy());
+        for (from, to) in [(0, 5), (2, 7), (4, 0)] {
+            let envelope = envelope(&mut sim, ids[from], ids[to]);
+            sim.act(ids[from], |node, ctx| node.send_envelope(envelope, ctx)).unwrap();
+            let attestation = attestation(&sim, ids[from]);
+            sim.act(ids[from], |node, ctx| node.publish(attestation, ctx)).unwrap();
+            sim.run_for(Duration::from_seconds(5)).unwrap();
+        }
+        sim.run_for(Duration::from_minutes(30)).unwrap();
+        sim.log().to_vec()
+    }
+
+    #[test]
+    fn sim_replays_seeded_runs() {
+        let log = lossy_run(42);
+        assert!(log.iter().any(|record| record.fate == Fate::Lost));
+        assert_eq!(log, lossy_run(42));
+        assert_ne!(log, lossy_run(43));
+    }
+}
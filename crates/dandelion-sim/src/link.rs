@@ -0,0 +1,57 @@
+use dandelion::{Duration, Instant};
+
+use crate::SimRng;
+
+/// The behaviour of one direction of a simulated connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkConfig {
+    /// Every message takes at least this long to arrive.
+    pub latency: Duration,
+    /// Up to this much extra delay is added to each message, uniformly at random.  Messages may
+    /// therefore arrive out of order.
+    pub jitter: Duration,
+    /// Each message is lost with this probability, in percent.
+    pub loss_percent: u32,
+}
+
+impl LinkConfig {
+    pub const PERFECT: Self =
+        Self { latency: Duration::ZERO, jitter: Duration::ZERO, loss_percent: 0 };
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self { latency: Duration::from_milliseconds(50), jitter: Duration::ZERO, loss_percent: 0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Link {
+    pub config: LinkConfig,
+    pub partitioned: bool,
+}
+
+/// What happened to a message handed to a link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Fate {
+    Delivered,
+    Lost,
+    Partitioned,
+    /// The nodes were never connected.
+    Unlinked,
+}
+
+impl Link {
+    /// Decides whether a message sent at `now` arrives, and when.
+    pub fn transmit(&self, now: Instant, rng: &mut SimRng) -> (Fate, Instant) {
+        if self.partitioned {
+            return (Fate::Partitioned, now);
+        }
+        if (rng.below(100) as u32) < self.config.loss_percent {
+            return (Fate::Lost, now);
+        }
+        let jitter = rng.below(self.config.jitter.as_nanoseconds().max(0) as u64);
+        let delay = self.config.latency + Duration::from_nanoseconds(jitter as i64);
+        (Fate::Delivered, now + delay)
+    }
+}
@@ -0,0 +1,206 @@
+use dandelion::store::MemoryBlockStore;
+use dandelion::{
+    Attestation,
+    Capabilities,
+    Entity,
+    Envelope,
+    Exchange,
+    Identity,
+    Instant,
+    Message,
+    Router,
+};
+use dandelion_wire::cryptography::digest::Digest;
+use dandelion_wire::{util, Result, Typed};
+
+use crate::SimRng;
+
+/// One virtual node.  The simulator calls these methods whenever something happens to the node
+/// and delivers whatever it sends through the [`Context`].
+pub trait Node {
+    fn entity(&self) -> Entity;
+
+    /// Called when a link to `peer` is established.
+    fn connect(&mut self, peer: Entity, ctx: &mut Context) -> Result<()>;
+
+    fn receive(&mut self, from: &Entity, message: Message, ctx: &mut Context) -> Result<()>;
+
+    /// Called at (or shortly after) the time returned by [`Node::next_deadline`].
+    fn tick(&mut self, ctx: &mut Context) -> Result<()>;
+
+    fn next_deadline(&self) -> Option<Instant>;
+}
+
+/// A node's view of the simulation during a callback.
+pub struct Context<'a> {
+    now: Instant,
+    rng: &'a mut SimRng,
+    outbox: Vec<(Entity, Message)>,
+}
+
+impl<'a> Context<'a> {
+    pub(crate) fn new(now: Instant, rng: &'a mut SimRng) -> Self {
+        Self { now, rng, outbox: Vec::new() }
+    }
+
+    pub(crate) fn into_outbox(self) -> Vec<(Entity, Message)> {
+        self.outbox
+    }
+
+    pub fn now(&self) -> Instant {
+        self.now
+    }
+
+    /// The node's own random stream.
+    pub fn rng(&mut self) -> &mut SimRng {
+        self.rng
+    }
+
+    pub fn send(&mut self, to: Entity, message: Message) {
+        self.outbox.push((to, message));
+    }
+}
+
+/// A node running the crate's [`Router`] and [`Exchange`] over a [`MemoryBlockStore`], and
+/// flooding [`Attestation`]s to every peer.  It greets each new peer with a [`Message::Hello`].
+pub struct StandardNode {
+    pub identity: Identity,
+    pub router: Router,
+    pub exchange: Exchange,
+    pub store: MemoryBlockStore,
+    /// Sent to every peer when the link starts.
+    pub capabilities: Capabilities,
+    /// Envelopes addressed to this node, in the order they arrived.
+    pub inbox: Vec<Envelope>,
+    /// Every attestation seen, in the order they arrived.
+    pub attestations: Vec<Attestation>,
+    peers: Vec<Entity>,
+    seen: Vec<Digest>,
+    agreed: Vec<(Entity, Capabilities)>,
+}
+
+impl StandardNode {
+    pub fn new(identity: Identity) -> Self {
+        Self {
+            identity,
+            router: Router::new(),
+            exchange: Exchange::new(),
+            store: MemoryBlockStore::new(),
+            capabilities: Capabilities::local(),
+            inbox: Vec::new(),
+            attestations: Vec::new(),
+            peers: Vec::new(),
+            seen: Vec::new(),
+            agreed: Vec::new(),
+        }
+    }
+
+    /// What this node and `peer` negotiated, once `peer`'s hello has arrived.
+    pub fn agreed(&self, peer: &Entity) -> Option<&Capabilities> {
+        self.agreed.iter().find(|(entity, _)| entity == peer).map(|(_, agreed)| agreed)
+    }
+
+    pub fn send_envelope(&mut self, envelope: Envelope, ctx: &mut Context) -> Result<()> {
+        let now = ctx.now();
+        self.router.originate(envelope, now, ctx.rng());
+        self.flush(ctx)
+    }
+
+    pub fn publish(&mut self, attestation: Attestation, ctx: &mut Context) -> Result<()> {
+        self.gossip(attestation, None, ctx)?;
+        self.flush(ctx)
+    }
+
+    /// Runs `f` against the exchange and store, then sends whatever the exchange queued.
+    pub fn with_exchange<R>(
+        &mut self,
+        ctx: &mut Context,
+        f: impl FnOnce(&mut Exchange, &mut MemoryBlockStore) -> R,
+    ) -> Result<R> {
+        let result = f(&mut self.exchange, &mut self.store);
+        self.flush(ctx)?;
+        Ok(result)
+    }
+
+    fn gossip(
+        &mut self,
+        attestation: Attestation,
+        except: Option<&Entity>,
+        ctx: &mut Context,
+    ) -> Result<()> {
+        let digest = Digest::compute(Attestation::TYPE_UUID, util::serialize(&attestation)?);
+        if self.seen.contains(&digest) {
+            return Ok(());
+        }
+        self.seen.push(digest);
+        self.attestations.push(attestation.clone());
+        for peer in &self.peers {
+            if Some(peer) != except {
+                ctx.send(*peer, Message::Attestation(attestation.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, ctx: &mut Context) -> Result<()> {
+        for peer in &self.peers {
+            for message in self.router.poll(peer)? {
+                ctx.send(*peer, message);
+            }
+            for message in self.exchange.poll(peer, &self.store)? {
+                ctx.send(*peer, message);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Node for StandardNode {
+    fn entity(&self) -> Entity {
+        self.identity.entity()
+    }
+
+    fn connect(&mut self, peer: Entity, ctx: &mut Context) -> Result<()> {
+        if !self.peers.contains(&peer) {
+            self.peers.push(peer);
+        }
+        ctx.send(peer, Message::Hello(self.capabilities.clone()));
+        self.router.connect(peer);
+        self.exchange.connect(peer);
+        self.flush(ctx)
+    }
+
+    fn receive(&mut self, from: &Entity, message: Message, ctx: &mut Context) -> Result<()> {
+        match message {
+            Message::Hello(theirs) => {
+                let agreed = self.capabilities.negotiate(&theirs)?;
+                self.agreed.retain(|(entity, _)| entity != from);
+                self.agreed.push((*from, agreed));
+            },
+            Message::Attestation(attestation) => self.gossip(attestation, Some(from), ctx)?,
+            Message::Envelope(_) | Message::StemEnvelope(_) => {
+                let now = ctx.now();
+                let envelope = self.router.receive(from, &message, now, ctx.rng())?;
+                if let Some(envelope) = envelope {
+                    if envelope.recipient == self.entity() {
+                        self.inbox.push(envelope);
+                    }
+                }
+            },
+            _ => {
+                self.exchange.receive(from, &message, &mut self.store)?;
+            },
+        }
+        self.flush(ctx)
+    }
+
+    fn tick(&mut self, ctx: &mut Context) -> Result<()> {
+        let now = ctx.now();
+        self.router.tick(now, ctx.rng());
+        self.flush(ctx)
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.router.next_deadline()
+    }
+}
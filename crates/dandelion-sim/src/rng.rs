@@ -0,0 +1,55 @@
+use dandelion::{EntityType, Identity, SplitMix64};
+use dandelion_wire::cryptography::sig;
+use dandelion_wire::rand_core::{Error, RngCore};
+use dandelion_wire::SecretBytes;
+
+/// A [`SplitMix64`] stream with the helpers a simulation needs.  It is not cryptographically
+/// secure, so it deliberately does not implement `CryptoRng`.
+#[derive(Clone, Debug)]
+pub struct SimRng {
+    inner: SplitMix64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self { inner: SplitMix64::new(seed) }
+    }
+
+    /// A new generator whose stream is independent of this one's.
+    pub fn fork(&mut self) -> Self {
+        Self::new(self.next_u64())
+    }
+
+    /// Returns a value in `0..bound`, or 0 if `bound` is 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        match bound {
+            0 => 0,
+            _ => dandelion::rng::below(self, bound),
+        }
+    }
+
+    /// An identity whose keys come from this generator, for reproducible test networks.
+    pub fn identity(&mut self, entity_type: EntityType) -> Identity {
+        let mut raw = [0u8; 32];
+        self.fill_bytes(&mut raw);
+        Identity::new(entity_type, sig::PrivateKey::from_exposed(raw))
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.inner.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.inner.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.inner.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.inner.try_fill_bytes(dest)
+    }
+}
@@ -1,8 +1,10 @@
 use cryptoxide::chacha20poly1305::{self, DecryptionResult};
+use rand_core::CryptoRngCore;
+use zeroize::Zeroize;
 
 use super::SharedSecret;
 use crate::bytes::{Buf, BufMut, BytesMut};
-use crate::{dandelion_wire, Error, PublicBytes, Result, SecretBytes};
+use crate::{dandelion_wire, ErrorKind, PublicBytes, Result, SecretBytes};
 
 const ROUNDS: usize = 20;
 
@@ -20,7 +22,11 @@ impl Key {
     }
 
     pub fn as_context(&self, nonce: Nonce) -> Context {
-        Context::new(self.expose(), nonce.as_slice())
+        // XChaCha20: derive a subkey from the first 16 bytes of the nonce, then run ChaCha20 with
+        // the remaining 8 bytes.
+        let (prefix, suffix) = nonce.as_exact().split_at(16);
+        let subkey = Key::from_exposed(hchacha20(self.expose(), prefix.try_into().unwrap()));
+        Context::new(subkey.expose(), suffix)
     }
 
     pub fn encrypt_in_place(
@@ -49,7 +55,7 @@ impl Key {
         context.decrypt_mut(buffer);
         match context.finalize(&tag.as_cryptoxide()) {
             DecryptionResult::Match => Ok(()),
-            DecryptionResult::MisMatch => Err(Error),
+            DecryptionResult::MisMatch => Err(ErrorKind::DecryptFailed.into()),
         }
     }
 
@@ -81,11 +87,19 @@ impl Key {
         transform_chunks(ciphertext, plaintext, |a, b| context.decrypt(a, b));
         match context.finalize(&tag.as_cryptoxide()) {
             DecryptionResult::Match => Ok(()),
-            DecryptionResult::MisMatch => Err(Error),
+            DecryptionResult::MisMatch => Err(ErrorKind::DecryptFailed.into()),
         }
     }
 }
 
+impl Nonce {
+    pub fn generate(rng: &mut dyn CryptoRngCore) -> Self {
+        let mut nonce = Self::zero();
+        rng.fill_bytes(nonce.as_slice_mut());
+        nonce
+    }
+}
+
 impl Tag {
     pub fn from_cryptoxide(tag: chacha20poly1305::Tag) -> Self {
         Self::from_exact(tag.0)
@@ -104,6 +118,46 @@ impl From<SharedSecret> for Key {
     }
 }
 
+fn hchacha20(key: &RawKey, nonce: &[u8; 16]) -> RawKey {
+    fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        state[a] = state[a].wrapping_add(state[b]);
+        state[d] = (state[d] ^ state[a]).rotate_left(16);
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_left(12);
+        state[a] = state[a].wrapping_add(state[b]);
+        state[d] = (state[d] ^ state[a]).rotate_left(8);
+        state[c] = state[c].wrapping_add(state[d]);
+        state[b] = (state[b] ^ state[c]).rotate_left(7);
+    }
+
+    let mut state = [0u32; 16];
+    state[0..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for (word, chunk) in state[4..12].iter_mut().zip(key.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    for (word, chunk) in state[12..16].iter_mut().zip(nonce.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    for _ in 0..(ROUNDS / 2) {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut subkey = [0u8; KEY_SIZE];
+    let words = state[0..4].iter().chain(state[12..16].iter());
+    for (chunk, word) in subkey.chunks_exact_mut(4).zip(words) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    state.zeroize();
+    subkey
+}
+
 fn consume_chunks(input: &mut dyn Buf, mut callback: impl FnMut(&[u8])) {
     while input.has_remaining() {
         let chunk = input.chunk();
@@ -125,3 +179,43 @@ fn transform_chunks(
         input.advance(in_chunk.len());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from draft-irtf-cfrg-xchacha-03.
+    fn hex<const N: usize>(text: &str) -> [u8; N] {
+        let mut out = [0u8; N];
+        for (index, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn hchacha20_vector() {
+        let key = hex::<32>("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let nonce = hex::<16>("000000090000004a0000000031415927");
+        let expect = hex::<32>("82413b4227b27bfed30e42508a877d73a0f9e4d58a74a853c12ec41326d3ecdc");
+        assert_eq!(expect, hchacha20(&key, &nonce));
+    }
+
+    #[test]
+    fn xchacha20_poly1305_vector() {
+        let key = Key::from_exposed(hex::<32>(
+            "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f",
+        ));
+        let nonce =
+            Nonce::from_exact(hex::<24>("404142434445464748494a4b4c4d4e4f5051525354555657"));
+        let associated = hex::<12>("50515253c0c1c2c3c4c5c6c7");
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let mut buffer = *plaintext;
+        let tag = key.encrypt_in_place(nonce, Some(&associated), &mut buffer);
+        assert_eq!(hex::<16>("bd6d179d3e83d43b9576579493c0e939"), buffer[0..16]);
+        assert_eq!(Tag::from_exact(hex::<16>("c0875924c1c7987947deafd8780acf49")), tag);
+
+        key.decrypt_in_place(nonce, Some(&associated), &mut buffer, tag).unwrap();
+        assert_eq!(plaintext, &buffer);
+    }
+}
@@ -1,12 +1,24 @@
+use cryptoxide::curve25519::{Fe, Ge};
+use cryptoxide::hashing::sha2::Sha512;
 use cryptoxide::x25519;
+use rand_core::CryptoRngCore;
+use zeroize::Zeroize;
 
-use super::SharedSecret;
-use crate::{dandelion_wire, PublicBytes, Result, SecretBytes};
+use super::{sig, SharedSecret};
+use crate::{dandelion_wire, Error, ErrorKind, PublicBytes, Result, SecretBytes};
 
 secret_bytes!(PrivateKey, raw RawPrivateKey, size PRIVATE_KEY_SIZE = 32);
 public_bytes!(PublicKey, raw RawPublicKey, size PUBLIC_KEY_SIZE = 32);
 
 impl PrivateKey {
+    pub fn generate(rng: &mut dyn CryptoRngCore) -> Self {
+        let mut raw = RawPrivateKey::default();
+        rng.fill_bytes(&mut raw);
+        let result = Self::from_exposed(raw);
+        raw.zeroize();
+        result
+    }
+
     pub fn from_shared_secret(secret: SharedSecret) -> Self {
         Self::from_box(secret.into_box())
     }
@@ -15,6 +27,14 @@ impl PrivateKey {
         Self::from_exposed_slice(cryptoxide.as_ref())
     }
 
+    /// Derives the X25519 private key that shares its scalar with an Ed25519 private key.
+    pub fn from_signing_key(key: &sig::PrivateKey) -> Self {
+        let mut hash = Sha512::new().update(key.expose()).finalize();
+        let result = Self::from_exposed_slice(&hash[0..PRIVATE_KEY_SIZE]);
+        hash.zeroize();
+        result
+    }
+
     pub fn as_cryptoxide(&self) -> x25519::SecretKey {
         x25519::SecretKey::from(*self.expose())
     }
@@ -36,6 +56,21 @@ impl PrivateKey {
 }
 
 impl PublicKey {
+    /// Maps an Ed25519 public key onto Curve25519 using the birational equivalence
+    /// u = (1 + y) / (1 - y).  Fails if the key is not a valid Edwards point.
+    pub fn from_signing_key(key: sig::PublicKey) -> Result<Self> {
+        if Ge::from_bytes(key.as_exact()).is_none() {
+            return Err(ErrorKind::BadKey.into());
+        }
+        let y = Fe::from_bytes(key.as_exact());
+        let denominator = &Fe::ONE - &y;
+        if !denominator.is_nonzero() {
+            return Err(ErrorKind::BadKey.into());
+        }
+        let u = &(&Fe::ONE + &y) * &denominator.invert();
+        Ok(Self::from_exact(u.to_bytes()))
+    }
+
     pub fn from_cryptoxide(cryptoxide: x25519::PublicKey) -> Self {
         Self::from_slice(cryptoxide.as_ref())
     }
@@ -49,6 +84,19 @@ impl PublicKey {
     }
 }
 
+impl From<&sig::PrivateKey> for PrivateKey {
+    fn from(value: &sig::PrivateKey) -> Self {
+        Self::from_signing_key(value)
+    }
+}
+
+impl TryFrom<sig::PublicKey> for PublicKey {
+    type Error = Error;
+    fn try_from(value: sig::PublicKey) -> Result<Self> {
+        Self::from_signing_key(value)
+    }
+}
+
 impl From<SharedSecret> for PrivateKey {
     fn from(value: SharedSecret) -> Self {
         Self::from_shared_secret(value)
@@ -84,3 +132,31 @@ impl From<&PublicKey> for x25519::PublicKey {
         value.as_cryptoxide()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signing_key_conversion_agrees() {
+        let alice = sig::PrivateKey::from_exposed([1; 32]);
+        let bob = sig::PrivateKey::from_exposed([2; 32]);
+        let alice_public = PublicKey::from_signing_key(alice.public_key()).unwrap();
+        let bob_public = PublicKey::from_signing_key(bob.public_key()).unwrap();
+        let alice_private = PrivateKey::from_signing_key(&alice);
+        let bob_private = PrivateKey::from_signing_key(&bob);
+        assert_eq!(alice_public, alice_private.public_key());
+        assert_eq!(bob_public, bob_private.public_key());
+
+        let ab = alice_private.diffie_hellman(bob_public).unwrap();
+        let ba = bob_private.diffie_hellman(alice_public).unwrap();
+        assert_eq!(ab.expose(), ba.expose());
+    }
+
+    #[test]
+    fn signing_key_conversion_rejects_identity() {
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        assert!(PublicKey::from_signing_key(sig::PublicKey::from_exact(identity)).is_err());
+    }
+}
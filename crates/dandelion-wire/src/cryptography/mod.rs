@@ -4,6 +4,8 @@ pub mod cipher;
 pub mod digest;
 pub mod ecdh;
 pub mod hkdf;
+pub mod noise;
+pub mod ratchet;
 pub mod sig;
 
 secret_bytes!(SharedSecret, raw RawSharedSecret, size SHARED_SECRET_SIZE = 32);
@@ -0,0 +1,601 @@
+//! The Noise protocol framework (revision 34), instantiated as `Noise_XX_25519_ChaChaPoly_BLAKE2s`
+//! and `Noise_IK_25519_ChaChaPoly_BLAKE2s`.
+
+use alloc::vec::Vec;
+
+use cryptoxide::blake2s::Blake2s;
+use cryptoxide::chacha20poly1305::DecryptionResult;
+use cryptoxide::digest::Digest as _;
+use rand_core::CryptoRngCore;
+
+use super::cipher::{self, Key, Tag, TAG_SIZE};
+use super::digest::{Digest, DIGEST_SIZE};
+use super::ecdh::{PrivateKey, PublicKey, PUBLIC_KEY_SIZE};
+use super::hkdf::Seed;
+use crate::bytes::Bytes;
+use crate::{ErrorKind, PublicBytes, Result, SecretBytes};
+
+/// The largest handshake or transport message permitted by the Noise specification.
+pub const MAX_MESSAGE_LEN: usize = 65535;
+
+/// The largest payload that fits in a single transport message.
+pub const MAX_PAYLOAD_LEN: usize = MAX_MESSAGE_LEN - TAG_SIZE;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Pattern {
+    /// Mutual authentication where neither party knows the other's static key in advance.
+    XX,
+    /// Mutual authentication in one round trip, where the initiator knows the responder's static
+    /// key in advance.
+    IK,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+    E,
+    S,
+    EE,
+    ES,
+    SE,
+    SS,
+}
+
+impl Pattern {
+    pub fn protocol_name(self) -> &'static [u8] {
+        match self {
+            Self::XX => b"Noise_XX_25519_ChaChaPoly_BLAKE2s",
+            Self::IK => b"Noise_IK_25519_ChaChaPoly_BLAKE2s",
+        }
+    }
+
+    fn messages(self) -> &'static [&'static [Token]] {
+        use Token::*;
+        match self {
+            Self::XX => &[&[E], &[E, EE, S, ES], &[S, SE]],
+            Self::IK => &[&[E, ES, S, SS], &[E, EE, SE]],
+        }
+    }
+}
+
+struct CipherState {
+    key: Option<Key>,
+    nonce: u64,
+}
+
+impl CipherState {
+    fn new(key: Option<Key>) -> Self {
+        Self { key, nonce: 0 }
+    }
+
+    fn context(key: &Key, nonce: u64) -> cipher::Context {
+        let mut raw = [0u8; 12];
+        raw[4..].copy_from_slice(&nonce.to_le_bytes());
+        cipher::Context::new(key.expose(), &raw)
+    }
+
+    fn next_nonce(&mut self) -> Result<u64> {
+        // 2^64 - 1 is reserved by the specification.
+        if self.nonce == u64::MAX {
+            return Err(ErrorKind::OutOfRange.into());
+        }
+        let nonce = self.nonce;
+        self.nonce = nonce.strict_add(1);
+        Ok(nonce)
+    }
+
+    fn encrypt_with_ad(&mut self, ad: &[u8], plaintext: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        if self.key.is_none() {
+            output.extend_from_slice(plaintext);
+            return Ok(());
+        }
+        let nonce = self.next_nonce()?;
+        let mut context = Self::context(self.key.as_ref().unwrap(), nonce);
+        context.add_data(ad);
+        let mut context = context.to_encryption();
+        let start = output.len();
+        output.extend_from_slice(plaintext);
+        context.encrypt_mut(&mut output[start..]);
+        output.extend_from_slice(Tag::from_cryptoxide(context.finalize()).as_slice());
+        Ok(())
+    }
+
+    fn decrypt_with_ad(&mut self, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let Some(key) = &self.key else {
+            return Ok(Vec::from(ciphertext));
+        };
+        let Some(split) = ciphertext.len().checked_sub(TAG_SIZE) else {
+            return Err(
+                ErrorKind::Truncated { needed: TAG_SIZE, remaining: ciphertext.len() }.into()
+            );
+        };
+        let (ciphertext, tag) = ciphertext.split_at(split);
+        let mut context = Self::context(key, self.nonce);
+        context.add_data(ad);
+        let mut context = context.to_decryption();
+        let mut plaintext = Vec::from(ciphertext);
+        context.decrypt_mut(&mut plaintext);
+        match context.finalize(&Tag::from_slice(tag).into_cryptoxide()) {
+            DecryptionResult::Match => {
+                self.next_nonce()?;
+                Ok(plaintext)
+            },
+            DecryptionResult::MisMatch => Err(ErrorKind::DecryptFailed.into()),
+        }
+    }
+}
+
+struct SymmetricState {
+    chaining_key: Seed,
+    hash: Digest,
+    cipher: CipherState,
+}
+
+impl SymmetricState {
+    fn new(protocol_name: &[u8]) -> Self {
+        let mut hash = Digest::zero();
+        if protocol_name.len() <= DIGEST_SIZE {
+            hash.as_slice_mut()[..protocol_name.len()].copy_from_slice(protocol_name);
+        } else {
+            hash = hash_all(&[protocol_name]);
+        }
+        let chaining_key = Seed::from_exposed(hash.into_exact());
+        Self { chaining_key, hash, cipher: CipherState::new(None) }
+    }
+
+    /// Noise's HKDF is RFC 5869 HKDF with the chaining key as salt and an empty info string.
+    fn derive(&self, input: &[u8]) -> (Seed, Key) {
+        let temp = Seed::from_key_material(self.chaining_key.expose(), input);
+        let mut output = [0u8; 64];
+        temp.generate_into(&[], &mut output);
+        let (first, second) = output.split_at(32);
+        let result = (Seed::from_exposed_slice(first), Key::from_exposed_slice(second));
+        zeroize::Zeroize::zeroize(&mut output);
+        result
+    }
+
+    fn mix_key(&mut self, input: &[u8]) {
+        let (chaining_key, key) = self.derive(input);
+        self.chaining_key = chaining_key;
+        self.cipher = CipherState::new(Some(key));
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        self.hash = hash_all(&[self.hash.as_slice(), data]);
+    }
+
+    fn encrypt_and_hash(&mut self, plaintext: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let start = output.len();
+        self.cipher.encrypt_with_ad(self.hash.as_slice(), plaintext, output)?;
+        self.hash = hash_all(&[self.hash.as_slice(), &output[start..]]);
+        Ok(())
+    }
+
+    fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let plaintext = self.cipher.decrypt_with_ad(self.hash.as_slice(), ciphertext)?;
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+
+    fn split(&self) -> (CipherState, CipherState) {
+        let (first, second) = self.derive(&[]);
+        let first = Key::from_exposed(*first.expose());
+        (CipherState::new(Some(first)), CipherState::new(Some(second)))
+    }
+
+    fn encrypted_len(&self, len: usize) -> usize {
+        match self.cipher.key {
+            Some(_) => len.strict_add(TAG_SIZE),
+            None => len,
+        }
+    }
+}
+
+fn hash_all(parts: &[&[u8]]) -> Digest {
+    let mut context = Blake2s::new(DIGEST_SIZE);
+    for part in parts {
+        context.input(part);
+    }
+    let mut hash = Digest::zero();
+    context.result(hash.as_slice_mut());
+    hash
+}
+
+/// An in-progress Noise handshake.  Each side alternates [`Handshake::write_message`] and
+/// [`Handshake::read_message`] until [`Handshake::is_finished`], then calls
+/// [`Handshake::into_transport`].
+pub struct Handshake {
+    pattern: Pattern,
+    initiator: bool,
+    symmetric: SymmetricState,
+    static_key: PrivateKey,
+    ephemeral_key: PrivateKey,
+    remote_static_key: Option<PublicKey>,
+    remote_ephemeral_key: Option<PublicKey>,
+    index: usize,
+    failed: bool,
+}
+
+impl Handshake {
+    pub fn initiate_xx(
+        static_key: PrivateKey,
+        prologue: &[u8],
+        rng: &mut dyn CryptoRngCore,
+    ) -> Self {
+        let ephemeral_key = PrivateKey::generate(rng);
+        Self::new(Pattern::XX, true, static_key, None, None, prologue, ephemeral_key)
+    }
+
+    pub fn respond_xx(
+        static_key: PrivateKey,
+        prologue: &[u8],
+        rng: &mut dyn CryptoRngCore,
+    ) -> Self {
+        let ephemeral_key = PrivateKey::generate(rng);
+        Self::new(Pattern::XX, false, static_key, None, None, prologue, ephemeral_key)
+    }
+
+    pub fn initiate_ik(
+        static_key: PrivateKey,
+        responder: PublicKey,
+        prologue: &[u8],
+        rng: &mut dyn CryptoRngCore,
+    ) -> Self {
+        let ephemeral_key = PrivateKey::generate(rng);
+        let remote = Some(responder);
+        Self::new(Pattern::IK, true, static_key, remote, remote, prologue, ephemeral_key)
+    }
+
+    pub fn respond_ik(
+        static_key: PrivateKey,
+        prologue: &[u8],
+        rng: &mut dyn CryptoRngCore,
+    ) -> Self {
+        let ephemeral_key = PrivateKey::generate(rng);
+        let known = Some(static_key.public_key());
+        Self::new(Pattern::IK, false, static_key, None, known, prologue, ephemeral_key)
+    }
+
+    /// `pre_message` is the responder's static key for patterns, such as IK, that start with it
+    /// already known.
+    fn new(
+        pattern: Pattern,
+        initiator: bool,
+        static_key: PrivateKey,
+        remote_static_key: Option<PublicKey>,
+        pre_message: Option<PublicKey>,
+        prologue: &[u8],
+        ephemeral_key: PrivateKey,
+    ) -> Self {
+        let mut symmetric = SymmetricState::new(pattern.protocol_name());
+        symmetric.mix_hash(prologue);
+        // IK's pre-message pattern: <- s
+        if let Some(responder) = pre_message {
+            symmetric.mix_hash(responder.as_slice());
+        }
+        Self {
+            pattern,
+            initiator,
+            symmetric,
+            static_key,
+            ephemeral_key,
+            remote_static_key,
+            remote_ephemeral_key: None,
+            index: 0,
+            failed: false,
+        }
+    }
+
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    pub fn is_initiator(&self) -> bool {
+        self.initiator
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.index == self.pattern.messages().len()
+    }
+
+    /// Whether the next step is [`Handshake::write_message`] rather than
+    /// [`Handshake::read_message`].
+    pub fn is_my_turn(&self) -> bool {
+        !self.failed && !self.is_finished() && (self.index % 2 == 0) == self.initiator
+    }
+
+    /// Whether a failed read or write has left the handshake unusable.
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// The peer's static key, once it has been received (or, for an IK initiator, from the start).
+    pub fn remote_static_key(&self) -> Option<PublicKey> {
+        self.remote_static_key
+    }
+
+    pub fn handshake_hash(&self) -> Digest {
+        self.symmetric.hash
+    }
+
+    /// Writes this side's next handshake message carrying `payload`.  A failed write leaves the
+    /// handshake unusable.
+    pub fn write_message(&mut self, payload: &[u8]) -> Result<Bytes> {
+        if !self.is_my_turn() {
+            return Err(ErrorKind::InvalidState.into());
+        }
+        let result = self.write_message_inner(payload);
+        self.failed = result.is_err();
+        result
+    }
+
+    fn write_message_inner(&mut self, payload: &[u8]) -> Result<Bytes> {
+        let mut output = Vec::new();
+        for &token in self.pattern.messages()[self.index] {
+            match token {
+                Token::E => {
+                    let public = self.ephemeral_key.public_key();
+                    output.extend_from_slice(public.as_slice());
+                    self.symmetric.mix_hash(public.as_slice());
+                },
+                Token::S => {
+                    let public = self.static_key.public_key();
+                    self.symmetric.encrypt_and_hash(public.as_slice(), &mut output)?;
+                },
+                _ => self.mix_dh(token)?,
+            }
+        }
+        self.symmetric.encrypt_and_hash(payload, &mut output)?;
+        if output.len() > MAX_MESSAGE_LEN {
+            return Err(ErrorKind::TooLarge { len: output.len(), max: MAX_MESSAGE_LEN }.into());
+        }
+        self.index = self.index.strict_add(1);
+        Ok(Bytes::from(output))
+    }
+
+    /// Processes the peer's next handshake message and returns its payload.  A failed read leaves
+    /// the handshake unusable.
+    pub fn read_message(&mut self, message: &[u8]) -> Result<Bytes> {
+        if self.failed || self.is_my_turn() || self.is_finished() {
+            return Err(ErrorKind::InvalidState.into());
+        }
+        let result = self.read_message_inner(message);
+        self.failed = result.is_err();
+        result
+    }
+
+    fn read_message_inner(&mut self, mut message: &[u8]) -> Result<Bytes> {
+        if message.len() > MAX_MESSAGE_LEN {
+            return Err(ErrorKind::TooLarge { len: message.len(), max: MAX_MESSAGE_LEN }.into());
+        }
+        for &token in self.pattern.messages()[self.index] {
+            match token {
+                Token::E => {
+                    let public = take(&mut message, PUBLIC_KEY_SIZE)?;
+                    self.symmetric.mix_hash(public);
+                    self.remote_ephemeral_key = Some(PublicKey::from_slice(public));
+                },
+                Token::S => {
+                    let len = self.symmetric.encrypted_len(PUBLIC_KEY_SIZE);
+                    let public = self.symmetric.decrypt_and_hash(take(&mut message, len)?)?;
+                    self.remote_static_key = Some(PublicKey::from_slice(&public));
+                },
+                _ => self.mix_dh(token)?,
+            }
+        }
+        let payload = self.symmetric.decrypt_and_hash(message)?;
+        self.index = self.index.strict_add(1);
+        Ok(Bytes::from(payload))
+    }
+
+    fn mix_dh(&mut self, token: Token) -> Result<()> {
+        // Tokens name the initiator's key first; the responder swaps local and remote.
+        let (local_ephemeral, remote_ephemeral) = match (token, self.initiator) {
+            (Token::EE, _) => (true, true),
+            (Token::SS, _) => (false, false),
+            (Token::ES, true) | (Token::SE, false) => (true, false),
+            (Token::SE, true) | (Token::ES, false) => (false, true),
+            (Token::E | Token::S, _) => unreachable!(),
+        };
+        let local = match local_ephemeral {
+            true => &self.ephemeral_key,
+            false => &self.static_key,
+        };
+        let remote = match remote_ephemeral {
+            true => self.remote_ephemeral_key,
+            false => self.remote_static_key,
+        };
+        let shared = local.diffie_hellman(remote.ok_or(ErrorKind::InvalidState)?)?;
+        self.symmetric.mix_key(shared.expose());
+        Ok(())
+    }
+
+    pub fn into_transport(self) -> Result<Transport> {
+        if self.failed || !self.is_finished() {
+            return Err(ErrorKind::InvalidState.into());
+        }
+        let (first, second) = self.symmetric.split();
+        let (sending, receiving) = match self.initiator {
+            true => (first, second),
+            false => (second, first),
+        };
+        Ok(Transport {
+            sending,
+            receiving,
+            handshake_hash: self.symmetric.hash,
+            remote_static_key: self.remote_static_key.ok_or(ErrorKind::InvalidState)?,
+        })
+    }
+}
+
+fn take<'a>(message: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if message.len() < len {
+        return Err(ErrorKind::Truncated { needed: len, remaining: message.len() }.into());
+    }
+    let (head, tail) = message.split_at(len);
+    *message = tail;
+    Ok(head)
+}
+
+/// The pair of cipher states produced by a completed [`Handshake`].  Messages must be decrypted in
+/// the order they were encrypted.
+pub struct Transport {
+    sending: CipherState,
+    receiving: CipherState,
+    handshake_hash: Digest,
+    remote_static_key: PublicKey,
+}
+
+impl Transport {
+    /// A value unique to this session, suitable for channel binding.
+    pub fn handshake_hash(&self) -> Digest {
+        self.handshake_hash
+    }
+
+    pub fn remote_static_key(&self) -> PublicKey {
+        self.remote_static_key
+    }
+
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<Bytes> {
+        if plaintext.len() > MAX_PAYLOAD_LEN {
+            return Err(ErrorKind::TooLarge { len: plaintext.len(), max: MAX_PAYLOAD_LEN }.into());
+        }
+        let mut output = Vec::with_capacity(plaintext.len().strict_add(TAG_SIZE));
+        self.sending.encrypt_with_ad(&[], plaintext, &mut output)?;
+        Ok(Bytes::from(output))
+    }
+
+    pub fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Bytes> {
+        if ciphertext.len() > MAX_MESSAGE_LEN {
+            return Err(ErrorKind::TooLarge { len: ciphertext.len(), max: MAX_MESSAGE_LEN }.into());
+        }
+        Ok(Bytes::from(self.receiving.decrypt_with_ad(&[], ciphertext)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn key(fill: u8) -> PrivateKey {
+        PrivateKey::from_exposed([fill; 32])
+    }
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn run(mut initiator: Handshake, mut responder: Handshake, expected: &[&str]) {
+        let payloads: [&[u8]; 3] = [b"one", b"two", b"three"];
+        let mut turn = 0;
+        while !initiator.is_finished() {
+            let (writer, reader) = match turn % 2 {
+                0 => (&mut initiator, &mut responder),
+                _ => (&mut responder, &mut initiator),
+            };
+            let message = writer.write_message(payloads[turn]).unwrap();
+            assert_eq!(unhex(expected[turn]), message.as_ref());
+            assert_eq!(payloads[turn], reader.read_message(&message).unwrap().as_ref());
+            turn += 1;
+        }
+        assert!(responder.is_finished());
+        assert_eq!(initiator.handshake_hash(), responder.handshake_hash());
+        assert_eq!(unhex(expected[turn]), initiator.handshake_hash().as_slice());
+        assert_eq!(Some(key(2).public_key()), initiator.remote_static_key());
+        assert_eq!(Some(key(1).public_key()), responder.remote_static_key());
+
+        let mut initiator = initiator.into_transport().unwrap();
+        let mut responder = responder.into_transport().unwrap();
+        let message = initiator.encrypt(b"transport").unwrap();
+        assert_eq!(unhex(expected[turn + 1]), message.as_ref());
+        assert_eq!(b"transport", responder.decrypt(&message).unwrap().as_ref());
+        let message = responder.encrypt(b"reply").unwrap();
+        assert_eq!(unhex(expected[turn + 2]), message.as_ref());
+        assert_eq!(b"reply", initiator.decrypt(&message).unwrap().as_ref());
+    }
+
+    #[test]
+    fn noise_xx_vector() {
+        let prologue = b"dandelion";
+        run(
+            Handshake::new(Pattern::XX, true, key(1), None, None, prologue, key(3)),
+            Handshake::new(Pattern::XX, false, key(2), None, None, prologue, key(4)),
+            &[
+                "5dfedd3b6bd47f6fa28ee15d969d5bb0ea53774d488bdaf9df1c6e0124b3ef226f6e65",
+                "ac01b2209e86354fb853237b5de0f4fab13c7fcbf433a61c019369617fecf10ba008df889bb35e822333\
+                 5723afaf9154defd8da5df240cc3592090c2fb4518e0ba95947a6319d3edd02c864eaa24eaa514426f97\
+                 b11449b12eea47a60d0dec336975d8",
+                "b7cdf8c991735a27cbc64fb59293bea96330d41179052f565b352ffad9b79b872f71e40851220d8ec970\
+                 c81ca01b5d3335701f4e737c5c3276934a7c4f00d6f87cd6f1d2f5",
+                "185e2b73926641170d23b5c031f375d7f13541ae5598e7eaa308bb1b1a316641",
+                "141cec459ffdbc3e4f2633dd93596b600ac06d2672679a0227",
+                "3fbc22ced2ab874d5c1a0cb517402780da01f5a22f",
+            ],
+        );
+    }
+
+    #[test]
+    fn noise_ik_vector() {
+        let prologue = b"dandelion";
+        let responder = key(2).public_key();
+        run(
+            Handshake::new(Pattern::IK, true, key(1), Some(responder), Some(responder), prologue, key(3)),
+            Handshake::new(Pattern::IK, false, key(2), None, Some(responder), prologue, key(4)),
+            &[
+                "5dfedd3b6bd47f6fa28ee15d969d5bb0ea53774d488bdaf9df1c6e0124b3ef224391bcfef3f1b0f05187\
+                 3c2103356110f8056ef928c4354783347c74dc7b71b7b974478a26feaab2ffd53351026ff537806c9f7a\
+                 3296b3180efdb1790fe85a8ba7a07b",
+                "ac01b2209e86354fb853237b5de0f4fab13c7fcbf433a61c019369617fecf10bdd9af1f791cf66533529\
+                 9acbc73b23077a5161",
+                "18ad222654c7de7c92c23b64e8cf5f02a9d14ede712e177d1455fc1838dab421",
+                "628d0a12cae9ba282d599dccf4de6a5259ada7d01f07ceeeaf",
+                "08d87b37529df25dcc2b3dfb99c5b1527cea5b3da5",
+            ],
+        );
+    }
+
+    #[test]
+    fn noise_rejects_wrong_responder_key() {
+        let mut initiator = Handshake::initiate_ik(key(1), key(9).public_key(), &[], &mut OsRng);
+        let mut responder = Handshake::respond_ik(key(2), &[], &mut OsRng);
+        let message = initiator.write_message(&[]).unwrap();
+        assert!(responder.read_message(&message).is_err());
+        assert!(responder.is_failed());
+
+        // Even the right message is refused once a read has failed.
+        let mut initiator = Handshake::initiate_ik(key(1), key(2).public_key(), &[], &mut OsRng);
+        let message = initiator.write_message(&[]).unwrap();
+        assert!(responder.read_message(&message).is_err());
+        assert!(!responder.is_my_turn());
+    }
+
+    #[test]
+    fn noise_enforces_order() {
+        let mut initiator = Handshake::initiate_xx(key(1), &[], &mut OsRng);
+        let mut responder = Handshake::respond_xx(key(2), &[], &mut OsRng);
+        assert!(responder.write_message(&[]).is_err());
+        assert!(initiator.read_message(&[]).is_err());
+        let message = initiator.write_message(&[]).unwrap();
+        responder.read_message(&message).unwrap();
+        assert!(responder.into_transport().is_err());
+
+        let mut initiator = Handshake::initiate_xx(key(1), &[], &mut OsRng);
+        let mut responder = Handshake::respond_xx(key(2), &[], &mut OsRng);
+        while !initiator.is_finished() {
+            let (writer, reader) = match initiator.is_my_turn() {
+                true => (&mut initiator, &mut responder),
+                false => (&mut responder, &mut initiator),
+            };
+            reader.read_message(&writer.write_message(&[]).unwrap()).unwrap();
+        }
+        let mut initiator = initiator.into_transport().unwrap();
+        let mut responder = responder.into_transport().unwrap();
+        let first = initiator.encrypt(b"first").unwrap();
+        let second = initiator.encrypt(b"second").unwrap();
+        assert!(responder.decrypt(&second).is_err());
+        assert_eq!(b"first", responder.decrypt(&first).unwrap().as_ref());
+        assert_eq!(b"second", responder.decrypt(&second).unwrap().as_ref());
+        assert!(responder.decrypt(&second).is_err());
+    }
+}
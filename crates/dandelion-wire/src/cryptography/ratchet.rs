@@ -0,0 +1,448 @@
+use alloc::fmt;
+use alloc::vec::Vec;
+
+use rand_core::CryptoRngCore;
+
+use super::cipher::{Key, Nonce};
+use super::ecdh::{PrivateKey, PublicKey};
+use super::hkdf::Seed;
+use super::SharedSecret;
+use crate::bytes::{Buf, BufMut};
+use crate::{
+    dandelion_wire,
+    BaseSerializable,
+    DecodeContext,
+    Encryptable,
+    Encrypted,
+    ErrorKind,
+    Printable,
+    PublicBytes,
+    Result,
+    SecretBytes,
+    Serializable,
+    WireFormat,
+};
+
+/// Maximum number of messages that may be skipped within a single receiving chain.
+pub const MAX_SKIP: u32 = 1000;
+
+/// Maximum number of skipped message keys retained across all chains.
+pub const MAX_SKIPPED_KEYS: usize = 2000;
+
+const INFO_ROOT: &[u8] = b"dandelion ratchet root";
+const INFO_CHAIN: &[u8] = b"dandelion ratchet chain";
+const INFO_MESSAGE: &[u8] = b"dandelion ratchet message";
+const INFO_KEY: &[u8] = b"dandelion ratchet key";
+const INFO_NONCE: &[u8] = b"dandelion ratchet nonce";
+
+/// One side of a Double Ratchet session.
+///
+/// Every message is encrypted with a fresh key derived from a symmetric chain, and the chains are
+/// re-seeded with a new X25519 exchange each time the conversation changes direction, so
+/// compromising the current state does not expose earlier messages.
+#[derive(Clone)]
+pub struct Session {
+    chains: Chains,
+    skipped: Vec<SkippedKey>,
+}
+
+/// The part of a [`Session`] that every received message may advance.
+#[derive(Clone, Serializable)]
+struct Chains {
+    root_key: Seed,
+    sending_key: PrivateKey,
+    receiving_key: Option<PublicKey>,
+    sending_chain: Option<Seed>,
+    receiving_chain: Option<Seed>,
+    sent: u32,
+    received: u32,
+    previous: u32,
+}
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable)]
+#[wire(fixed)]
+pub struct Header {
+    pub ratchet_key: PublicKey,
+    pub previous: u32,
+    pub counter: u32,
+}
+
+#[derive(Clone, Serializable)]
+pub struct RatchetMessage {
+    pub header: Header,
+    pub payload: Encrypted,
+}
+
+#[derive(Clone, Serializable)]
+#[wire(fixed)]
+struct SkippedKey {
+    ratchet_key: PublicKey,
+    counter: u32,
+    message_key: Seed,
+}
+
+impl Session {
+    /// Starts a session as the party that sends first.  `shared` must be a secret already agreed
+    /// with the peer, e.g. the output of a handshake, and `their_key` is the peer's ratchet key.
+    pub fn initiate(
+        shared: SharedSecret,
+        their_key: PublicKey,
+        rng: &mut dyn CryptoRngCore,
+    ) -> Result<Self> {
+        let sending_key = PrivateKey::generate(rng);
+        let (root_key, sending_chain) =
+            kdf_root(&Seed::from_shared_secret(shared), &sending_key, their_key)?;
+        let chains = Chains {
+            root_key,
+            sending_key,
+            receiving_key: Some(their_key),
+            sending_chain: Some(sending_chain),
+            receiving_chain: None,
+            sent: 0,
+            received: 0,
+            previous: 0,
+        };
+        Ok(Self { chains, skipped: Vec::new() })
+    }
+
+    /// Starts a session as the party that receives first, using the private half of the ratchet
+    /// key given to the initiator.
+    pub fn respond(shared: SharedSecret, our_key: PrivateKey) -> Self {
+        let chains = Chains {
+            root_key: Seed::from_shared_secret(shared),
+            sending_key: our_key,
+            receiving_key: None,
+            sending_chain: None,
+            receiving_chain: None,
+            sent: 0,
+            received: 0,
+            previous: 0,
+        };
+        Self { chains, skipped: Vec::new() }
+    }
+
+    pub fn ratchet_key(&self) -> PublicKey {
+        self.chains.sending_key.public_key()
+    }
+
+    /// Fails if this side has not yet received anything from an initiator.
+    pub fn encrypt<T: Encryptable>(&mut self, value: &T) -> Result<RatchetMessage> {
+        let chains = &mut self.chains;
+        let chain = chains.sending_chain.as_ref().ok_or(ErrorKind::InvalidState)?;
+        let (next_chain, message_key) = kdf_chain(chain);
+        let header = Header {
+            ratchet_key: chains.sending_key.public_key(),
+            previous: chains.previous,
+            counter: chains.sent,
+        };
+        let counter = chains.sent.checked_add(1).ok_or(ErrorKind::OutOfRange)?;
+        let (key, nonce) = message_secrets(&message_key);
+        let payload = value.encrypt(&key, nonce, header)?;
+        chains.sending_chain = Some(next_chain);
+        chains.sent = counter;
+        Ok(RatchetMessage { header, payload })
+    }
+
+    /// Decrypts `message`, advancing the ratchet.  The session is left untouched on failure.
+    pub fn decrypt<T: Encryptable>(
+        &mut self,
+        message: &RatchetMessage,
+        rng: &mut dyn CryptoRngCore,
+    ) -> Result<T> {
+        let header = message.header;
+        let found = self.skipped.iter().position(|skipped| {
+            skipped.ratchet_key == header.ratchet_key && skipped.counter == header.counter
+        });
+        if let Some(index) = found {
+            let value = open(message, &self.skipped[index].message_key)?;
+            self.skipped.remove(index);
+            return Ok(value);
+        }
+
+        // Advance a copy of the chains, collecting the keys skipped on the way, and keep both
+        // only once the message has been authenticated.
+        let mut chains = self.chains.clone();
+        let mut skipped = Vec::new();
+        if chains.receiving_key != Some(header.ratchet_key) {
+            chains.skip_until(header.previous, &mut skipped)?;
+            chains.step(header.ratchet_key, rng)?;
+        }
+        chains.skip_until(header.counter, &mut skipped)?;
+        let message_key = chains.next_receiving_key()?;
+        let value = open(message, &message_key)?;
+        self.chains = chains;
+        self.skipped.append(&mut skipped);
+        if self.skipped.len() > MAX_SKIPPED_KEYS {
+            let excess = self.skipped.len() - MAX_SKIPPED_KEYS;
+            self.skipped.drain(0..excess);
+        }
+        Ok(value)
+    }
+}
+
+impl Chains {
+    fn step(&mut self, their_key: PublicKey, rng: &mut dyn CryptoRngCore) -> Result<()> {
+        self.previous = self.sent;
+        self.sent = 0;
+        self.received = 0;
+        self.receiving_key = Some(their_key);
+        let (root_key, receiving_chain) = kdf_root(&self.root_key, &self.sending_key, their_key)?;
+        self.sending_key = PrivateKey::generate(rng);
+        let (root_key, sending_chain) = kdf_root(&root_key, &self.sending_key, their_key)?;
+        self.root_key = root_key;
+        self.receiving_chain = Some(receiving_chain);
+        self.sending_chain = Some(sending_chain);
+        Ok(())
+    }
+
+    fn next_receiving_key(&mut self) -> Result<Seed> {
+        let chain = self.receiving_chain.as_ref().ok_or(ErrorKind::InvalidState)?;
+        let (next_chain, message_key) = kdf_chain(chain);
+        self.receiving_chain = Some(next_chain);
+        self.received = self.received.checked_add(1).ok_or(ErrorKind::OutOfRange)?;
+        Ok(message_key)
+    }
+
+    fn skip_until(&mut self, until: u32, skipped: &mut Vec<SkippedKey>) -> Result<()> {
+        let Some(ratchet_key) = self.receiving_key else {
+            return Ok(());
+        };
+        if self.receiving_chain.is_none() {
+            return Ok(());
+        }
+        if until < self.received || until - self.received > MAX_SKIP {
+            return Err(ErrorKind::OutOfRange.into());
+        }
+        while self.received < until {
+            let counter = self.received;
+            let message_key = self.next_receiving_key()?;
+            skipped.push(SkippedKey { ratchet_key, counter, message_key });
+        }
+        Ok(())
+    }
+}
+
+/// Reading rejects more than [`MAX_SKIPPED_KEYS`] skipped keys.
+impl BaseSerializable for Session {
+    fn wire_write(&self, buffer: &mut dyn BufMut, format: WireFormat) {
+        self.chains.wire_write(buffer, format);
+        self.skipped.wire_write(buffer, format);
+    }
+    fn wire_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Self> {
+        let chains = Chains::wire_read(buffer, context)?;
+        let count = usize::wire_read(buffer, context)?;
+        if count > MAX_SKIPPED_KEYS {
+            return Err(ErrorKind::TooLarge { len: count, max: MAX_SKIPPED_KEYS }.into());
+        }
+        let skipped = (0..count)
+            .map(|_| SkippedKey::wire_read(buffer, context))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { chains, skipped })
+    }
+    fn wire_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()> {
+        Chains::wire_skip(buffer, context)?;
+        Vec::<SkippedKey>::wire_skip(buffer, context)
+    }
+}
+
+impl Serializable for Session {
+    fn wire_size(&self, format: WireFormat) -> usize {
+        self.chains.wire_size(format).strict_add(self.skipped.wire_size(format))
+    }
+}
+
+fn kdf_root(root_key: &Seed, ours: &PrivateKey, theirs: PublicKey) -> Result<(Seed, Seed)> {
+    let shared = ours.diffie_hellman(theirs)?;
+    let seed = Seed::from_key_material(root_key.expose(), shared.expose());
+    Ok((Seed::from(seed.generate(INFO_ROOT)), Seed::from(seed.generate(INFO_CHAIN))))
+}
+
+fn kdf_chain(chain: &Seed) -> (Seed, Seed) {
+    (Seed::from(chain.generate(INFO_CHAIN)), Seed::from(chain.generate(INFO_MESSAGE)))
+}
+
+fn message_secrets(message_key: &Seed) -> (Key, Nonce) {
+    let key = Key::from_shared_secret(message_key.generate(INFO_KEY));
+    let mut nonce = Nonce::zero();
+    message_key.generate_into(INFO_NONCE, nonce.as_slice_mut());
+    (key, nonce)
+}
+
+fn open<T: Encryptable>(message: &RatchetMessage, message_key: &Seed) -> Result<T> {
+    let (key, nonce) = message_secrets(message_key);
+    if message.payload.nonce != nonce {
+        return Err(ErrorKind::DecryptFailed.into());
+    }
+    T::decrypt(&message.payload, &key, message.header)
+}
+
+impl Printable for Session {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        write!(
+            writer,
+            "{{ratchet_key: {}, sent: {}, received: {}, skipped: {}}}",
+            self.ratchet_key(),
+            self.chains.sent,
+            self.chains.received,
+            self.skipped.len()
+        )
+    }
+}
+
+impl Printable for Header {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        write!(
+            writer,
+            "{{ratchet_key: {}, previous: {}, counter: {}}}",
+            self.ratchet_key, self.previous, self.counter
+        )
+    }
+}
+
+impl Printable for RatchetMessage {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        write!(writer, "{{header: {}, payload: {}}}", self.header, self.payload)
+    }
+}
+
+impl_debug_for_printable!(Session);
+impl_display_for_printable!(Session);
+
+impl_debug_for_printable!(Header);
+impl_display_for_printable!(Header);
+
+impl_debug_for_printable!(RatchetMessage);
+impl_display_for_printable!(RatchetMessage);
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::bytes::Bytes;
+    use crate::{util, Typed, UUID};
+
+    #[derive(Clone, PartialEq, Eq, Debug, Serializable)]
+    struct Text(Bytes);
+
+    impl Typed for Text {
+        const TYPE_UUID: UUID = UUID([0x5a; 16]);
+    }
+
+    impl Encryptable for Text {}
+
+    fn text(value: &'static str) -> Text {
+        Text(Bytes::from_static(value.as_bytes()))
+    }
+
+    fn pair() -> (Session, Session) {
+        let shared = SharedSecret::from_exposed([7; 32]);
+        let bob_key = PrivateKey::generate(&mut OsRng);
+        let alice = Session::initiate(shared.clone(), bob_key.public_key(), &mut OsRng).unwrap();
+        let bob = Session::respond(shared, bob_key);
+        (alice, bob)
+    }
+
+    #[test]
+    fn ratchet_conversation() {
+        let (mut alice, mut bob) = pair();
+        assert!(bob.encrypt(&text("too early")).is_err());
+
+        let m1 = alice.encrypt(&text("hello")).unwrap();
+        assert_eq!(text("hello"), bob.decrypt::<Text>(&m1, &mut OsRng).unwrap());
+
+        let m2 = bob.encrypt(&text("hi")).unwrap();
+        assert_ne!(m1.header.ratchet_key, m2.header.ratchet_key);
+        assert_eq!(text("hi"), alice.decrypt::<Text>(&m2, &mut OsRng).unwrap());
+
+        let m3 = alice.encrypt(&text("bye")).unwrap();
+        assert_ne!(m1.header.ratchet_key, m3.header.ratchet_key);
+        assert_eq!(text("bye"), bob.decrypt::<Text>(&m3, &mut OsRng).unwrap());
+    }
+
+    #[test]
+    fn ratchet_out_of_order() {
+        let (mut alice, mut bob) = pair();
+        let m1 = alice.encrypt(&text("one")).unwrap();
+        let m2 = alice.encrypt(&text("two")).unwrap();
+        let m3 = alice.encrypt(&text("three")).unwrap();
+
+        assert_eq!(text("three"), bob.decrypt::<Text>(&m3, &mut OsRng).unwrap());
+        let reply = bob.encrypt(&text("ack")).unwrap();
+        assert_eq!(text("ack"), alice.decrypt::<Text>(&reply, &mut OsRng).unwrap());
+        let m4 = alice.encrypt(&text("four")).unwrap();
+        assert_eq!(text("four"), bob.decrypt::<Text>(&m4, &mut OsRng).unwrap());
+
+        assert_eq!(text("one"), bob.decrypt::<Text>(&m1, &mut OsRng).unwrap());
+        assert_eq!(text("two"), bob.decrypt::<Text>(&m2, &mut OsRng).unwrap());
+    }
+
+    #[test]
+    fn ratchet_rejects_replay_and_tampering() {
+        let (mut alice, mut bob) = pair();
+        let m1 = alice.encrypt(&text("once")).unwrap();
+        assert!(bob.decrypt::<Text>(&m1, &mut OsRng).is_ok());
+        assert!(bob.decrypt::<Text>(&m1, &mut OsRng).is_err());
+
+        let mut m2 = alice.encrypt(&text("twice")).unwrap();
+        m2.header.previous = 9;
+        assert!(bob.decrypt::<Text>(&m2, &mut OsRng).is_err());
+        m2.header.previous = 0;
+        assert_eq!(text("twice"), bob.decrypt::<Text>(&m2, &mut OsRng).unwrap());
+    }
+
+    #[test]
+    fn ratchet_limits_skipping() {
+        let (mut alice, mut bob) = pair();
+        let messages: Vec<_> =
+            (0..=MAX_SKIP + 1).map(|_| alice.encrypt(&text("far ahead")).unwrap()).collect();
+        let err = bob.decrypt::<Text>(&messages[MAX_SKIP as usize + 1], &mut OsRng).unwrap_err();
+        assert_eq!(ErrorKind::OutOfRange, err.kind());
+        assert!(bob.skipped.is_empty());
+        assert!(bob.decrypt::<Text>(&messages[MAX_SKIP as usize], &mut OsRng).is_ok());
+        assert_eq!(MAX_SKIP as usize, bob.skipped.len());
+
+        // Skipping over more chains than that drops the oldest keys.
+        for _ in 0..2 {
+            let reply = bob.encrypt(&text("ack")).unwrap();
+            alice.decrypt::<Text>(&reply, &mut OsRng).unwrap();
+            let last = (0..=MAX_SKIP).map(|_| alice.encrypt(&text("again")).unwrap()).last();
+            bob.decrypt::<Text>(&last.unwrap(), &mut OsRng).unwrap();
+        }
+        assert_eq!(MAX_SKIPPED_KEYS, bob.skipped.len());
+        assert!(bob.decrypt::<Text>(&messages[0], &mut OsRng).is_err());
+    }
+
+    #[test]
+    fn ratchet_rejects_oversize_sessions() {
+        let (_, mut bob) = pair();
+        let skipped = SkippedKey {
+            ratchet_key: bob.ratchet_key(),
+            counter: 0,
+            message_key: Seed::from_exposed([1; 32]),
+        };
+        bob.skipped = alloc::vec![skipped; MAX_SKIPPED_KEYS + 1];
+        let err = util::deserialize::<Session>(util::serialize(&bob).unwrap().into())
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            ErrorKind::TooLarge { len: MAX_SKIPPED_KEYS + 1, max: MAX_SKIPPED_KEYS },
+            err.kind()
+        );
+    }
+
+    #[test]
+    fn ratchet_session_resumes() {
+        let (mut alice, mut bob) = pair();
+        let m1 = alice.encrypt(&text("before")).unwrap();
+        let m2 = alice.encrypt(&text("skipped")).unwrap();
+        assert!(bob.decrypt::<Text>(&m2, &mut OsRng).is_ok());
+
+        let mut bob = util::deserialize::<Session>(util::serialize(&bob).unwrap().into()).unwrap();
+        let mut alice =
+            util::deserialize::<Session>(util::serialize(&alice).unwrap().into()).unwrap();
+        assert_eq!(text("before"), bob.decrypt::<Text>(&m1, &mut OsRng).unwrap());
+        let m3 = bob.encrypt(&text("after")).unwrap();
+        assert_eq!(text("after"), alice.decrypt::<Text>(&m3, &mut OsRng).unwrap());
+    }
+}
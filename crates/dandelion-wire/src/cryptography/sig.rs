@@ -1,7 +1,7 @@
 use cryptoxide::ed25519;
 
 use super::SharedSecret;
-use crate::{dandelion_wire, Error, PublicBytes, Result, SecretBytes};
+use crate::{dandelion_wire, ErrorKind, PublicBytes, Result, SecretBytes};
 
 secret_bytes!(PrivateKey, raw RawPrivateKey, size PRIVATE_KEY_SIZE = 32);
 public_bytes!(PublicKey, raw RawPublicKey, size PUBLIC_KEY_SIZE = 32);
@@ -28,7 +28,7 @@ impl PublicKey {
         if ed25519::verify(data, self.as_exact(), sig.as_exact()) {
             Ok(())
         } else {
-            Err(Error)
+            Err(ErrorKind::BadSignature.into())
         }
     }
 }
@@ -6,21 +6,28 @@ use super::{
     dandelion_wire,
     util,
     BaseSerializable,
+    DecodeContext,
+    DecodeLimits,
     FixedSizeSerializable,
     Printable,
     Result,
     Serializable,
     Typed,
+    WireFormat,
     UUID,
 };
 
 pub trait Encryptable: Typed + Serializable {
-    fn encrypt(&self, key: &Key, nonce: Nonce, extra: impl Serializable) -> Encrypted {
+    /// The limits [`Encryptable::decrypt`] decodes the plaintext with.  Authentication only proves
+    /// who encrypted it, so types that peers send should tighten these.
+    const DECODE_LIMITS: DecodeLimits = DecodeLimits::DEFAULT;
+
+    fn encrypt(&self, key: &Key, nonce: Nonce, extra: impl Serializable) -> Result<Encrypted> {
         let associated = prepare_associated(Self::TYPE_UUID, nonce, extra);
-        let mut buffer = util::serialize(self);
+        let mut buffer = util::serialize(self)?;
         let tag = key.encrypt_in_place(nonce, Some(associated.as_ref()), &mut buffer);
         let ciphertext = Bytes::from(buffer);
-        Encrypted { nonce, ciphertext, tag }
+        Ok(Encrypted { nonce, ciphertext, tag })
     }
 
     fn decrypt(encrypted: &Encrypted, key: &Key, extra: impl Serializable) -> Result<Self> {
@@ -32,20 +39,22 @@ pub trait Encryptable: Typed + Serializable {
             &mut buffer,
             encrypted.tag,
         )?;
-        util::deserialize::<Self>(buffer.into())
+        let mut context = DecodeContext::with_limits(WireFormat::V1, Self::DECODE_LIMITS);
+        util::deserialize_with::<Self>(buffer.into(), &mut context)
     }
 }
 
 fn prepare_associated(type_uuid: UUID, nonce: Nonce, extra: impl Serializable) -> Bytes {
-    let capacity = UUID::WIRE_SIZE.strict_add(Nonce::WIRE_SIZE).strict_add(extra.wire_size());
+    let capacity =
+        UUID::WIRE_SIZE.strict_add(Nonce::WIRE_SIZE).strict_add(extra.wire_size(WireFormat::V1));
     let mut buffer = BytesMut::with_capacity(capacity);
-    type_uuid.wire_write(&mut buffer);
-    nonce.wire_write(&mut buffer);
-    extra.wire_write(&mut buffer);
+    type_uuid.wire_write(&mut buffer, WireFormat::V1);
+    nonce.wire_write(&mut buffer, WireFormat::V1);
+    extra.wire_write(&mut buffer, WireFormat::V1);
     Bytes::from(buffer)
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serializable)]
 pub struct Encrypted {
     pub nonce: Nonce,
     pub ciphertext: Bytes,
@@ -58,6 +67,5 @@ impl Printable for Encrypted {
     }
 }
 
-impl_serializable_for_struct!(Encrypted { nonce: Nonce, ciphertext: Bytes, tag: Tag });
 impl_debug_for_printable!(Encrypted);
 impl_display_for_printable!(Encrypted);
@@ -1,17 +1,248 @@
+use core::fmt;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// What went wrong, plus the struct fields that were being decoded when it did.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    path: FieldPath,
+}
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-pub struct Error;
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A value needed `needed` bytes but the buffer had only `remaining` left.
+    Truncated {
+        needed: usize,
+        remaining: usize,
+    },
+    /// A complete value was decoded but `remaining` bytes were left over.
+    TrailingBytes {
+        remaining: usize,
+    },
+    /// An enum or message code that this implementation does not know.
+    UnknownCode {
+        type_name: &'static str,
+        code: u64,
+    },
+    /// A type UUID other than the one expected.
+    UnexpectedType,
+    /// A length of `len` where at most `max` is allowed.
+    TooLarge {
+        len: usize,
+        max: usize,
+    },
+    /// Values nested more than `max` deep.
+    TooDeep {
+        max: usize,
+    },
+    /// An integer that does not fit its destination, or a counter that would overflow.
+    OutOfRange,
+    /// A value that decoded but breaks the rules of its type.
+    Invalid {
+        type_name: &'static str,
+    },
+    /// A public key that is not a valid curve point.
+    BadKey,
+    BadSignature,
+    /// A correctly signed value whose signer is not the key that signed it.
+    SignerMismatch,
+    /// The authentication tag did not match, or the nonce was not the expected one.
+    DecryptFailed,
+    /// Content whose digest is not the ID it was stored or requested under.
+    DigestMismatch,
+    /// An operation that is not allowed in the current state, e.g. out of turn in a handshake.
+    InvalidState,
+    UnknownPeer,
+    /// The peer shares no protocol version or cipher suite with this side.
+    Incompatible,
+    NotFound,
+    /// The operating system refused access, e.g. to a file.
+    PermissionDenied,
+    /// The operating system reported an I/O failure.
+    Io,
+}
+
+/// The struct fields being decoded when an error occurred, recorded as `Type.field` by
+/// [`impl_serializable_for_struct`](crate::impl_serializable_for_struct).  Only the innermost
+/// [`FieldPath::CAPACITY`] are kept so that errors stay small and `Copy`.
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub struct FieldPath {
+    // Innermost first.
+    fields: [&'static str; FieldPath::CAPACITY],
+    len: u8,
+    elided: bool,
+}
+
+impl Error {
+    pub const fn new(kind: ErrorKind) -> Self {
+        Self { kind, path: FieldPath::EMPTY }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn path(&self) -> &FieldPath {
+        &self.path
+    }
+
+    /// Records that the error happened while decoding `field`, written as `Type.field`.  Called
+    /// from the innermost field outwards.
+    pub fn in_field(mut self, field: &'static str) -> Self {
+        self.path.push(field);
+        self
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl FieldPath {
+    pub const CAPACITY: usize = 4;
+
+    pub const EMPTY: Self = Self { fields: [""; Self::CAPACITY], len: 0, elided: false };
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The recorded fields, outermost first.
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.fields[..usize::from(self.len)].iter().rev().copied()
+    }
+
+    /// Whether outer fields were dropped because the path was full.
+    pub fn is_elided(&self) -> bool {
+        self.elided
+    }
+
+    fn push(&mut self, field: &'static str) {
+        match self.fields.get_mut(usize::from(self.len)) {
+            Some(slot) => {
+                *slot = field;
+                self.len = self.len.strict_add(1);
+            },
+            None => self.elided = true,
+        }
+    }
+}
+
+impl Default for FieldPath {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated { needed, remaining } => {
+                write!(fmt, "truncated: needed {needed} bytes, {remaining} remaining")
+            },
+            Self::TrailingBytes { remaining } => write!(fmt, "{remaining} trailing bytes"),
+            Self::UnknownCode { type_name, code } => {
+                write!(fmt, "unknown {type_name} code {code:#x}")
+            },
+            Self::UnexpectedType => fmt.write_str("unexpected type UUID"),
+            Self::TooLarge { len, max } => write!(fmt, "length {len} exceeds maximum {max}"),
+            Self::TooDeep { max } => write!(fmt, "nested more than {max} deep"),
+            Self::OutOfRange => fmt.write_str("value out of range"),
+            Self::Invalid { type_name } => write!(fmt, "invalid {type_name}"),
+            Self::BadKey => fmt.write_str("invalid public key"),
+            Self::BadSignature => fmt.write_str("bad signature"),
+            Self::SignerMismatch => fmt.write_str("signer mismatch"),
+            Self::DecryptFailed => fmt.write_str("decryption failed"),
+            Self::DigestMismatch => fmt.write_str("digest mismatch"),
+            Self::InvalidState => fmt.write_str("invalid state"),
+            Self::UnknownPeer => fmt.write_str("unknown peer"),
+            Self::Incompatible => fmt.write_str("incompatible peer"),
+            Self::NotFound => fmt.write_str("not found"),
+            Self::PermissionDenied => fmt.write_str("permission denied"),
+            Self::Io => fmt.write_str("I/O error"),
+        }
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.elided {
+            fmt.write_str("...")?;
+        }
+        for (index, field) in self.iter().enumerate() {
+            if index > 0 || self.elided {
+                fmt.write_str(" > ")?;
+            }
+            fmt.write_str(field)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for FieldPath {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "FieldPath({self})")
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(fmt, "{}: ", self.path)?;
+        }
+        self.kind.fmt(fmt)
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl From<core::convert::Infallible> for Error {
+    fn from(never: core::convert::Infallible) -> Self {
+        match never {}
+    }
+}
 
 macro_rules! error_from {
-    ($source:ty) => {
+    ($source:ty => $kind:expr) => {
         impl From<$source> for Error {
             fn from(_: $source) -> Self {
-                Self
+                Self::new($kind)
             }
         }
     };
 }
 
-error_from!(core::convert::Infallible);
-error_from!(core::num::TryFromIntError);
+error_from!(core::num::TryFromIntError => ErrorKind::OutOfRange);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_path() {
+        let err = Error::new(ErrorKind::Truncated { needed: 16, remaining: 3 })
+            .in_field("Encrypted.tag")
+            .in_field("Envelope.payload");
+        assert_eq!(ErrorKind::Truncated { needed: 16, remaining: 3 }, err.kind());
+        assert_eq!(
+            alloc::vec!["Envelope.payload", "Encrypted.tag"],
+            err.path().iter().collect::<alloc::vec::Vec<_>>()
+        );
+        assert_eq!(
+            "Envelope.payload > Encrypted.tag: truncated: needed 16 bytes, 3 remaining",
+            alloc::format!("{err}")
+        );
+
+        let mut deep = Error::new(ErrorKind::BadKey);
+        for _ in 0..=FieldPath::CAPACITY {
+            deep = deep.in_field("T.f");
+        }
+        assert!(deep.path().is_elided());
+        assert_eq!(FieldPath::CAPACITY, deep.path().iter().count());
+        assert!(alloc::format!("{deep}").starts_with("... > T.f > "));
+    }
+}
@@ -6,6 +6,7 @@ mod macros;
 
 extern crate alloc;
 pub extern crate bytes;
+pub extern crate rand_core;
 pub extern crate zeroize;
 use alloc::boxed::Box;
 use alloc::fmt;
@@ -21,10 +22,18 @@ pub mod signable;
 pub mod util;
 pub mod uuid;
 
+pub use dandelion_macros::{Printable, Serializable};
 pub use encryptable::{Encryptable, Encrypted};
-pub use error::{Error, Result};
+pub use error::{Error, ErrorKind, FieldPath, Result};
 pub use printable::Printable;
-pub use serializable::{BaseSerializable, FixedSizeSerializable, Serializable};
+pub use serializable::{
+    BaseSerializable,
+    DecodeContext,
+    DecodeLimits,
+    FixedSizeSerializable,
+    Serializable,
+    WireFormat,
+};
 pub use signable::{Signable, Signed};
 pub use uuid::{Typed, UUID};
 
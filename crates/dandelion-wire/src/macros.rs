@@ -70,21 +70,27 @@ macro_rules! secret_bytes {
 
         #[allow(unused_imports)]
         impl dandelion_wire::BaseSerializable for $ty {
-            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut) {
+            fn wire_write(
+                &self,
+                buffer: &mut dyn dandelion_wire::bytes::BufMut,
+                format: dandelion_wire::WireFormat,
+            ) {
                 use dandelion_wire::{BaseSerializable, SecretBytes};
-                self.expose().wire_write(buffer);
+                self.expose().wire_write(buffer, format);
             }
             fn wire_read(
                 buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
             ) -> dandelion_wire::Result<Self> {
                 use dandelion_wire::{BaseSerializable, SecretBytes};
-                Ok(Self::from_exposed($raw::wire_read(buffer)?))
+                Ok(Self::from_exposed($raw::wire_read(buffer, context)?))
             }
             fn wire_skip(
                 buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
             ) -> dandelion_wire::Result<()> {
                 use dandelion_wire::BaseSerializable;
-                $raw::wire_skip(buffer)
+                $raw::wire_skip(buffer, context)
             }
         }
 
@@ -155,21 +161,27 @@ macro_rules! public_bytes {
 
         #[allow(unused_imports)]
         impl dandelion_wire::BaseSerializable for $ty {
-            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut) {
+            fn wire_write(
+                &self,
+                buffer: &mut dyn dandelion_wire::bytes::BufMut,
+                format: dandelion_wire::WireFormat,
+            ) {
                 use dandelion_wire::{BaseSerializable, PublicBytes};
-                self.as_exact().wire_write(buffer);
+                self.as_exact().wire_write(buffer, format);
             }
             fn wire_read(
                 buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
             ) -> dandelion_wire::Result<Self> {
                 use dandelion_wire::{BaseSerializable, PublicBytes};
-                Ok(Self::from_exact($raw::wire_read(buffer)?))
+                Ok(Self::from_exact($raw::wire_read(buffer, context)?))
             }
             fn wire_skip(
                 buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
             ) -> dandelion_wire::Result<()> {
                 use dandelion_wire::BaseSerializable;
-                $raw::wire_skip(buffer)
+                $raw::wire_skip(buffer, context)
             }
         }
 
@@ -315,18 +327,28 @@ macro_rules! public_bytes {
 macro_rules! impl_serializable_todo {
     ( $ty:ty ) => {
         impl dandelion_wire::BaseSerializable for $ty {
-            fn wire_write(&self, _: &mut dyn dandelion_wire::bytes::BufMut) {
+            fn wire_write(
+                &self,
+                _: &mut dyn dandelion_wire::bytes::BufMut,
+                _: dandelion_wire::WireFormat,
+            ) {
                 todo!()
             }
-            fn wire_read(_: &mut dyn dandelion_wire::bytes::Buf) -> dandelion_wire::Result<Self> {
+            fn wire_read(
+                _: &mut dyn dandelion_wire::bytes::Buf,
+                _: &mut dandelion_wire::DecodeContext,
+            ) -> dandelion_wire::Result<Self> {
                 todo!()
             }
-            fn wire_skip(_: &mut dyn dandelion_wire::bytes::Buf) -> dandelion_wire::Result<()> {
+            fn wire_skip(
+                _: &mut dyn dandelion_wire::bytes::Buf,
+                _: &mut dandelion_wire::DecodeContext,
+            ) -> dandelion_wire::Result<()> {
                 todo!()
             }
         }
         impl dandelion_wire::Serializable for $ty {
-            fn wire_size(&self) -> usize {
+            fn wire_size(&self, _: dandelion_wire::WireFormat) -> usize {
                 todo!()
             }
         }
@@ -337,18 +359,24 @@ macro_rules! impl_serializable_todo {
 macro_rules! impl_serializable_for_wrapper {
     ( $ty:ty, wraps $inner:ty, fixed size ) => {
         impl dandelion_wire::BaseSerializable for $ty {
-            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut) {
-                self.0.wire_write(buffer)
+            fn wire_write(
+                &self,
+                buffer: &mut dyn dandelion_wire::bytes::BufMut,
+                format: dandelion_wire::WireFormat,
+            ) {
+                self.0.wire_write(buffer, format)
             }
             fn wire_read(
                 buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
             ) -> dandelion_wire::Result<Self> {
-                Ok(Self(<$inner>::wire_read(buffer)?))
+                Ok(Self(<$inner>::wire_read(buffer, context)?))
             }
             fn wire_skip(
                 buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
             ) -> dandelion_wire::Result<()> {
-                <$inner>::wire_skip(buffer)?;
+                <$inner>::wire_skip(buffer, context)?;
                 Ok(())
             }
         }
@@ -358,24 +386,30 @@ macro_rules! impl_serializable_for_wrapper {
     };
     ( $ty:ty, wraps $inner:ty ) => {
         impl dandelion_wire::BaseSerializable for $ty {
-            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut) {
-                self.0.wire_write(buffer)
+            fn wire_write(
+                &self,
+                buffer: &mut dyn dandelion_wire::bytes::BufMut,
+                format: dandelion_wire::WireFormat,
+            ) {
+                self.0.wire_write(buffer, format)
             }
             fn wire_read(
                 buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
             ) -> dandelion_wire::Result<Self> {
-                Ok(Self(<$inner>::wire_read(buffer)?))
+                Ok(Self(<$inner>::wire_read(buffer, context)?))
             }
             fn wire_skip(
                 buffer: &mut dyn dandelion_wire::bytes::Buf,
+                context: &mut dandelion_wire::DecodeContext,
             ) -> dandelion_wire::Result<()> {
-                <$inner>::wire_skip(buffer)?;
+                <$inner>::wire_skip(buffer, context)?;
                 Ok(())
             }
         }
         impl dandelion_wire::Serializable for $ty {
-            fn wire_size(&self) -> usize {
-                self.0.wire_size()
+            fn wire_size(&self, format: dandelion_wire::WireFormat) -> usize {
+                self.0.wire_size(format)
             }
         }
     };
@@ -385,16 +419,18 @@ macro_rules! impl_serializable_for_wrapper {
 macro_rules! impl_serializable_for_struct {
     ( $ty:ty { $( $field:ident : $field_ty:ty ),* $(,)? }, fixed size ) => {
         impl dandelion_wire::BaseSerializable for $ty {
-            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut) {
-                $( self.$field.wire_write(buffer); )*
+            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut, format: dandelion_wire::WireFormat) {
+                $( self.$field.wire_write(buffer, format); )*
             }
-            fn wire_read(buffer: &mut dyn dandelion_wire::bytes::Buf) -> dandelion_wire::Result<Self> {
+            fn wire_read(buffer: &mut dyn dandelion_wire::bytes::Buf, context: &mut dandelion_wire::DecodeContext) -> dandelion_wire::Result<Self> {
                 Ok(Self {
-                    $( $field: <$field_ty>::wire_read(buffer)?, )*
+                    $( $field: <$field_ty>::wire_read(buffer, context)
+                        .map_err(|err| err.in_field(concat!(stringify!($ty), ".", stringify!($field))))?, )*
                 })
             }
-            fn wire_skip(buffer: &mut dyn dandelion_wire::bytes::Buf) -> dandelion_wire::Result<()> {
-                $( <$field_ty>::wire_skip(buffer)?; )*
+            fn wire_skip(buffer: &mut dyn dandelion_wire::bytes::Buf, context: &mut dandelion_wire::DecodeContext) -> dandelion_wire::Result<()> {
+                $( <$field_ty>::wire_skip(buffer, context)
+                    .map_err(|err| err.in_field(concat!(stringify!($ty), ".", stringify!($field))))?; )*
                 Ok(())
             }
         }
@@ -404,22 +440,24 @@ macro_rules! impl_serializable_for_struct {
     };
     ( $ty:ty { $( $field:ident : $field_ty:ty ),* $(,)? } ) => {
         impl dandelion_wire::BaseSerializable for $ty {
-            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut) {
-                $( self.$field.wire_write(buffer); )*
+            fn wire_write(&self, buffer: &mut dyn dandelion_wire::bytes::BufMut, format: dandelion_wire::WireFormat) {
+                $( self.$field.wire_write(buffer, format); )*
             }
-            fn wire_read(buffer: &mut dyn dandelion_wire::bytes::Buf) -> dandelion_wire::Result<Self> {
+            fn wire_read(buffer: &mut dyn dandelion_wire::bytes::Buf, context: &mut dandelion_wire::DecodeContext) -> dandelion_wire::Result<Self> {
                 Ok(Self {
-                    $( $field: <$field_ty>::wire_read(buffer)?, )*
+                    $( $field: <$field_ty>::wire_read(buffer, context)
+                        .map_err(|err| err.in_field(concat!(stringify!($ty), ".", stringify!($field))))?, )*
                 })
             }
-            fn wire_skip(buffer: &mut dyn dandelion_wire::bytes::Buf) -> dandelion_wire::Result<()> {
-                $( <$field_ty>::wire_skip(buffer)?; )*
+            fn wire_skip(buffer: &mut dyn dandelion_wire::bytes::Buf, context: &mut dandelion_wire::DecodeContext) -> dandelion_wire::Result<()> {
+                $( <$field_ty>::wire_skip(buffer, context)
+                    .map_err(|err| err.in_field(concat!(stringify!($ty), ".", stringify!($field))))?; )*
                 Ok(())
             }
         }
         impl dandelion_wire::Serializable for $ty {
-            fn wire_size(&self) -> usize {
-                0usize $( .strict_add(self.$field.wire_size()) )*
+            fn wire_size(&self, format: dandelion_wire::WireFormat) -> usize {
+                0usize $( .strict_add(self.$field.wire_size(format)) )*
             }
         }
     };
@@ -470,3 +508,158 @@ macro_rules! impl_printable_for_struct {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use alloc::vec::Vec;
+
+    use crate::bytes::Bytes;
+    use crate::cryptography::sig::PublicKey;
+    use crate::{
+        dandelion_wire,
+        util,
+        BaseSerializable,
+        DecodeContext,
+        ErrorKind,
+        FixedSizeSerializable,
+        Printable,
+        PublicBytes,
+        Serializable,
+        WireFormat,
+    };
+
+    #[derive(Clone)]
+    struct OldRecord {
+        key: PublicKey,
+        keys: Vec<PublicKey>,
+        maybe: Option<PublicKey>,
+    }
+
+    impl_serializable_for_struct!(OldRecord { key: PublicKey, keys: Vec<PublicKey>, maybe: Option<PublicKey> });
+    impl_printable_for_struct!(OldRecord { key, keys, maybe });
+    impl_debug_for_printable!(OldRecord);
+
+    #[derive(Clone, Serializable, Printable)]
+    struct NewRecord {
+        key: PublicKey,
+        keys: Vec<PublicKey>,
+        maybe: Option<PublicKey>,
+    }
+
+    struct OldFixed {
+        a: u32,
+        b: [u8; 3],
+        c: bool,
+    }
+
+    impl_serializable_for_struct!(OldFixed { a: u32, b: [u8; 3], c: bool }, fixed size);
+
+    #[derive(Serializable)]
+    struct NewFixed {
+        a: u32,
+        b: [u8; 3],
+        c: bool,
+    }
+
+    struct OldWrapper(Bytes);
+
+    impl_serializable_for_wrapper!(OldWrapper, wraps Bytes);
+
+    #[derive(Serializable)]
+    struct NewWrapper(Bytes);
+
+    #[derive(Serializable, Printable)]
+    struct Framed {
+        #[wire(nested)]
+        record: NewRecord,
+        #[print(redact)]
+        key: PublicKey,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq, Serializable, Printable)]
+    #[repr(u8)]
+    enum Color {
+        Red = 1,
+        #[wire(name = "verdant")]
+        Green = 7,
+    }
+
+    fn key(fill: u8) -> PublicKey {
+        PublicKey::from_exact([fill; 32])
+    }
+
+    #[test]
+    fn derived_structs_match_macros() {
+        let records = [(Vec::new(), None), (Vec::from([key(2), key(3)]), Some(key(4)))];
+        for ((keys, maybe), format) in records.into_iter().zip([WireFormat::V1, WireFormat::V2]) {
+            let old = OldRecord { key: key(1), keys: keys.clone(), maybe };
+            let new = NewRecord { key: key(1), keys, maybe };
+            let raw = util::serialize_as(&old, format).unwrap().freeze();
+            assert_eq!(raw, util::serialize_as(&new, format).unwrap().freeze());
+            assert_eq!(old.wire_size(format), new.wire_size(format));
+            assert_eq!(raw.len(), new.wire_size(format));
+            assert_eq!(old.as_printed(), new.as_printed());
+            assert!(util::deserialize_as::<NewRecord>(raw.clone(), format).is_ok());
+
+            let truncated = raw.slice(..raw.len() - 1);
+            let old_err = util::deserialize_as::<OldRecord>(truncated.clone(), format).unwrap_err();
+            let new_err = util::deserialize_as::<NewRecord>(truncated, format).unwrap_err();
+            assert_eq!(old_err.kind(), new_err.kind());
+            assert_eq!(
+                format!("{old_err}").replace("OldRecord", "Record"),
+                format!("{new_err}").replace("NewRecord", "Record"),
+            );
+
+            let mut skipped = raw;
+            NewRecord::wire_skip(&mut skipped, &mut DecodeContext::new(format)).unwrap();
+            assert!(skipped.is_empty());
+        }
+
+        assert_eq!(OldFixed::WIRE_SIZE, NewFixed::WIRE_SIZE);
+        assert_eq!(
+            util::serialize(&OldFixed { a: 0x01020304, b: [5, 6, 7], c: true }).unwrap(),
+            util::serialize(&NewFixed { a: 0x01020304, b: [5, 6, 7], c: true }).unwrap(),
+        );
+
+        let payload = Bytes::from_static(b"payload");
+        let raw = util::serialize(&OldWrapper(payload.clone())).unwrap().freeze();
+        assert_eq!(raw, util::serialize(&NewWrapper(payload.clone())).unwrap().freeze());
+        assert_eq!(payload, util::deserialize::<NewWrapper>(raw).unwrap().0);
+    }
+
+    #[test]
+    fn derived_attributes() {
+        let record = NewRecord { key: key(1), keys: Vec::new(), maybe: None };
+        let framed = Framed { record: record.clone(), key: key(9) };
+        let mut expected = util::serialize(&(record.wire_size(WireFormat::V1) as u32)).unwrap();
+        expected.extend_from_slice(&util::serialize(&record).unwrap());
+        expected.extend_from_slice(&[9; 32]);
+        assert_eq!(expected, util::serialize(&framed).unwrap());
+        assert_eq!(expected.len(), framed.wire_size(WireFormat::V1));
+        assert_eq!(
+            format!("{{record: {}, key: <redacted>}}", record.as_printed()),
+            framed.as_printed()
+        );
+
+        let err = util::deserialize::<Framed>(expected.freeze().slice(..40)).unwrap_err();
+        assert_eq!(alloc::vec!["Framed.record"], err.path().iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn derived_code_enums() {
+        assert_eq!(1, Color::WIRE_SIZE);
+        assert_eq!([7], util::serialize(&Color::Green).unwrap().as_ref());
+        assert_eq!(Color::Red, util::deserialize::<Color>(Bytes::from_static(&[1])).unwrap());
+        assert_eq!(
+            ErrorKind::UnknownCode { type_name: "Color", code: 2 },
+            util::deserialize::<Color>(Bytes::from_static(&[2])).unwrap_err().kind()
+        );
+        assert_eq!((Color::CODE_GREEN, Color::NAME_GREEN), (7, "verdant"));
+        assert_eq!([1, 7], Color::ALL_CODES);
+        assert_eq!(Ok(Color::Green), Color::try_from(7).map_err(|err| err.kind()));
+        assert_eq!(7u8, Color::Green.into());
+        assert_eq!("verdant", format!("{}", Color::Green));
+        assert_eq!("Color::verdant", format!("{:?}", Color::Green));
+    }
+}
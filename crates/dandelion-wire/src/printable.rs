@@ -36,6 +36,15 @@ impl<T: Printable> Printable for Vec<T> {
     }
 }
 
+impl<T: Printable> Printable for Option<T> {
+    fn print(&self, writer: &mut dyn Write) -> Result {
+        match self {
+            Some(value) => value.print(writer),
+            None => writer.write_str("None"),
+        }
+    }
+}
+
 pub fn print_public_bytes(writer: &mut dyn Write, data: &[u8]) -> Result {
     const SIZE_LIMIT: usize = 96;
     if data.len() >= SIZE_LIMIT {
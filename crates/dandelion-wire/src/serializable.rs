@@ -1,115 +1,288 @@
 use alloc::vec::Vec;
 
 use super::bytes::{Buf, BufMut, Bytes, BytesMut};
-use super::{util, Error, Result};
+use super::{util, ErrorKind, Result};
+
+/// How lengths and counts are encoded: every `usize`, and so every `Vec`, `Bytes` and nested
+/// value.  Everything else is the same in every format.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WireFormat {
+    /// A big-endian `u32`.  Lengths of 4 GiB or more cannot be represented.
+    #[default]
+    V1,
+    /// A QUIC variable-length integer (RFC 9000, section 16): 1, 2, 4 or 8 bytes, with the size
+    /// in the top two bits of the first byte.  Lengths of 2^62 or more cannot be represented.
+    V2,
+}
+
+impl WireFormat {
+    /// The format used by links that agreed on protocol `version`.
+    pub const fn for_version(version: u16) -> Self {
+        if version >= 2 {
+            Self::V2
+        } else {
+            Self::V1
+        }
+    }
+
+    /// The largest length or count this format can represent.
+    pub const fn max_len(self) -> usize {
+        let max = match self {
+            Self::V1 => u32::MAX as u64,
+            Self::V2 => util::VARINT_MAX,
+        };
+        if max > usize::MAX as u64 {
+            usize::MAX
+        } else {
+            max as usize
+        }
+    }
+}
+
+/// Bounds on what decoding one value may cost, so that a hostile peer cannot make a small input
+/// claim huge lengths, counts or nesting.  Exceeding one fails with [`ErrorKind::TooLarge`] or
+/// [`ErrorKind::TooDeep`].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// The most bytes that length-prefixed values may take from the input, summed over the whole
+    /// value.  A nested value counts once for its frame and again for its contents.  Decoding
+    /// from [`Bytes`] shares the input rather than copying it, but is held to the same limit.
+    pub max_total_bytes: usize,
+    /// The most items any one `Vec` may hold.
+    pub max_collection_len: usize,
+    /// The most nested values (see [`util::nested_read`]) that may enclose one another.
+    pub max_depth: usize,
+}
+
+impl DecodeLimits {
+    /// What [`Default`] returns: generous enough for any value a peer sends legitimately.
+    pub const DEFAULT: Self =
+        Self { max_total_bytes: 64 << 20, max_collection_len: 1 << 20, max_depth: 16 };
+
+    /// For input that is already trusted, e.g. read back from local storage.
+    pub const UNLIMITED: Self =
+        Self { max_total_bytes: usize::MAX, max_collection_len: usize::MAX, max_depth: usize::MAX };
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The state of one decode: the format being read and how much of its [`DecodeLimits`] has been
+/// used so far.
+#[derive(Clone, Debug, Default)]
+pub struct DecodeContext {
+    pub format: WireFormat,
+    pub limits: DecodeLimits,
+    total_bytes: usize,
+    depth: usize,
+}
+
+impl DecodeContext {
+    /// Uses the default [`DecodeLimits`].
+    pub fn new(format: WireFormat) -> Self {
+        Self::with_limits(format, DecodeLimits::default())
+    }
+
+    pub fn with_limits(format: WireFormat, limits: DecodeLimits) -> Self {
+        Self { format, limits, total_bytes: 0, depth: 0 }
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Counts `len` bytes against `max_total_bytes`.  Call before allocating them.
+    pub fn reserve_bytes(&mut self, len: usize) -> Result<()> {
+        let max = self.limits.max_total_bytes;
+        match self.total_bytes.checked_add(len) {
+            Some(total) if total <= max => {
+                self.total_bytes = total;
+                Ok(())
+            },
+            _ => Err(ErrorKind::TooLarge { len, max: max.saturating_sub(self.total_bytes) }.into()),
+        }
+    }
+
+    /// Fails if a collection of `len` items is over `max_collection_len`.
+    pub fn check_len(&self, len: usize) -> Result<()> {
+        let max = self.limits.max_collection_len;
+        if len > max {
+            return Err(ErrorKind::TooLarge { len, max }.into());
+        }
+        Ok(())
+    }
+
+    /// Runs `read` one level deeper, failing if that is over `max_depth`.
+    pub fn nested<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= self.limits.max_depth {
+            return Err(ErrorKind::TooDeep { max: self.limits.max_depth }.into());
+        }
+        self.depth = self.depth.strict_add(1);
+        let result = read(self);
+        self.depth = self.depth.strict_sub(1);
+        result
+    }
+}
 
 pub trait BaseSerializable: Sized {
-    fn wire_write(&self, buffer: &mut dyn BufMut);
-    fn wire_read(buffer: &mut dyn Buf) -> Result<Self>;
-    fn wire_skip(buffer: &mut dyn Buf) -> Result<()>;
+    fn wire_write(&self, buffer: &mut dyn BufMut, format: WireFormat);
+    fn wire_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Self>;
+    fn wire_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()>;
 
-    fn wire_skip_many(buffer: &mut dyn Buf, count: usize) -> Result<()> {
-        util::generic_skip_many::<Self>(buffer, count)
+    fn wire_skip_many(
+        buffer: &mut dyn Buf,
+        context: &mut DecodeContext,
+        count: usize,
+    ) -> Result<()> {
+        util::generic_skip_many::<Self>(buffer, context, count)
     }
 }
 
+/// A type whose wire size is the same for every value and in every [`WireFormat`].
 pub trait FixedSizeSerializable: BaseSerializable {
     const WIRE_SIZE: usize;
 
-    fn wire_skip_many(buffer: &mut dyn Buf, count: usize) -> Result<()> {
+    fn wire_skip_many(
+        buffer: &mut dyn Buf,
+        context: &mut DecodeContext,
+        count: usize,
+    ) -> Result<()> {
         if let Some(len) = Self::WIRE_SIZE.checked_mul(count) {
             util::generic_skip(buffer, len)
         } else {
-            util::generic_skip_many::<Self>(buffer, count)
+            util::generic_skip_many::<Self>(buffer, context, count)
         }
     }
 }
 
 pub trait Serializable: BaseSerializable {
-    fn wire_size(&self) -> usize;
+    fn wire_size(&self, format: WireFormat) -> usize;
 }
 
 impl<T: FixedSizeSerializable> Serializable for T {
-    fn wire_size(&self) -> usize {
+    fn wire_size(&self, _: WireFormat) -> usize {
         T::WIRE_SIZE
     }
 }
 
 impl BaseSerializable for BytesMut {
-    fn wire_write(&self, buffer: &mut dyn BufMut) {
-        util::varlen_write(buffer, self.as_ref());
+    fn wire_write(&self, buffer: &mut dyn BufMut, format: WireFormat) {
+        util::varlen_write(buffer, format, self.as_ref());
     }
-    fn wire_read(buffer: &mut dyn Buf) -> Result<Self> {
-        util::varlen_read(buffer)
+    fn wire_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Self> {
+        Ok(BytesMut::from(util::varlen_read(buffer, context)?.as_ref()))
     }
-    fn wire_skip(buffer: &mut dyn Buf) -> Result<()> {
-        util::varlen_skip(buffer)?;
+    fn wire_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()> {
+        util::varlen_skip(buffer, context)?;
         Ok(())
     }
 }
 
 impl Serializable for BytesMut {
-    fn wire_size(&self) -> usize {
-        util::varlen_wire_size(self.len())
+    fn wire_size(&self, format: WireFormat) -> usize {
+        util::varlen_wire_size(format, self.len())
     }
 }
 
 impl BaseSerializable for Bytes {
-    fn wire_write(&self, buffer: &mut dyn BufMut) {
-        util::varlen_write(buffer, self.as_ref());
+    fn wire_write(&self, buffer: &mut dyn BufMut, format: WireFormat) {
+        util::varlen_write(buffer, format, self.as_ref());
     }
-    fn wire_read(buffer: &mut dyn Buf) -> Result<Self> {
-        Ok(Bytes::from(util::varlen_read(buffer)?))
+    fn wire_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Self> {
+        util::varlen_read(buffer, context)
     }
-    fn wire_skip(buffer: &mut dyn Buf) -> Result<()> {
-        util::varlen_skip(buffer)?;
+    fn wire_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()> {
+        util::varlen_skip(buffer, context)?;
         Ok(())
     }
 }
 
 impl Serializable for Bytes {
-    fn wire_size(&self) -> usize {
-        util::varlen_wire_size(self.len())
+    fn wire_size(&self, format: WireFormat) -> usize {
+        util::varlen_wire_size(format, self.len())
     }
 }
 
+/// The most memory [`Vec::wire_read`](BaseSerializable::wire_read) reserves before reading items.
+const MAX_RESERVE_BYTES: usize = 64 << 10;
+
 impl<T: Serializable> BaseSerializable for Vec<T> {
-    fn wire_write(&self, buffer: &mut dyn BufMut) {
-        self.len().wire_write(buffer);
+    fn wire_write(&self, buffer: &mut dyn BufMut, format: WireFormat) {
+        self.len().wire_write(buffer, format);
         for item in self {
-            item.wire_write(buffer);
+            item.wire_write(buffer, format);
         }
     }
-    fn wire_read(buffer: &mut dyn Buf) -> Result<Self> {
-        let count = usize::wire_read(buffer)?;
-        let mut vec = Vec::with_capacity(count);
+    fn wire_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Self> {
+        let count = usize::wire_read(buffer, context)?;
+        context.check_len(count)?;
+        // Even within the limit, reserve no more than the input could possibly fill, nor more
+        // than a small block of memory up front; a longer vector grows as its items are read.
+        let reserve = MAX_RESERVE_BYTES / core::mem::size_of::<T>().max(1);
+        let mut vec = Vec::with_capacity(count.min(buffer.remaining()).min(reserve));
         for _ in 0..count {
-            vec.push(T::wire_read(buffer)?);
+            vec.push(T::wire_read(buffer, context)?);
         }
         Ok(vec)
     }
-    fn wire_skip(buffer: &mut dyn Buf) -> Result<()> {
-        let count = usize::wire_read(buffer)?;
-        T::wire_skip_many(buffer, count)
+    fn wire_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()> {
+        let count = usize::wire_read(buffer, context)?;
+        T::wire_skip_many(buffer, context, count)
     }
 }
 
 impl<T: Serializable> Serializable for Vec<T> {
-    fn wire_size(&self) -> usize {
-        let mut sum = usize::WIRE_SIZE;
+    fn wire_size(&self, format: WireFormat) -> usize {
+        let mut sum = self.len().wire_size(format);
         for item in self {
-            sum = sum.strict_add(item.wire_size());
+            sum = sum.strict_add(item.wire_size(format));
         }
         sum
     }
 }
 
+impl<T: Serializable> BaseSerializable for Option<T> {
+    fn wire_write(&self, buffer: &mut dyn BufMut, format: WireFormat) {
+        self.is_some().wire_write(buffer, format);
+        if let Some(value) = self {
+            value.wire_write(buffer, format);
+        }
+    }
+    fn wire_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Self> {
+        if bool::wire_read(buffer, context)? {
+            Ok(Some(T::wire_read(buffer, context)?))
+        } else {
+            Ok(None)
+        }
+    }
+    fn wire_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()> {
+        if bool::wire_read(buffer, context)? {
+            T::wire_skip(buffer, context)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<T: Serializable> Serializable for Option<T> {
+    fn wire_size(&self, format: WireFormat) -> usize {
+        bool::WIRE_SIZE.strict_add(self.as_ref().map_or(0, |value| value.wire_size(format)))
+    }
+}
+
 impl BaseSerializable for () {
-    fn wire_write(&self, _: &mut dyn BufMut) {}
-    fn wire_read(_: &mut dyn Buf) -> Result<Self> {
+    fn wire_write(&self, _: &mut dyn BufMut, _: WireFormat) {}
+    fn wire_read(_: &mut dyn Buf, _: &mut DecodeContext) -> Result<Self> {
         Ok(())
     }
-    fn wire_skip(_: &mut dyn Buf) -> Result<()> {
+    fn wire_skip(_: &mut dyn Buf, _: &mut DecodeContext) -> Result<()> {
         Ok(())
     }
 }
@@ -119,13 +292,13 @@ impl FixedSizeSerializable for () {
 }
 
 impl<const N: usize> BaseSerializable for [u8; N] {
-    fn wire_write(&self, buffer: &mut dyn BufMut) {
+    fn wire_write(&self, buffer: &mut dyn BufMut, _: WireFormat) {
         util::fixed_write::<N>(buffer, self)
     }
-    fn wire_read(buffer: &mut dyn Buf) -> Result<Self> {
+    fn wire_read(buffer: &mut dyn Buf, _: &mut DecodeContext) -> Result<Self> {
         util::fixed_read::<N>(buffer)
     }
-    fn wire_skip(buffer: &mut dyn Buf) -> Result<()> {
+    fn wire_skip(buffer: &mut dyn Buf, _: &mut DecodeContext) -> Result<()> {
         util::fixed_skip::<N>(buffer)
     }
 }
@@ -137,16 +310,17 @@ impl<const N: usize> FixedSizeSerializable for [u8; N] {
 macro_rules! numtype {
     ( $ty:ty, $size:expr, $put:ident, $get:ident ) => {
         impl BaseSerializable for $ty {
-            fn wire_write(&self, buffer: &mut dyn BufMut) {
+            fn wire_write(&self, buffer: &mut dyn BufMut, _: WireFormat) {
                 buffer.$put(*self);
             }
-            fn wire_read(buffer: &mut dyn Buf) -> Result<Self> {
-                if buffer.remaining() < Self::WIRE_SIZE {
-                    return Err(Error);
+            fn wire_read(buffer: &mut dyn Buf, _: &mut DecodeContext) -> Result<Self> {
+                let remaining = buffer.remaining();
+                if remaining < Self::WIRE_SIZE {
+                    return Err(ErrorKind::Truncated { needed: Self::WIRE_SIZE, remaining }.into());
                 }
                 Ok(buffer.$get())
             }
-            fn wire_skip(buffer: &mut dyn Buf) -> Result<()> {
+            fn wire_skip(buffer: &mut dyn Buf, _: &mut DecodeContext) -> Result<()> {
                 const WIRE_SIZE: usize = $size;
                 util::fixed_skip::<WIRE_SIZE>(buffer)
             }
@@ -175,14 +349,14 @@ numtype!(f64, 8, put_f64, get_f64);
 macro_rules! thunktype {
     ( $ty:ty => $repr:ty, $to_repr:path, $from_repr:path ) => {
         impl BaseSerializable for $ty {
-            fn wire_write(&self, buffer: &mut dyn BufMut) {
-                $to_repr(*self).wire_write(buffer);
+            fn wire_write(&self, buffer: &mut dyn BufMut, format: WireFormat) {
+                $to_repr(*self).wire_write(buffer, format);
             }
-            fn wire_read(buffer: &mut dyn Buf) -> Result<Self> {
-                Ok($from_repr(<$repr>::wire_read(buffer)?)?)
+            fn wire_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Self> {
+                $from_repr(<$repr>::wire_read(buffer, context)?)
             }
-            fn wire_skip(buffer: &mut dyn Buf) -> Result<()> {
-                <$repr>::wire_skip(buffer)
+            fn wire_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()> {
+                <$repr>::wire_skip(buffer, context)
             }
         }
         impl FixedSizeSerializable for $ty {
@@ -192,7 +366,35 @@ macro_rules! thunktype {
 }
 
 thunktype!(bool => u8, bool_to_u8, u8_to_bool);
-thunktype!(usize => u32, usize_to_u32, u32_to_usize);
+
+/// Lengths and counts; see [`WireFormat`].  Writing one above [`WireFormat::max_len`] panics,
+/// since no encoding would read back the same; [`util::serialize_as`] checks for this up front.
+impl BaseSerializable for usize {
+    fn wire_write(&self, buffer: &mut dyn BufMut, format: WireFormat) {
+        assert!(*self <= format.max_len(), "length {self} cannot be written in {format:?}");
+        match format {
+            WireFormat::V1 => (*self as u32).wire_write(buffer, format),
+            WireFormat::V2 => util::varint_write(buffer, *self as u64),
+        }
+    }
+    fn wire_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Self> {
+        let value = match context.format {
+            WireFormat::V1 => u64::from(u32::wire_read(buffer, context)?),
+            WireFormat::V2 => util::varint_read(buffer)?,
+        };
+        Ok(value.try_into()?)
+    }
+    fn wire_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()> {
+        Self::wire_read(buffer, context)?;
+        Ok(())
+    }
+}
+
+impl Serializable for usize {
+    fn wire_size(&self, format: WireFormat) -> usize {
+        util::len_wire_size(format, *self)
+    }
+}
 
 fn bool_to_u8(value: bool) -> u8 {
     if value {
@@ -206,14 +408,6 @@ fn u8_to_bool(value: u8) -> Result<bool> {
     match value {
         0 => Ok(false),
         1 => Ok(true),
-        _ => Err(Error),
+        _ => Err(ErrorKind::Invalid { type_name: "bool" }.into()),
     }
 }
-
-fn usize_to_u32(value: usize) -> u32 {
-    value.try_into().unwrap()
-}
-
-fn u32_to_usize(value: u32) -> Result<usize> {
-    Ok(value.try_into()?)
-}
@@ -6,25 +6,26 @@ use super::{
     dandelion_wire,
     util,
     BaseSerializable,
-    Error,
+    ErrorKind,
     FixedSizeSerializable,
     Printable,
     Result,
     Serializable,
     Typed,
+    WireFormat,
     UUID,
 };
 
 pub trait Signable: Typed + Serializable {
     fn signer(&self) -> PublicKey;
 
-    fn seal(&self, key: &PrivateKey) -> Signed {
+    fn seal(&self, key: &PrivateKey) -> Result<Signed> {
         let signer = key.public_key();
         assert_eq!(signer, self.signer());
-        let payload = Bytes::from(util::serialize(self));
+        let payload = Bytes::from(util::serialize(self)?);
         let prepared = prepare_payload(Self::TYPE_UUID, signer, payload.as_ref());
         let signature = key.sign(prepared.as_ref());
-        Signed { signer, payload, signature }
+        Ok(Signed { signer, payload, signature })
     }
 
     fn unseal(signed: &Signed) -> Result<Self> {
@@ -35,7 +36,7 @@ pub trait Signable: Typed + Serializable {
         if value.signer() == signer {
             Ok(value)
         } else {
-            Err(Error)
+            Err(ErrorKind::SignerMismatch.into())
         }
     }
 }
@@ -43,13 +44,13 @@ pub trait Signable: Typed + Serializable {
 fn prepare_payload(type_uuid: UUID, signer: PublicKey, payload: &[u8]) -> Bytes {
     let cap = payload.len().strict_add(UUID::WIRE_SIZE).strict_add(PublicKey::WIRE_SIZE);
     let mut buf = BytesMut::with_capacity(cap);
-    type_uuid.wire_write(&mut buf);
-    signer.wire_write(&mut buf);
+    type_uuid.wire_write(&mut buf, WireFormat::V1);
+    signer.wire_write(&mut buf, WireFormat::V1);
     buf.extend_from_slice(payload);
     Bytes::from(buf)
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serializable)]
 pub struct Signed {
     pub signer: PublicKey,
     pub payload: Bytes,
@@ -62,6 +63,5 @@ impl Printable for Signed {
     }
 }
 
-impl_serializable_for_struct!(Signed { signer: PublicKey, payload: Bytes, signature: Signature });
 impl_debug_for_printable!(Signed);
 impl_display_for_printable!(Signed);
@@ -1,71 +1,181 @@
 use super::bytes::{Buf, BufMut, Bytes, BytesMut};
-use super::{BaseSerializable, Error, FixedSizeSerializable, Result, Serializable};
+use super::{
+    BaseSerializable,
+    DecodeContext,
+    ErrorKind,
+    FixedSizeSerializable,
+    Result,
+    Serializable,
+    WireFormat,
+};
 
-pub fn serialize(value: &impl Serializable) -> BytesMut {
-    let mut buffer = BytesMut::with_capacity(value.wire_size());
-    value.wire_write(&mut buffer);
-    buffer
+/// Serializes `value` in [`WireFormat::V1`], as signed and encrypted payloads always are.
+pub fn serialize(value: &impl Serializable) -> Result<BytesMut> {
+    serialize_as(value, WireFormat::V1)
 }
 
-pub fn deserialize<T: Serializable>(mut buffer: Bytes) -> Result<T> {
-    let value = T::wire_read(&mut buffer)?;
+/// Fails with [`ErrorKind::TooLarge`] if `value` holds a length `format` cannot represent.
+pub fn serialize_as(value: &impl Serializable, format: WireFormat) -> Result<BytesMut> {
+    let mut buffer = BytesMut::with_capacity(check_wire_size(format, value)?);
+    value.wire_write(&mut buffer, format);
+    Ok(buffer)
+}
+
+/// The wire size of `value`, or [`ErrorKind::TooLarge`] if it is above
+/// [`WireFormat::max_len`].  Every length and count inside a value is at most its total size, so
+/// a value that passes can be written without panicking.
+pub fn check_wire_size(format: WireFormat, value: &impl Serializable) -> Result<usize> {
+    let len = value.wire_size(format);
+    if len > format.max_len() {
+        return Err(ErrorKind::TooLarge { len, max: format.max_len() }.into());
+    }
+    Ok(len)
+}
+
+/// Deserializes a value written by [`serialize`], within the default
+/// [`DecodeLimits`](crate::DecodeLimits).
+pub fn deserialize<T: Serializable>(buffer: Bytes) -> Result<T> {
+    deserialize_as(buffer, WireFormat::V1)
+}
+
+pub fn deserialize_as<T: Serializable>(buffer: Bytes, format: WireFormat) -> Result<T> {
+    deserialize_with(buffer, &mut DecodeContext::new(format))
+}
+
+/// Fails unless `buffer` holds exactly one `T`.
+pub fn deserialize_with<T: Serializable>(
+    mut buffer: Bytes,
+    context: &mut DecodeContext,
+) -> Result<T> {
+    let value = T::wire_read(&mut buffer, context)?;
     if !buffer.is_empty() {
-        return Err(Error);
+        return Err(ErrorKind::TrailingBytes { remaining: buffer.len() }.into());
     }
     Ok(value)
 }
 
-pub fn nested_wire_size(inner: &impl Serializable) -> usize {
-    varlen_wire_size(inner.wire_size())
+pub fn nested_wire_size(format: WireFormat, inner: &impl Serializable) -> usize {
+    varlen_wire_size(format, inner.wire_size(format))
 }
 
-pub fn nested_write(buffer: &mut dyn BufMut, inner: &impl Serializable) {
-    let payload = serialize(inner);
-    varlen_write(buffer, payload.as_ref());
+pub fn nested_write(buffer: &mut dyn BufMut, format: WireFormat, inner: &impl Serializable) {
+    inner.wire_size(format).wire_write(buffer, format);
+    inner.wire_write(buffer, format);
 }
 
-pub fn nested_read<T: Serializable>(buffer: &mut dyn Buf) -> Result<T> {
-    let payload = varlen_read(buffer)?;
-    deserialize::<T>(Bytes::from(payload))
+/// Reads a value framed by [`nested_write`], one level deeper in `context`.
+pub fn nested_read<T: Serializable>(
+    buffer: &mut dyn Buf,
+    context: &mut DecodeContext,
+) -> Result<T> {
+    let payload = varlen_read(buffer, context)?;
+    context.nested(|context| deserialize_with::<T>(payload, context))
 }
 
-pub const fn varlen_wire_size(len: usize) -> usize {
-    usize::WIRE_SIZE.strict_add(len)
+/// The wire size of a length prefix for `len` bytes, plus the bytes themselves.
+pub const fn varlen_wire_size(format: WireFormat, len: usize) -> usize {
+    len_wire_size(format, len).strict_add(len)
 }
 
-pub fn varlen_write(buffer: &mut dyn BufMut, value: &[u8]) {
-    value.len().wire_write(buffer);
+/// The wire size of a length or count; see [`WireFormat`].
+pub const fn len_wire_size(format: WireFormat, len: usize) -> usize {
+    match format {
+        WireFormat::V1 => u32::WIRE_SIZE,
+        WireFormat::V2 => varint_wire_size(len as u64),
+    }
+}
+
+/// The largest wire size of a length or count in any [`WireFormat`].
+pub const MAX_LEN_WIRE_SIZE: usize = 8;
+
+pub fn varlen_write(buffer: &mut dyn BufMut, format: WireFormat, value: &[u8]) {
+    value.len().wire_write(buffer, format);
     buffer.put_slice(value);
 }
 
-pub fn varlen_fill(buffer: &mut dyn BufMut, value: u8, count: usize) {
-    count.wire_write(buffer);
+pub fn varlen_fill(buffer: &mut dyn BufMut, format: WireFormat, value: u8, count: usize) {
+    count.wire_write(buffer, format);
     buffer.put_bytes(value, count);
 }
 
-pub fn varlen_read(buffer: &mut dyn Buf) -> Result<BytesMut> {
-    let len = usize::wire_read(buffer)?;
+/// Reads a value written by [`varlen_write`].  When `buffer` is [`Bytes`] the result shares its
+/// storage instead of copying, so decode from `Bytes` wherever possible.
+pub fn varlen_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Bytes> {
+    let len = usize::wire_read(buffer, context)?;
     if buffer.remaining() < len {
-        return Err(Error);
+        return Err(ErrorKind::Truncated { needed: len, remaining: buffer.remaining() }.into());
     }
-    let mut value = BytesMut::zeroed(len);
-    buffer.copy_to_slice(value.as_mut());
-    Ok(value)
+    context.reserve_bytes(len)?;
+    Ok(buffer.copy_to_bytes(len))
 }
 
-pub fn varlen_skip(buffer: &mut dyn Buf) -> Result<usize> {
-    let len = usize::wire_read(buffer)?;
+pub fn varlen_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<usize> {
+    let len = usize::wire_read(buffer, context)?;
     generic_skip(buffer, len)?;
     Ok(len)
 }
 
+/// The largest value a QUIC variable-length integer can hold.
+pub const VARINT_MAX: u64 = (1 << 62) - 1;
+
+pub const fn varint_wire_size(value: u64) -> usize {
+    if value < 1 << 6 {
+        1
+    } else if value < 1 << 14 {
+        2
+    } else if value < 1 << 30 {
+        4
+    } else {
+        8
+    }
+}
+
+/// The size of the QUIC variable-length integer that starts with `first`.
+pub const fn varint_prefix_size(first: u8) -> usize {
+    1 << (first >> 6)
+}
+
+/// Writes `value` as a QUIC variable-length integer in the fewest bytes possible.  Panics above
+/// [`VARINT_MAX`], which cannot be represented.
+pub fn varint_write(buffer: &mut dyn BufMut, value: u64) {
+    assert!(value <= VARINT_MAX, "{value} cannot be written as a varint");
+    match varint_wire_size(value) {
+        1 => buffer.put_u8(value as u8),
+        2 => buffer.put_u16(0x4000 | value as u16),
+        4 => buffer.put_u32(0x8000_0000 | value as u32),
+        _ => buffer.put_u64(0xc000_0000_0000_0000 | value),
+    }
+}
+
+/// Reads a QUIC variable-length integer.  Fails unless it was written in the fewest bytes
+/// possible, so that every value has exactly one encoding.
+pub fn varint_read(buffer: &mut dyn Buf) -> Result<u64> {
+    let Some(&first) = buffer.chunk().first() else {
+        return Err(ErrorKind::Truncated { needed: 1, remaining: 0 }.into());
+    };
+    let len = varint_prefix_size(first);
+    if buffer.remaining() < len {
+        return Err(ErrorKind::Truncated { needed: len, remaining: buffer.remaining() }.into());
+    }
+    let value = match len {
+        1 => u64::from(buffer.get_u8()),
+        2 => u64::from(buffer.get_u16() & 0x3fff),
+        4 => u64::from(buffer.get_u32() & 0x3fff_ffff),
+        _ => buffer.get_u64() & VARINT_MAX,
+    };
+    if varint_wire_size(value) != len {
+        return Err(ErrorKind::Invalid { type_name: "varint" }.into());
+    }
+    Ok(value)
+}
+
 pub fn fixed_write<const N: usize>(buffer: &mut dyn BufMut, value: &[u8; N]) {
     buffer.put_slice(value);
 }
 
 pub fn fixed_read<const N: usize>(buffer: &mut dyn Buf) -> Result<[u8; N]> {
     if buffer.remaining() < N {
-        return Err(Error);
+        return Err(ErrorKind::Truncated { needed: N, remaining: buffer.remaining() }.into());
     }
     let mut value = [0u8; N];
     buffer.copy_to_slice(&mut value);
@@ -78,15 +188,19 @@ pub fn fixed_skip<const N: usize>(buffer: &mut dyn Buf) -> Result<()> {
 
 pub fn generic_skip(buffer: &mut dyn Buf, len: usize) -> Result<()> {
     if buffer.remaining() < len {
-        return Err(Error);
+        return Err(ErrorKind::Truncated { needed: len, remaining: buffer.remaining() }.into());
     }
     buffer.advance(len);
     Ok(())
 }
 
-pub fn generic_skip_many<T: BaseSerializable>(buffer: &mut dyn Buf, count: usize) -> Result<()> {
+pub fn generic_skip_many<T: BaseSerializable>(
+    buffer: &mut dyn Buf,
+    context: &mut DecodeContext,
+    count: usize,
+) -> Result<()> {
     for _ in 0..count {
-        T::wire_skip(buffer)?;
+        T::wire_skip(buffer, context)?;
     }
     Ok(())
 }
@@ -94,3 +208,154 @@ pub fn generic_skip_many<T: BaseSerializable>(buffer: &mut dyn Buf, count: usize
 pub fn eq<const N: usize>(lhs: &[u8; N], rhs: &[u8; N]) -> bool {
     constant_time_eq::constant_time_eq_n::<N>(lhs, rhs)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_boundaries() {
+        let cases: [(u64, &[u8]); 8] = [
+            (0, &[0x00]),
+            (63, &[0x3f]),
+            (64, &[0x40, 0x40]),
+            (16383, &[0x7f, 0xff]),
+            (16384, &[0x80, 0x00, 0x40, 0x00]),
+            ((1 << 30) - 1, &[0xbf, 0xff, 0xff, 0xff]),
+            (1 << 30, &[0xc0, 0, 0, 0, 0x40, 0, 0, 0]),
+            (VARINT_MAX, &[0xff; 8]),
+        ];
+        for (value, encoded) in cases {
+            let mut buffer = BytesMut::new();
+            varint_write(&mut buffer, value);
+            assert_eq!(encoded, buffer.as_ref());
+            assert_eq!(encoded.len(), varint_wire_size(value));
+            assert_eq!(value, varint_read(&mut buffer.freeze()).unwrap());
+        }
+    }
+
+    #[test]
+    fn varint_rejects_bad_encodings() {
+        assert_eq!(
+            ErrorKind::Invalid { type_name: "varint" },
+            varint_read(&mut Bytes::from_static(&[0x40, 0x3f])).unwrap_err().kind()
+        );
+        assert_eq!(
+            ErrorKind::Truncated { needed: 4, remaining: 2 },
+            varint_read(&mut Bytes::from_static(&[0x80, 0x01])).unwrap_err().kind()
+        );
+        assert_eq!(
+            ErrorKind::Truncated { needed: 1, remaining: 0 },
+            varint_read(&mut Bytes::new()).unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn lengths_by_format() {
+        let payload = [7u8; 100];
+        for (format, prefix) in
+            [(WireFormat::V1, &[0, 0, 0, 100][..]), (WireFormat::V2, &[0x40, 100])]
+        {
+            let mut buffer = BytesMut::new();
+            varlen_write(&mut buffer, format, &payload);
+            assert_eq!(prefix, &buffer[..prefix.len()]);
+            assert_eq!(buffer.len(), varlen_wire_size(format, payload.len()));
+            let mut context = DecodeContext::new(format);
+            assert_eq!(payload, varlen_read(&mut buffer.freeze(), &mut context).unwrap().as_ref());
+        }
+    }
+
+    /// Claims a size without holding the data.
+    struct Huge(usize);
+
+    impl BaseSerializable for Huge {
+        fn wire_write(&self, _: &mut dyn BufMut, _: WireFormat) {
+            unreachable!()
+        }
+        fn wire_read(_: &mut dyn Buf, _: &mut DecodeContext) -> Result<Self> {
+            unreachable!()
+        }
+        fn wire_skip(_: &mut dyn Buf, _: &mut DecodeContext) -> Result<()> {
+            unreachable!()
+        }
+    }
+
+    impl Serializable for Huge {
+        fn wire_size(&self, _: WireFormat) -> usize {
+            self.0
+        }
+    }
+
+    #[test]
+    fn oversize_lengths() {
+        let len = u32::MAX as usize + 1;
+        assert_eq!(
+            ErrorKind::TooLarge { len, max: u32::MAX as usize },
+            serialize(&Huge(len)).unwrap_err().kind()
+        );
+        assert_eq!(Ok(len), check_wire_size(WireFormat::V2, &Huge(len)).map_err(|e| e.kind()));
+        assert_eq!(u32::MAX as usize, WireFormat::V1.max_len());
+        assert_eq!(VARINT_MAX as usize, WireFormat::V2.max_len());
+    }
+
+    #[test]
+    fn decode_limits() {
+        use alloc::vec::Vec;
+
+        use crate::DecodeLimits;
+
+        // A count near 2^32 fails on the limit, and one within it on the input, before allocating.
+        let huge = Bytes::from_static(&[0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(
+            ErrorKind::TooLarge { len: u32::MAX as usize, max: 1 << 20 },
+            deserialize::<Vec<u64>>(huge).unwrap_err().kind()
+        );
+        let short = Bytes::from_static(&[0, 0x0f, 0, 0]);
+        assert!(matches!(
+            deserialize::<Vec<u64>>(short).unwrap_err().kind(),
+            ErrorKind::Truncated { .. }
+        ));
+
+        let limits = DecodeLimits { max_total_bytes: 10, max_collection_len: 2, max_depth: 1 };
+        let mut context = DecodeContext::with_limits(WireFormat::V1, limits);
+        let raw = serialize(&Bytes::from_static(&[1; 6])).unwrap().freeze();
+        assert!(deserialize_with::<Bytes>(raw.clone(), &mut context).is_ok());
+        assert_eq!(6, context.total_bytes());
+        assert_eq!(
+            ErrorKind::TooLarge { len: 6, max: 4 },
+            deserialize_with::<Bytes>(raw, &mut context).unwrap_err().kind()
+        );
+
+        let mut context = DecodeContext::with_limits(WireFormat::V1, limits);
+        let three = serialize(&Vec::from([1u8, 2, 3])).unwrap().freeze();
+        assert_eq!(
+            ErrorKind::TooLarge { len: 3, max: 2 },
+            deserialize_with::<Vec<u8>>(three, &mut context).unwrap_err().kind()
+        );
+
+        let mut nested = BytesMut::new();
+        nested_write(&mut nested, WireFormat::V1, &5u8);
+        let nested = nested.freeze();
+        let mut context = DecodeContext::with_limits(WireFormat::V1, limits);
+        assert_eq!(5, nested_read::<u8>(&mut nested.clone(), &mut context).unwrap());
+        assert_eq!(0, context.depth());
+        let too_deep = context.nested(|context| nested_read::<u8>(&mut nested.clone(), context));
+        assert_eq!(ErrorKind::TooDeep { max: 1 }, too_deep.unwrap_err().kind());
+    }
+
+    #[test]
+    fn varlen_read_shares_bytes() {
+        let mut nested = BytesMut::new();
+        nested_write(&mut nested, WireFormat::V2, &Bytes::from_static(b"payload"));
+        let raw = nested.freeze();
+        let mut context = DecodeContext::new(WireFormat::V2);
+        let payload = nested_read::<Bytes>(&mut raw.clone(), &mut context).unwrap();
+        assert_eq!(b"payload", payload.as_ref());
+        assert!(raw.as_ptr_range().contains(&payload.as_ptr()));
+
+        let mut slice = raw.as_ref();
+        let copied = nested_read::<Bytes>(&mut slice, &mut context).unwrap();
+        assert_eq!(payload, copied);
+        assert!(!raw.as_ptr_range().contains(&copied.as_ptr()));
+    }
+}
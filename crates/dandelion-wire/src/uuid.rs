@@ -1,15 +1,15 @@
 use super::bytes::Buf;
-use super::{dandelion_wire, BaseSerializable, Error, Result};
+use super::{dandelion_wire, BaseSerializable, DecodeContext, ErrorKind, Result};
 
 public_bytes!(UUID, raw RawUUID, size UUID_SIZE = 16);
 
 impl UUID {
-    pub fn wire_verify(self, buffer: &mut dyn Buf) -> Result<()> {
-        let actual = Self::wire_read(buffer)?;
+    pub fn wire_verify(self, buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()> {
+        let actual = Self::wire_read(buffer, context)?;
         if actual == self {
             Ok(())
         } else {
-            Err(Error)
+            Err(ErrorKind::UnexpectedType.into())
         }
     }
 }
@@ -1,15 +1,27 @@
 use dandelion_wire::cryptography::sig::PublicKey;
-use dandelion_wire::{Signable, Typed, UUID};
+use dandelion_wire::{Result, Signable, Signed, Typed, UUID};
 
-use super::{Claims, Entity, Instant};
+use super::{Claims, Clock, Entity, Identity, Instant};
 
-#[derive(Clone)]
+#[derive(Clone, Serializable, Printable)]
 pub struct Attestation {
     pub attestor: Entity,
     pub time: Instant,
     pub claims: Claims,
 }
 
+impl Attestation {
+    /// An attestation made now, according to `clock`.
+    pub fn new(attestor: Entity, claims: Claims, clock: &dyn Clock) -> Self {
+        Self { attestor, time: clock.now(), claims }
+    }
+
+    /// Makes an attestation by `identity` at the current time and signs it.
+    pub fn issue(identity: &Identity, claims: Claims, clock: &dyn Clock) -> Result<Signed> {
+        Self::new(identity.entity(), claims, clock).seal(identity.signing_key())
+    }
+}
+
 impl Typed for Attestation {
     const TYPE_UUID: UUID = crate::constants::ATTESTATION_TYPE;
 }
@@ -20,7 +32,22 @@ impl Signable for Attestation {
     }
 }
 
-impl_serializable_for_struct!(Attestation { attestor: Entity, time: Instant, claims: Claims });
-impl_printable_for_struct!(Attestation { attestor, time, claims });
-impl_debug_for_printable!(Attestation);
-impl_display_for_printable!(Attestation);
+#[cfg(test)]
+mod tests {
+    use dandelion_wire::cryptography::sig;
+    use dandelion_wire::SecretBytes;
+
+    use super::*;
+    use crate::{Duration, EntityType, ManualClock};
+
+    #[test]
+    fn attestation_issue_uses_clock() {
+        let identity = Identity::new(EntityType::Node, sig::PrivateKey::from_exposed([1; 32]));
+        let now = Instant::ZERO + Duration::from_days(20000);
+        let clock = ManualClock::new(now);
+        let signed = Attestation::issue(&identity, Claims(Default::default()), &clock).unwrap();
+        let attestation = Attestation::unseal(&signed).unwrap();
+        assert_eq!(now, attestation.time);
+        assert_eq!(identity.entity(), attestation.attestor);
+    }
+}
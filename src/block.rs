@@ -1,25 +1,149 @@
+use alloc::fmt;
+
+use dandelion_wire::bytes::{Buf, BufMut, Bytes};
 use dandelion_wire::cryptography::digest::Digest;
-use dandelion_wire::PublicBytes;
+use dandelion_wire::{
+    printable,
+    util,
+    BaseSerializable,
+    DecodeContext,
+    ErrorKind,
+    Printable,
+    Result,
+    Serializable,
+    WireFormat,
+};
+
+pub const BLOCK_SIZE: usize = 1 << 20;
 
-public_bytes!(Block, raw RawBlock, size BLOCK_SIZE = 1 << 20);
+/// Up to [`BLOCK_SIZE`] bytes of content-addressed data.
+#[derive(Clone, Default, Hash, PartialEq, Eq)]
+pub struct Block(Bytes);
 
 impl Block {
+    /// Fails if `data` is longer than [`BLOCK_SIZE`].
+    pub fn new(data: Bytes) -> Result<Self> {
+        if data.len() > BLOCK_SIZE {
+            return Err(ErrorKind::TooLarge { len: data.len(), max: BLOCK_SIZE }.into());
+        }
+        Ok(Self(data))
+    }
+
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        if data.len() > BLOCK_SIZE {
+            return Err(ErrorKind::TooLarge { len: data.len(), max: BLOCK_SIZE }.into());
+        }
+        Ok(Self(Bytes::copy_from_slice(data)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+
     pub fn compute_id(&self) -> BlockID {
         BlockID::compute_from(self)
     }
 }
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+impl BaseSerializable for Block {
+    fn wire_write(&self, buffer: &mut dyn BufMut, format: WireFormat) {
+        util::varlen_write(buffer, format, self.as_slice());
+    }
+    fn wire_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Self> {
+        let len = read_len(buffer, context)?;
+        context.reserve_bytes(len)?;
+        Ok(Self(buffer.copy_to_bytes(len)))
+    }
+    fn wire_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()> {
+        let len = read_len(buffer, context)?;
+        buffer.advance(len);
+        Ok(())
+    }
+}
+
+/// Reads a block's length, checking it against both [`BLOCK_SIZE`] and the input.
+fn read_len(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<usize> {
+    let len = usize::wire_read(buffer, context)?;
+    if len > BLOCK_SIZE {
+        return Err(ErrorKind::TooLarge { len, max: BLOCK_SIZE }.into());
+    }
+    if buffer.remaining() < len {
+        return Err(ErrorKind::Truncated { needed: len, remaining: buffer.remaining() }.into());
+    }
+    Ok(len)
+}
+
+impl Serializable for Block {
+    fn wire_size(&self, format: WireFormat) -> usize {
+        util::varlen_wire_size(format, self.len())
+    }
+}
+
+impl Printable for Block {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        printable::print_public_bytes(writer, self.as_slice())
+    }
+}
+
+impl_debug_for_printable!(Block);
+impl_display_for_printable!(Block);
+
+#[cfg(test)]
+pub(crate) fn filled_block(fill: u8) -> Block {
+    Block::new(Bytes::from(alloc::vec![fill; BLOCK_SIZE])).unwrap()
+}
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable, Printable)]
+#[wire(fixed)]
 #[repr(transparent)]
 pub struct BlockID(pub Digest);
 
 impl BlockID {
+    /// Hashes a 32-bit big-endian length followed by the contents, so that blocks differing only
+    /// in trailing zeros get distinct IDs.
     pub fn compute_from(block: &Block) -> Self {
-        Self(Digest::compute(crate::constants::BLOCK_TYPE, block.as_slice()))
+        let prefix = (block.len() as u32).to_be_bytes();
+        let data = (&prefix[..]).chain(block.as_slice());
+        Self(Digest::compute(crate::constants::BLOCK_TYPE, data))
     }
 }
 
-impl_serializable_for_wrapper!(BlockID, wraps Digest, fixed size);
-impl_printable_for_wrapper!(BlockID);
-impl_debug_for_printable!(BlockID);
-impl_display_for_printable!(BlockID);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_id_covers_length() {
+        let short = Block::from_slice(b"abc").unwrap();
+        let padded = Block::from_slice(b"abc\0").unwrap();
+        assert_ne!(short.compute_id(), padded.compute_id());
+        assert_eq!(short.compute_id(), Block::from_slice(b"abc").unwrap().compute_id());
+        assert_eq!(7, util::serialize(&short).unwrap().len());
+        assert_eq!(4, util::serialize_as(&short, WireFormat::V2).unwrap().len());
+    }
+
+    #[test]
+    fn block_rejects_oversize() {
+        assert!(Block::new(Bytes::from(alloc::vec![0u8; BLOCK_SIZE + 1])).is_err());
+        let mut buffer = util::serialize(&(BLOCK_SIZE + 1)).unwrap();
+        buffer.put_bytes(0, BLOCK_SIZE + 1);
+        assert!(util::deserialize::<Block>(buffer.into()).is_err());
+        assert_eq!(BLOCK_SIZE, filled_block(1).len());
+    }
+}
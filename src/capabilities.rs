@@ -0,0 +1,218 @@
+use alloc::fmt;
+use alloc::vec::Vec;
+
+use dandelion_wire::{ErrorKind, Printable, Result, Serializable, WireFormat};
+
+use super::Message;
+
+/// The newest protocol version this crate speaks.  Version 2 switched lengths to
+/// [`WireFormat::V2`].
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// The oldest protocol version this crate still speaks.
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// The largest serialized [`crate::Messages`] batch accepted by default: room for a few
+/// full-sized blocks.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 4 << 20;
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable, Printable)]
+#[repr(u16)]
+pub enum CipherSuite {
+    #[wire(name = "Noise_XX_25519_ChaChaPoly_BLAKE2s")]
+    NoiseXX = 0,
+    #[wire(name = "Noise_IK_25519_ChaChaPoly_BLAKE2s")]
+    NoiseIK = 1,
+}
+
+/// What one side of a link supports, sent as [`crate::Message::Hello`] when the link starts.
+/// Lists are in order of preference.  Hellos are always in [`WireFormat::V1`]; switch to
+/// [`Capabilities::wire_format`] of the negotiated capabilities once both have been exchanged.
+///
+/// Cipher suites are kept as raw [`CipherSuite`] codes, like message codes, so that a peer
+/// offering suites this crate does not know can still be understood.
+#[derive(Clone, Hash, PartialEq, Eq, Serializable)]
+pub struct Capabilities {
+    pub min_version: u16,
+    pub max_version: u16,
+    pub message_codes: Vec<u16>,
+    pub cipher_suites: Vec<u16>,
+    /// The largest serialized [`crate::Messages`] batch this side accepts.
+    pub max_batch_size: usize,
+}
+
+impl Capabilities {
+    /// Everything this crate supports.  Add the codes of any registered
+    /// [`crate::Extension`]s to `message_codes`.
+    pub fn local() -> Self {
+        Self {
+            min_version: MIN_PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION,
+            message_codes: Vec::from(Message::ALL_CODES),
+            cipher_suites: Vec::from([CipherSuite::NoiseXX.code(), CipherSuite::NoiseIK.code()]),
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+        }
+    }
+
+    /// The capabilities both sides share, in this side's order of preference, with the version
+    /// range narrowed to the newest common version.  Fails if there is no common version or
+    /// known cipher suite.
+    ///
+    /// Only the order of the lists depends on which side negotiates, so both sides agree on
+    /// everything but their preferred [`Capabilities::cipher_suite`].  A protocol that needs one
+    /// suite must have a fixed side, such as the initiator, pick it.
+    pub fn negotiate(&self, peer: &Self) -> Result<Self> {
+        let version = self.max_version.min(peer.max_version);
+        if version < self.min_version.max(peer.min_version) {
+            return Err(ErrorKind::Incompatible.into());
+        }
+        let cipher_suites: Vec<_> = self
+            .cipher_suites
+            .iter()
+            .copied()
+            .filter(|code| {
+                CipherSuite::from_code(*code).is_ok() && peer.cipher_suites.contains(code)
+            })
+            .collect();
+        if cipher_suites.is_empty() {
+            return Err(ErrorKind::Incompatible.into());
+        }
+        Ok(Self {
+            min_version: version,
+            max_version: version,
+            message_codes: self
+                .message_codes
+                .iter()
+                .copied()
+                .filter(|code| peer.message_codes.contains(code))
+                .collect(),
+            cipher_suites,
+            max_batch_size: self.max_batch_size.min(peer.max_batch_size),
+        })
+    }
+
+    pub fn supports(&self, code: u16) -> bool {
+        self.message_codes.contains(&code)
+    }
+
+    /// The most preferred cipher suite this crate knows, if any.
+    pub fn cipher_suite(&self) -> Option<CipherSuite> {
+        self.cipher_suites.iter().find_map(|code| CipherSuite::from_code(*code).ok())
+    }
+
+    /// The format of the newest version in range, which after [`Capabilities::negotiate`] is the
+    /// agreed one.
+    pub fn wire_format(&self) -> WireFormat {
+        WireFormat::for_version(self.max_version)
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::local()
+    }
+}
+
+impl Printable for Capabilities {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        write!(writer, "{{min_version: {}, max_version: {}, ", self.min_version, self.max_version)?;
+        writer.write_str("message_codes: [")?;
+        for (index, code) in self.message_codes.iter().enumerate() {
+            let separator = if index == 0 { " " } else { ", " };
+            write!(writer, "{separator}{code:#06x}")?;
+        }
+        writer.write_str(if self.message_codes.is_empty() { "]" } else { " ]" })?;
+        writer.write_str(", cipher_suites: [")?;
+        for (index, code) in self.cipher_suites.iter().enumerate() {
+            writer.write_str(if index == 0 { " " } else { ", " })?;
+            match CipherSuite::from_code(*code) {
+                Ok(suite) => suite.print(writer)?,
+                Err(_) => write!(writer, "{code:#06x}")?,
+            }
+        }
+        writer.write_str(if self.cipher_suites.is_empty() { "]" } else { " ]" })?;
+        write!(writer, ", max_batch_size: {}}}", self.max_batch_size)
+    }
+}
+
+impl_debug_for_printable!(Capabilities);
+impl_display_for_printable!(Capabilities);
+
+#[cfg(test)]
+mod tests {
+    use dandelion_wire::util;
+
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        let ours = Capabilities::local();
+        let theirs = Capabilities {
+            min_version: 1,
+            max_version: 3,
+            message_codes: Vec::from([0x0000, 0x0002, 0x8000]),
+            cipher_suites: Vec::from([CipherSuite::NoiseIK.code()]),
+            max_batch_size: 1 << 16,
+        };
+        let agreed = ours.negotiate(&theirs).unwrap();
+        assert_eq!(agreed, theirs.negotiate(&ours).unwrap());
+        assert_eq!((2, 2), (agreed.min_version, agreed.max_version));
+        assert_eq!(WireFormat::V2, agreed.wire_format());
+        assert_eq!(Vec::from([0x0000, 0x0002]), agreed.message_codes);
+        assert!(agreed.supports(Message::CODE_ENVELOPE) && !agreed.supports(0x8000));
+        assert_eq!(Vec::from([CipherSuite::NoiseIK.code()]), agreed.cipher_suites);
+        assert_eq!(Some(CipherSuite::NoiseIK), agreed.cipher_suite());
+        assert_eq!(1 << 16, agreed.max_batch_size);
+
+        let older = Capabilities { max_version: 1, ..theirs.clone() };
+        assert_eq!(WireFormat::V1, ours.negotiate(&older).unwrap().wire_format());
+        let newer = Capabilities { min_version: 3, ..theirs.clone() };
+        assert_eq!(Err(ErrorKind::Incompatible), ours.negotiate(&newer).map_err(|e| e.kind()));
+        let no_suites = Capabilities { cipher_suites: Vec::new(), ..theirs };
+        assert_eq!(Err(ErrorKind::Incompatible), ours.negotiate(&no_suites).map_err(|e| e.kind()));
+    }
+
+    #[test]
+    fn negotiate_suites() {
+        let ours = Capabilities::local();
+        let (xx, ik) = (CipherSuite::NoiseXX.code(), CipherSuite::NoiseIK.code());
+        let theirs = Capabilities { cipher_suites: Vec::from([0x7777, ik, xx]), ..ours.clone() };
+        let agreed = ours.negotiate(&theirs).unwrap();
+        assert_eq!(Vec::from([xx, ik]), agreed.cipher_suites);
+        assert_eq!(Some(CipherSuite::NoiseXX), agreed.cipher_suite());
+        // Everything else agrees, but each side keeps its own preference.
+        let reverse = theirs.negotiate(&ours).unwrap();
+        assert_eq!(Vec::from([ik, xx]), reverse.cipher_suites);
+        assert_eq!(Capabilities { cipher_suites: Vec::from([xx, ik]), ..reverse }, agreed);
+
+        let unknown = Capabilities { cipher_suites: Vec::from([0x7777]), ..ours.clone() };
+        assert_eq!(None, unknown.cipher_suite());
+        assert!(unknown.negotiate(&unknown).is_err());
+        let hello = Message::Hello(unknown.clone());
+        let Message::Hello(decoded) =
+            util::deserialize::<Message>(util::serialize(&hello).unwrap().freeze()).unwrap()
+        else {
+            panic!("expected Hello");
+        };
+        assert_eq!(unknown, decoded);
+        assert!(hello.as_printed().contains("cipher_suites: [ 0x7777 ]"));
+    }
+
+    #[test]
+    fn hello_round_trip() {
+        let hello = Message::Hello(Capabilities::local());
+        let raw = util::serialize(&hello).unwrap().freeze();
+        assert_eq!(raw.len(), hello.wire_size(WireFormat::V1));
+        let Message::Hello(decoded) = util::deserialize::<Message>(raw).unwrap() else {
+            panic!("expected Hello");
+        };
+        assert_eq!(Capabilities::local(), decoded);
+        assert_eq!(
+            "Hello({min_version: 1, max_version: 2, message_codes: [ 0x0000, 0x0001, 0x0002, \
+             0x0003, 0x0004, 0x0100, 0x0101, 0x0102 ], cipher_suites: [ \
+             Noise_XX_25519_ChaChaPoly_BLAKE2s, Noise_IK_25519_ChaChaPoly_BLAKE2s ], \
+             max_batch_size: 4194304})",
+            hello.as_printed()
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use dandelion_wire::bytes::{Buf, BufMut, BytesMut};
+use dandelion_wire::cryptography::ecdh;
+use dandelion_wire::cryptography::noise::{self, Transport, MAX_MESSAGE_LEN, MAX_PAYLOAD_LEN};
+use dandelion_wire::{
+    util,
+    BaseSerializable,
+    DecodeContext,
+    DecodeLimits,
+    Error,
+    ErrorKind,
+    FixedSizeSerializable,
+    Result,
+    WireFormat,
+};
+
+use super::capabilities::DEFAULT_MAX_BATCH_SIZE;
+use super::{Messages, PaddingPolicy, PublicIdentity};
+
+/// Carries [`Messages`] batches over a completed Noise handshake.
+///
+/// Each batch is padded according to the channel's [`PaddingPolicy`], written with a length
+/// prefix in the channel's [`WireFormat`], and split across as many Noise transport messages as
+/// needed, each framed by a `u16` length.  Frames must be read in the order they were written, but
+/// may arrive split across any number of reads; each frame is decrypted once it has arrived in
+/// full and kept until the rest of its batch follows.  Any error leaves the channel unusable.
+/// Incoming batches may be up to [`DEFAULT_MAX_BATCH_SIZE`] and are decoded within
+/// [`Messages::DECODE_LIMITS`] unless changed with [`Channel::set_max_batch_size`] or
+/// [`Channel::set_limits`].
+pub struct Channel {
+    transport: Transport,
+    padding: PaddingPolicy,
+    format: WireFormat,
+    max_batch_size: usize,
+    limits: DecodeLimits,
+    /// Received frames that have not been decrypted yet.
+    received: BytesMut,
+    /// The decrypted part of a batch that has not arrived in full.
+    plaintext: BytesMut,
+    failed: Option<Error>,
+}
+
+impl Channel {
+    /// Uses the default [`PaddingPolicy`].
+    pub fn new(transport: Transport) -> Self {
+        Self::with_padding(transport, PaddingPolicy::default())
+    }
+
+    pub fn with_padding(transport: Transport, padding: PaddingPolicy) -> Self {
+        Self {
+            transport,
+            padding,
+            format: WireFormat::V1,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            limits: Messages::DECODE_LIMITS,
+            received: BytesMut::new(),
+            plaintext: BytesMut::new(),
+            failed: None,
+        }
+    }
+
+    pub fn from_handshake(handshake: noise::Handshake) -> Result<Self> {
+        Ok(Self::new(handshake.into_transport()?))
+    }
+
+    pub fn padding(&self) -> &PaddingPolicy {
+        &self.padding
+    }
+
+    /// Starts out as [`WireFormat::V1`], which every peer understands.
+    pub fn format(&self) -> WireFormat {
+        self.format
+    }
+
+    /// Switches both directions to `format`, e.g. once a [`Message::Hello`](crate::Message::Hello)
+    /// exchange has agreed on a protocol version.  Both sides must switch at the same point.
+    pub fn set_format(&mut self, format: WireFormat) {
+        self.format = format;
+    }
+
+    pub fn max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    /// Rejects incoming batches larger than `max_batch_size`, e.g. the negotiated
+    /// [`Capabilities::max_batch_size`](crate::Capabilities::max_batch_size), and decodes them
+    /// within [`Messages::decode_limits`] of it.
+    pub fn set_max_batch_size(&mut self, max_batch_size: usize) {
+        self.max_batch_size = max_batch_size;
+        self.limits = Messages::decode_limits(max_batch_size);
+    }
+
+    pub fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    /// Overrides the limits set by [`Channel::set_max_batch_size`].
+    pub fn set_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    pub fn remote_static_key(&self) -> ecdh::PublicKey {
+        self.transport.remote_static_key()
+    }
+
+    /// Whether the peer proved possession of `identity`'s key-agreement key.
+    pub fn authenticates(&self, identity: &PublicIdentity) -> bool {
+        self.remote_static_key() == identity.agreement_key
+    }
+
+    pub fn write_messages(&mut self, messages: &Messages, buffer: &mut dyn BufMut) -> Result<()> {
+        let mut messages = messages.clone();
+        self.padding.pad(&mut messages, self.format)?;
+        let len = util::check_wire_size(self.format, &messages)?;
+        let mut plaintext = BytesMut::with_capacity(util::varlen_wire_size(self.format, len));
+        util::nested_write(&mut plaintext, self.format, &messages);
+        for chunk in plaintext.chunks(MAX_PAYLOAD_LEN) {
+            let frame = self.transport.encrypt(chunk)?;
+            (frame.len() as u16).wire_write(buffer, self.format);
+            buffer.put_slice(&frame);
+        }
+        Ok(())
+    }
+
+    /// Takes in all of `buffer` and returns the next batch, or `None` if it has not arrived in
+    /// full yet.  Anything after that batch is kept for the next call, which may pass an empty
+    /// buffer.
+    pub fn read_messages(&mut self, buffer: &mut dyn Buf) -> Result<Option<Messages>> {
+        while buffer.has_remaining() {
+            let chunk = buffer.chunk();
+            let len = chunk.len();
+            self.received.extend_from_slice(chunk);
+            buffer.advance(len);
+        }
+        if let Some(err) = self.failed {
+            return Err(err);
+        }
+        let result = self.next_batch();
+        if let Err(err) = result {
+            self.failed = Some(err);
+        }
+        result
+    }
+
+    fn next_batch(&mut self) -> Result<Option<Messages>> {
+        let mut context = DecodeContext::with_limits(self.format, self.limits);
+        loop {
+            if !self.plaintext.is_empty() {
+                let len = usize::wire_read(&mut self.plaintext.as_ref(), &mut context)?;
+                // Check before decrypting any more frames, rather than once they have all arrived.
+                if len > self.max_batch_size {
+                    return Err(ErrorKind::TooLarge { len, max: self.max_batch_size }.into());
+                }
+                let total = util::varlen_wire_size(self.format, len);
+                if self.plaintext.len() > total {
+                    let remaining = self.plaintext.len() - total;
+                    return Err(ErrorKind::TrailingBytes { remaining }.into());
+                }
+                if self.plaintext.len() == total {
+                    break;
+                }
+            }
+            if !self.decrypt_frame()? {
+                return Ok(None);
+            }
+        }
+        let mut batch = self.plaintext.split().freeze();
+        util::nested_read(&mut batch, &mut context).map(Some)
+    }
+
+    /// Decrypts the next received frame into the plaintext, or returns `false` if it has not
+    /// arrived in full.
+    fn decrypt_frame(&mut self) -> Result<bool> {
+        let header = u16::WIRE_SIZE;
+        let Some(mut prefix) = self.received.get(..header) else {
+            return Ok(false);
+        };
+        let len = usize::from(u16::wire_read(&mut prefix, &mut DecodeContext::new(self.format))?);
+        debug_assert!(len <= MAX_MESSAGE_LEN);
+        if self.received.len() < header.strict_add(len) {
+            return Ok(false);
+        }
+        let frame = self.received.split_to(header.strict_add(len));
+        self.plaintext.extend_from_slice(&self.transport.decrypt(&frame[header..])?);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use dandelion_wire::cryptography::digest::Digest;
+    use dandelion_wire::cryptography::noise::Handshake;
+    use dandelion_wire::rand_core::OsRng;
+    use dandelion_wire::{Printable, PublicBytes};
+
+    use super::*;
+    use crate::{BlockID, EntityType, Identity, Message};
+
+    fn connect(alice: &Identity, bob: &Identity) -> (Channel, Channel) {
+        let prologue = b"dandelion test";
+        let bob_key = bob.public().agreement_key;
+        let mut initiator =
+            Handshake::initiate_ik(alice.agreement_key().clone(), bob_key, prologue, &mut OsRng);
+        let mut responder =
+            Handshake::respond_ik(bob.agreement_key().clone(), prologue, &mut OsRng);
+        let first = initiator.write_message(&[]).unwrap();
+        responder.read_message(&first).unwrap();
+        let second = responder.write_message(&[]).unwrap();
+        initiator.read_message(&second).unwrap();
+        (Channel::from_handshake(initiator).unwrap(), Channel::from_handshake(responder).unwrap())
+    }
+
+    #[test]
+    fn channel_round_trip() {
+        let alice = Identity::generate(EntityType::Node, &mut OsRng);
+        let bob = Identity::generate(EntityType::Node, &mut OsRng);
+        let (mut to_bob, mut from_alice) = connect(&alice, &bob);
+        assert!(to_bob.authenticates(&bob.public()));
+        assert!(from_alice.authenticates(&alice.public()));
+
+        let small = Messages(Vec::from([
+            Message::Padding(3),
+            Message::DontWantBlock(BlockID(Digest::from_exact([7; 32]))),
+        ]));
+        let large = Messages(Vec::from([Message::Padding(3 * MAX_PAYLOAD_LEN)]));
+        let mut buffer = BytesMut::new();
+        to_bob.write_messages(&small, &mut buffer).unwrap();
+        to_bob.write_messages(&large, &mut buffer).unwrap();
+
+        let mut buffer = buffer.freeze();
+        let decoded = from_alice.read_messages(&mut buffer).unwrap().unwrap();
+        assert_eq!(small.as_printed(), decoded.0[..2].as_printed());
+        let decoded = from_alice.read_messages(&mut buffer).unwrap().unwrap();
+        assert_eq!(large.as_printed(), decoded.0[..1].as_printed());
+        assert!(buffer.is_empty());
+
+        to_bob.set_format(WireFormat::V2);
+        from_alice.set_format(WireFormat::V2);
+        let mut buffer = BytesMut::new();
+        to_bob.write_messages(&large, &mut buffer).unwrap();
+        let decoded = from_alice.read_messages(&mut buffer.freeze()).unwrap().unwrap();
+        assert_eq!(large.as_printed(), decoded.0[..1].as_printed());
+    }
+
+    #[test]
+    fn channel_resumes_partial_batches() {
+        let alice = Identity::generate(EntityType::Node, &mut OsRng);
+        let bob = Identity::generate(EntityType::Node, &mut OsRng);
+        let (mut to_bob, mut from_alice) = connect(&alice, &bob);
+
+        let large = Messages(Vec::from([Message::Padding(3 * MAX_PAYLOAD_LEN)]));
+        let small = Messages(Vec::from([Message::Padding(3)]));
+        let mut buffer = BytesMut::new();
+        to_bob.write_messages(&large, &mut buffer).unwrap();
+        to_bob.write_messages(&small, &mut buffer).unwrap();
+
+        let mut decoded = Vec::new();
+        for chunk in buffer.chunks(MAX_PAYLOAD_LEN / 3) {
+            if let Some(messages) = from_alice.read_messages(&mut &chunk[..]).unwrap() {
+                decoded.push(messages);
+            }
+        }
+        assert_eq!(1, decoded.len());
+        decoded.extend(from_alice.read_messages(&mut &[][..]).unwrap());
+        assert!(from_alice.read_messages(&mut &[][..]).unwrap().is_none());
+        assert_eq!(large.as_printed(), decoded[0].0[..1].as_printed());
+        assert_eq!(small.as_printed(), decoded[1].0[..1].as_printed());
+    }
+
+    #[test]
+    fn channel_rejects_tampering() {
+        let alice = Identity::generate(EntityType::Endpoint, &mut OsRng);
+        let bob = Identity::generate(EntityType::Node, &mut OsRng);
+        let (mut to_bob, mut from_alice) = connect(&alice, &bob);
+
+        let mut buffer = BytesMut::new();
+        to_bob.write_messages(&Messages(Vec::from([Message::Padding(1)])), &mut buffer).unwrap();
+        let last = buffer.len() - 1;
+        buffer[last] ^= 1;
+        assert!(from_alice.read_messages(&mut buffer.freeze()).is_err());
+        assert!(from_alice.read_messages(&mut &[][..]).is_err());
+    }
+
+    #[test]
+    fn channel_pads_batches() {
+        let alice = Identity::generate(EntityType::Endpoint, &mut OsRng);
+        let bob = Identity::generate(EntityType::Node, &mut OsRng);
+        let (mut to_bob, _) = connect(&alice, &bob);
+
+        let mut one = BytesMut::new();
+        to_bob.write_messages(&Messages(Vec::from([Message::Padding(1)])), &mut one).unwrap();
+        let mut two = BytesMut::new();
+        let messages = Messages(Vec::from([Message::Padding(1), Message::Padding(500)]));
+        to_bob.write_messages(&messages, &mut two).unwrap();
+        assert_eq!(one.len(), two.len());
+    }
+
+    #[test]
+    fn channel_enforces_limits() {
+        let alice = Identity::generate(EntityType::Endpoint, &mut OsRng);
+        let bob = Identity::generate(EntityType::Node, &mut OsRng);
+        let (mut to_bob, mut from_alice) = connect(&alice, &bob);
+        from_alice.set_max_batch_size(1000);
+        assert_eq!(Messages::decode_limits(1000), *from_alice.limits());
+
+        let mut buffer = BytesMut::new();
+        let large = Messages(Vec::from([Message::Padding(3 * MAX_PAYLOAD_LEN)]));
+        to_bob.write_messages(&large, &mut buffer).unwrap();
+        let err = from_alice.read_messages(&mut buffer.freeze()).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::TooLarge { max: 1000, .. }));
+    }
+}
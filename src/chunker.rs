@@ -0,0 +1,170 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use dandelion_wire::{ErrorKind, Result};
+
+use super::block::BLOCK_SIZE;
+use super::rng::SplitMix64;
+
+/// How [`Chunker`] picks chunk boundaries.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Chunking {
+    /// Every chunk is exactly `size` bytes, except possibly the last.
+    Fixed { size: usize },
+    /// FastCDC content-defined chunking with normalized chunk sizes, so that an insertion only
+    /// disturbs the chunks around it.  `avg` must be a power of two.
+    ContentDefined { min: usize, avg: usize, max: usize },
+}
+
+impl Chunking {
+    pub const FIXED: Self = Self::Fixed { size: BLOCK_SIZE };
+    pub const CONTENT_DEFINED: Self =
+        Self::ContentDefined { min: BLOCK_SIZE / 4, avg: BLOCK_SIZE / 2, max: BLOCK_SIZE };
+
+    fn check(self) -> Result<()> {
+        let ok = match self {
+            Self::Fixed { size } => size > 0 && size <= BLOCK_SIZE,
+            Self::ContentDefined { min, avg, max } => {
+                avg.is_power_of_two()
+                    && avg.ilog2() > 1
+                    && min <= avg
+                    && avg <= max
+                    && max <= BLOCK_SIZE
+                    && min > 0
+            },
+        };
+        match ok {
+            true => Ok(()),
+            false => Err(ErrorKind::Invalid { type_name: "Chunking" }.into()),
+        }
+    }
+}
+
+impl Default for Chunking {
+    fn default() -> Self {
+        Self::CONTENT_DEFINED
+    }
+}
+
+/// Splits data into ranges that each fit in one [`Block`](crate::Block).
+#[derive(Clone, Copy, Debug)]
+pub struct Chunker {
+    chunking: Chunking,
+}
+
+impl Chunker {
+    pub fn new(chunking: Chunking) -> Result<Self> {
+        chunking.check()?;
+        Ok(Self { chunking })
+    }
+
+    pub fn chunking(&self) -> Chunking {
+        self.chunking
+    }
+
+    pub fn split(&self, data: &[u8]) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        while start < data.len() {
+            let len = self.next_len(&data[start..]);
+            let end = start.strict_add(len);
+            ranges.push(start..end);
+            start = end;
+        }
+        ranges
+    }
+
+    fn next_len(&self, data: &[u8]) -> usize {
+        match self.chunking {
+            Chunking::Fixed { size } => data.len().min(size),
+            Chunking::ContentDefined { min, avg, max } => fastcdc_cut(data, min, avg, max),
+        }
+    }
+}
+
+fn fastcdc_cut(data: &[u8], min: usize, avg: usize, max: usize) -> usize {
+    if data.len() <= min {
+        return data.len();
+    }
+    let end = data.len().min(max);
+    let normal = end.min(avg);
+    let bits = avg.ilog2();
+    // A stricter mask below the average size and a looser one above it pull chunk sizes
+    // towards the average.
+    let mask_small = top_bits(bits + 1);
+    let mask_large = top_bits(bits - 1);
+    let mut hash = 0u64;
+    for (index, &byte) in data.iter().enumerate().take(end).skip(min) {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        let mask = if index < normal { mask_small } else { mask_large };
+        if hash & mask == 0 {
+            return index + 1;
+        }
+    }
+    end
+}
+
+const fn top_bits(count: u32) -> u64 {
+    !(u64::MAX >> count)
+}
+
+/// Random values for the gear hash, generated with SplitMix64 so that chunk boundaries are stable
+/// across builds and implementations.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut rng = SplitMix64::new(0x6461_6e64_656c_696f);
+    let mut index = 0;
+    while index < 256 {
+        (table[index], rng) = rng.step();
+        index += 1;
+    }
+    table
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::sample;
+
+    fn check_cover(ranges: &[Range<usize>], len: usize, max: usize) {
+        let mut next = 0;
+        for range in ranges {
+            assert_eq!(next, range.start);
+            assert!(range.end > range.start && range.len() <= max);
+            next = range.end;
+        }
+        assert_eq!(len, next);
+    }
+
+    #[test]
+    fn chunker_fixed() {
+        let chunker = Chunker::new(Chunking::Fixed { size: 100 }).unwrap();
+        let ranges = chunker.split(&sample(250));
+        assert_eq!(Vec::from([0..100, 100..200, 200..250]), ranges);
+        assert!(chunker.split(&[]).is_empty());
+        assert!(Chunker::new(Chunking::Fixed { size: BLOCK_SIZE + 1 }).is_err());
+    }
+
+    #[test]
+    fn chunker_content_defined_resynchronizes() {
+        let chunking = Chunking::ContentDefined { min: 1024, avg: 4096, max: 16384 };
+        let chunker = Chunker::new(chunking).unwrap();
+        let data = sample(200_000);
+        let ranges = chunker.split(&data);
+        check_cover(&ranges, data.len(), 16384);
+        assert!(ranges.len() > 20 && ranges.len() < 100);
+
+        // Inserting bytes near the front shifts, but does not change, the later chunks.
+        let mut edited = Vec::from(&b"inserted"[..]);
+        edited.extend_from_slice(&data);
+        let edited_ranges = chunker.split(&edited);
+        check_cover(&edited_ranges, edited.len(), 16384);
+        let chunks = |data: &[u8], ranges: &[Range<usize>]| -> Vec<Vec<u8>> {
+            ranges.iter().map(|range| Vec::from(&data[range.clone()])).collect()
+        };
+        let original = chunks(&data, &ranges);
+        let shifted = chunks(&edited, &edited_ranges);
+        let shared = shifted.iter().filter(|chunk| original.contains(chunk)).count();
+        assert!(shared + 3 >= original.len());
+    }
+}
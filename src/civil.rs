@@ -0,0 +1,457 @@
+//! Civil (calendar) time in UTC, using the proleptic Gregorian calendar.
+//!
+//! An [`Instant`] normally ignores leap seconds, like Unix time: every day is exactly 86400
+//! seconds long, so truncating an instant with [`Instant::truncate`] lands on a UTC day or hour
+//! boundary.  Where leap seconds matter, pass a [`LeapSeconds`] table; the instant is then taken
+//! to count every elapsed SI second, and the table decides when `23:59:60` occurs.
+
+use alloc::fmt;
+
+use dandelion_wire::{ErrorKind, Printable, Result};
+
+use super::time::{NANOS_PER_MICRO, NANOS_PER_MILLI, NANOS_PER_SECOND, SECONDS_PER_DAY};
+use super::{Duration, Instant};
+
+/// Dates are limited to this many years either side of year 0, so that calendar arithmetic can
+/// never overflow.  Every finite [`Instant`] lies well within the range.
+pub const MAX_YEAR: i64 = 1_000_000;
+pub const MIN_YEAR: i64 = -MAX_YEAR;
+
+pub const fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub const fn days_in_month(year: i64, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Self; 7] = [
+        Self::Monday,
+        Self::Tuesday,
+        Self::Wednesday,
+        Self::Thursday,
+        Self::Friday,
+        Self::Saturday,
+        Self::Sunday,
+    ];
+}
+
+/// A date that exists, which [`Date::new`] checks.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    year: i64,
+    month: u8,
+    day: u8,
+}
+
+impl Date {
+    pub const EPOCH: Self = Self { year: 1970, month: 1, day: 1 };
+
+    /// Fails unless the date exists.
+    pub fn new(year: i64, month: u8, day: u8) -> Result<Self> {
+        let date = Self { year, month, day };
+        date.check()?;
+        Ok(date)
+    }
+
+    fn check(&self) -> Result<()> {
+        let ok = (MIN_YEAR..=MAX_YEAR).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month);
+        match ok {
+            true => Ok(()),
+            false => Err(ErrorKind::Invalid { type_name: "Date" }.into()),
+        }
+    }
+
+    pub const fn year(&self) -> i64 {
+        self.year
+    }
+
+    pub const fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub const fn day(&self) -> u8 {
+        self.day
+    }
+
+    /// The date `days` days after 1970-01-01.  After Howard Hinnant's `civil_from_days`.
+    pub fn from_days(days: i64) -> Result<Self> {
+        // Bounds the arithmetic below; the year is checked precisely at the end.
+        if days.unsigned_abs() > (MAX_YEAR as u64 + 1) * 366 {
+            return Err(ErrorKind::OutOfRange.into());
+        }
+        let days = days + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        Self::new(year, month as u8, day as u8)
+    }
+
+    /// Days since 1970-01-01.  After Howard Hinnant's `days_from_civil`.
+    pub fn to_days(&self) -> i64 {
+        let (month, day) = (self.month as i64, self.day as i64);
+        let year = if month <= 2 { self.year - 1 } else { self.year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146097 + day_of_era - 719468
+    }
+
+    /// The UTC date containing `instant`, or `None` if it is infinite.
+    pub fn containing(instant: Instant) -> Option<Self> {
+        DateTime::from_instant(instant).map(|datetime| datetime.date)
+    }
+
+    /// Midnight UTC at the start of this date.  Saturates outside the range of [`Instant`].
+    pub fn start(&self) -> Instant {
+        Instant::ZERO + Duration::from_days(self.to_days())
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        // 1970-01-01 was a Thursday.
+        Weekday::ALL[(self.to_days() + 3).rem_euclid(7) as usize]
+    }
+
+    pub fn add_days(&self, days: i64) -> Result<Self> {
+        Self::from_days(self.to_days().checked_add(days).ok_or(ErrorKind::OutOfRange)?)
+    }
+
+    /// Moves by whole months, clamping the day to the length of the resulting month, so that
+    /// one month after January 31st is the last day of February.
+    pub fn add_months(&self, months: i64) -> Result<Self> {
+        let index = (self.month as i64 - 1).checked_add(months).ok_or(ErrorKind::OutOfRange)?;
+        let year = self.year.checked_add(index.div_euclid(12)).ok_or(ErrorKind::OutOfRange)?;
+        let month = index.rem_euclid(12) as u8 + 1;
+        Self::new(year, month, self.day.min(days_in_month(year, month)))
+    }
+
+    pub fn add_years(&self, years: i64) -> Result<Self> {
+        self.add_months(years.checked_mul(12).ok_or(ErrorKind::OutOfRange)?)
+    }
+}
+
+/// A UTC date and time of day.  `second` is 60 only during a leap second.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    date: Date,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    nanosecond: u32,
+}
+
+impl DateTime {
+    /// Fails unless the fields are in range.  A leap second is accepted at the end of any day;
+    /// whether one actually occurred is only checked by [`DateTime::to_instant_with`].
+    pub fn new(date: Date, hour: u8, minute: u8, second: u8, nanosecond: u32) -> Result<Self> {
+        let ok = hour < 24
+            && minute < 60
+            && (second < 60 || (second == 60 && hour == 23 && minute == 59))
+            && (nanosecond as i64) < NANOS_PER_SECOND;
+        match ok {
+            true => Ok(Self { date, hour, minute, second, nanosecond }),
+            false => Err(ErrorKind::Invalid { type_name: "DateTime" }.into()),
+        }
+    }
+
+    pub const fn date(&self) -> Date {
+        self.date
+    }
+
+    pub const fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub const fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub const fn second(&self) -> u8 {
+        self.second
+    }
+
+    pub const fn nanosecond(&self) -> u32 {
+        self.nanosecond
+    }
+
+    /// Breaks down an instant, ignoring leap seconds.  Returns `None` if it is infinite.
+    pub fn from_instant(instant: Instant) -> Option<Self> {
+        Self::from_instant_with(instant, &LeapSeconds::NONE)
+    }
+
+    /// Breaks down an instant that counts leap seconds.
+    pub fn from_instant_with(instant: Instant, leap_seconds: &LeapSeconds) -> Option<Self> {
+        let since_epoch = instant.since_epoch();
+        if since_epoch.is_infinity() {
+            return None;
+        }
+        let nanoseconds = since_epoch.as_nanoseconds();
+        let seconds = nanoseconds.div_euclid(NANOS_PER_SECOND);
+        let nanosecond = nanoseconds.rem_euclid(NANOS_PER_SECOND) as u32;
+        // Leap second k (counting from 0) starts when the UTC clock would read midnight on the
+        // following day, plus the k earlier leap seconds.
+        let mut leap = false;
+        let mut earlier = 0;
+        for (index, date) in leap_seconds.0.iter().enumerate() {
+            let start = (date.to_days() + 1) * SECONDS_PER_DAY + index as i64;
+            if seconds < start {
+                break;
+            }
+            leap = seconds == start;
+            earlier += 1;
+        }
+        let mut datetime = Self::from_unix_seconds(seconds - earlier, nanosecond)?;
+        if leap {
+            datetime.second = 60;
+        }
+        Some(datetime)
+    }
+
+    /// The instant of this time, ignoring leap seconds; `23:59:60` is treated as the following
+    /// midnight.  Saturates outside the range of [`Instant`].
+    pub fn to_instant(&self) -> Instant {
+        self.instant_from_unix_seconds(self.unix_seconds())
+    }
+
+    /// The instant of this time, counting leap seconds.  Fails for `23:59:60` on a day without
+    /// a leap second.
+    pub fn to_instant_with(&self, leap_seconds: &LeapSeconds) -> Result<Instant> {
+        let unix_seconds = self.unix_seconds();
+        let earlier = leap_seconds
+            .0
+            .iter()
+            .take_while(|date| (date.to_days() + 1) * SECONDS_PER_DAY <= unix_seconds)
+            .count();
+        let seconds = unix_seconds + earlier as i64;
+        if self.second == 60 {
+            // `unix_seconds` is the following midnight, which already counted this leap second.
+            if !leap_seconds.0.contains(&self.date) {
+                return Err(ErrorKind::Invalid { type_name: "DateTime" }.into());
+            }
+            return Ok(self.instant_from_unix_seconds(seconds - 1));
+        }
+        Ok(self.instant_from_unix_seconds(seconds))
+    }
+
+    fn from_unix_seconds(seconds: i64, nanosecond: u32) -> Option<Self> {
+        let date = Date::from_days(seconds.div_euclid(SECONDS_PER_DAY)).ok()?;
+        let seconds = seconds.rem_euclid(SECONDS_PER_DAY);
+        let (hour, minute, second) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        Some(Self {
+            date,
+            hour: hour as u8,
+            minute: minute as u8,
+            second: second as u8,
+            nanosecond,
+        })
+    }
+
+    fn unix_seconds(&self) -> i64 {
+        self.date.to_days() * SECONDS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+    }
+
+    fn instant_from_unix_seconds(&self, seconds: i64) -> Instant {
+        let nanoseconds = seconds as i128 * NANOS_PER_SECOND as i128 + self.nanosecond as i128;
+        let nanoseconds = nanoseconds.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        Instant::from_since_epoch(Duration::from_nanoseconds(nanoseconds))
+    }
+}
+
+/// The dates at the end of which a leap second was inserted, in increasing order.
+#[derive(Clone, Copy, Debug)]
+pub struct LeapSeconds<'a>(pub &'a [Date]);
+
+const fn date(year: i64, month: u8, day: u8) -> Date {
+    Date { year, month, day }
+}
+
+impl LeapSeconds<'static> {
+    pub const NONE: Self = Self(&[]);
+
+    /// Every leap second announced by the IERS as of 2024.  None has ever been removed.
+    pub const IERS: Self = Self(&[
+        date(1972, 6, 30),
+        date(1972, 12, 31),
+        date(1973, 12, 31),
+        date(1974, 12, 31),
+        date(1975, 12, 31),
+        date(1976, 12, 31),
+        date(1977, 12, 31),
+        date(1978, 12, 31),
+        date(1979, 12, 31),
+        date(1981, 6, 30),
+        date(1982, 6, 30),
+        date(1983, 6, 30),
+        date(1985, 6, 30),
+        date(1987, 12, 31),
+        date(1989, 12, 31),
+        date(1990, 12, 31),
+        date(1992, 6, 30),
+        date(1993, 6, 30),
+        date(1994, 6, 30),
+        date(1995, 12, 31),
+        date(1997, 6, 30),
+        date(1998, 12, 31),
+        date(2005, 12, 31),
+        date(2008, 12, 31),
+        date(2012, 6, 30),
+        date(2015, 6, 30),
+        date(2016, 12, 31),
+    ]);
+}
+
+impl Printable for Date {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        write!(writer, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Prints in RFC 3339 form with a `Z` offset.  The fraction is omitted, or has 3, 6 or 9 digits,
+/// whichever is shortest and exact.
+impl Printable for DateTime {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        self.date.print(writer)?;
+        write!(writer, "T{:02}:{:02}:{:02}", self.hour, self.minute, self.second)?;
+        let ns = self.nanosecond;
+        if ns % NANOS_PER_MILLI as u32 == 0 {
+            if ns != 0 {
+                write!(writer, ".{:03}", ns / NANOS_PER_MILLI as u32)?;
+            }
+        } else if ns % NANOS_PER_MICRO as u32 == 0 {
+            write!(writer, ".{:06}", ns / NANOS_PER_MICRO as u32)?;
+        } else {
+            write!(writer, ".{:09}", ns)?;
+        }
+        writer.write_char('Z')
+    }
+}
+
+impl_debug_for_printable!(Date);
+impl_display_for_printable!(Date);
+impl_debug_for_printable!(DateTime);
+impl_display_for_printable!(DateTime);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> Instant {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(0, Date::EPOCH.to_days());
+        assert_eq!(Weekday::Thursday, Date::EPOCH.weekday());
+        let leap_day = Date::new(2000, 2, 29).unwrap();
+        assert_eq!(11016, leap_day.to_days());
+        assert_eq!(Weekday::Tuesday, leap_day.weekday());
+        assert!(Date::new(1900, 2, 29).is_err());
+        assert!(Date::new(2024, 4, 31).is_err());
+        for days in (-800_000..800_000).step_by(997) {
+            assert_eq!(days, Date::from_days(days).unwrap().to_days());
+        }
+        assert_eq!(
+            Date::new(-1, 12, 31).unwrap(),
+            Date::new(0, 1, 1).unwrap().add_days(-1).unwrap()
+        );
+        assert!(Date::from_days(i64::MAX).is_err());
+    }
+
+    #[test]
+    fn civil_month_arithmetic() {
+        let date = Date::new(2024, 1, 31).unwrap();
+        assert_eq!(Date::new(2024, 2, 29).unwrap(), date.add_months(1).unwrap());
+        assert_eq!(Date::new(2023, 11, 30).unwrap(), date.add_months(-2).unwrap());
+        assert_eq!(Date::new(2025, 2, 28).unwrap(), date.add_months(13).unwrap());
+        assert_eq!(Date::new(2025, 2, 28).unwrap(), leap_day().add_years(1).unwrap());
+        assert!(date.add_years(MAX_YEAR).is_err());
+    }
+
+    fn leap_day() -> Date {
+        Date::new(2024, 2, 29).unwrap()
+    }
+
+    #[test]
+    fn civil_breakdown_and_truncation() {
+        let instant = at("2024-08-20T12:34:56.789Z");
+        let datetime = DateTime::from_instant(instant).unwrap();
+        assert_eq!(Date::new(2024, 8, 20).unwrap(), datetime.date());
+        assert_eq!(
+            (12, 34, 56, 789_000_000),
+            (datetime.hour(), datetime.minute(), datetime.second(), datetime.nanosecond())
+        );
+        assert_eq!(instant, datetime.to_instant());
+        assert_eq!("2024-08-20T12:34:56.789Z", datetime.as_printed());
+        assert!(DateTime::from_instant(Instant::MAX).is_none());
+
+        // "Valid until the end of the day".
+        let end_of_day = datetime.date().add_days(1).unwrap().start();
+        assert_eq!(at("2024-08-21T00:00:00Z"), end_of_day);
+        assert_eq!(at("2024-08-20T00:00:00Z"), instant.truncate(Duration::from_days(1)));
+        assert_eq!(at("2024-08-20T12:00:00Z"), instant.truncate(Duration::from_hours(1)));
+        let before_epoch = at("1969-12-31T23:59:59Z");
+        assert_eq!(at("1969-12-31T00:00:00Z"), before_epoch.truncate(Duration::from_days(1)));
+    }
+
+    #[test]
+    fn civil_leap_seconds() {
+        let leaps = LeapSeconds::IERS;
+        // There were 10 leap seconds before 1972, by definition, which an Instant does not see,
+        // and 27 since.
+        let posix = at("2017-01-01T00:00:00Z");
+        let counted = posix + Duration::from_seconds(27);
+        let datetime = DateTime::from_instant_with(counted, &leaps).unwrap();
+        assert_eq!("2017-01-01T00:00:00Z", datetime.as_printed());
+        assert_eq!(Ok(counted), datetime.to_instant_with(&leaps));
+
+        let leap = counted - Duration::from_milliseconds(500);
+        let datetime = DateTime::from_instant_with(leap, &leaps).unwrap();
+        assert_eq!("2016-12-31T23:59:60.500Z", datetime.as_printed());
+        assert_eq!(Ok(leap), datetime.to_instant_with(&leaps));
+        let before = counted - Duration::from_seconds(2);
+        let datetime = DateTime::from_instant_with(before, &leaps).unwrap();
+        assert_eq!("2016-12-31T23:59:59Z", datetime.as_printed());
+        assert_eq!(Ok(before), datetime.to_instant_with(&leaps));
+
+        let bogus = DateTime::new(Date::new(2016, 12, 30).unwrap(), 23, 59, 60, 0).unwrap();
+        assert!(bogus.to_instant_with(&leaps).is_err());
+        assert_eq!(at("2016-12-31T00:00:00Z"), bogus.to_instant());
+        assert!(DateTime::new(Date::EPOCH, 12, 0, 60, 0).is_err());
+        assert!(DateTime::new(Date::EPOCH, 0, 0, 0, NANOS_PER_SECOND as u32).is_err());
+
+        let early = at("1972-01-01T00:00:00Z");
+        assert_eq!(Some(early), DateTime::from_instant_with(early, &leaps).map(|d| d.to_instant()));
+    }
+}
@@ -1,17 +1,162 @@
+use alloc::fmt;
 use alloc::vec::Vec;
 
-#[derive(Clone)]
+use dandelion_wire::bytes::Bytes;
+use dandelion_wire::cryptography::ecdh;
+use dandelion_wire::cryptography::sig::PublicKey;
+use dandelion_wire::{Printable, Serializable};
+
+use super::Entity;
+
+#[derive(Clone, Hash, PartialEq, Eq, Serializable, Printable)]
 pub struct Claims(pub Vec<Claim>);
 
-impl_serializable_for_wrapper!(Claims, wraps Vec<Claim>);
-impl_printable_for_wrapper!(Claims);
-impl_debug_for_printable!(Claims);
-impl_display_for_printable!(Claims);
+#[derive(Clone, Hash, PartialEq, Eq, Serializable, Printable)]
+pub enum Claim {
+    #[wire(code = 0x0001)]
+    ZoneMember(ZoneMember),
+    #[wire(code = 0x0002)]
+    NodeServes(NodeServes),
+    #[wire(code = 0x0003)]
+    KeySupersedes(KeySupersedes),
+    #[wire(code = 0x0004)]
+    ReachableVia(ReachableVia),
+    #[wire(code = 0x0005)]
+    AgreementKey(AgreementKey),
+}
+
+/// Entity `member` is a member of the zone identified by `zone`.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable, Printable)]
+#[wire(fixed)]
+pub struct ZoneMember {
+    pub member: Entity,
+    pub zone: Entity,
+}
+
+/// Node `node` serves as a relay for the endpoint `endpoint`.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable, Printable)]
+#[wire(fixed)]
+pub struct NodeServes {
+    pub node: Entity,
+    pub endpoint: Entity,
+}
+
+/// Signing key `key` replaces the older signing key `superseded`.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable, Printable)]
+#[wire(fixed)]
+pub struct KeySupersedes {
+    pub key: PublicKey,
+    pub superseded: PublicKey,
+}
+
+/// Entity `entity` can be reached at the transport address `address`.
+#[derive(Clone, Hash, PartialEq, Eq, Serializable, Printable)]
+pub struct ReachableVia {
+    pub entity: Entity,
+    pub address: TransportAddress,
+}
+
+/// Entity `entity` publishes `key` as its X25519 key-agreement key, instead of the key derived
+/// from its Ed25519 signing key.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable, Printable)]
+#[wire(fixed)]
+pub struct AgreementKey {
+    pub entity: Entity,
+    pub key: ecdh::PublicKey,
+}
+
+/// Opaque, transport-specific address.  Interpretation is left to the transport agent.
+#[derive(Clone, Hash, PartialEq, Eq, Serializable)]
+#[repr(transparent)]
+pub struct TransportAddress(pub Bytes);
+
+impl Printable for TransportAddress {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        dandelion_wire::printable::print_public_bytes(writer, self.0.as_ref())
+    }
+}
+
+impl_debug_for_printable!(TransportAddress);
+impl_display_for_printable!(TransportAddress);
+
+#[cfg(test)]
+mod tests {
+    use dandelion_wire::{util, BaseSerializable, DecodeContext, PublicBytes, WireFormat};
+
+    use super::*;
+    use crate::EntityType;
+
+    fn entity(entity_type: EntityType, fill: u8) -> Entity {
+        Entity { entity_type, public_key: PublicKey::from_exact([fill; 32]) }
+    }
+
+    fn sample_claims() -> Claims {
+        Claims(Vec::from([
+            Claim::ZoneMember(ZoneMember {
+                member: entity(EntityType::Node, 1),
+                zone: entity(EntityType::Zone, 2),
+            }),
+            Claim::NodeServes(NodeServes {
+                node: entity(EntityType::Node, 3),
+                endpoint: entity(EntityType::Endpoint, 4),
+            }),
+            Claim::KeySupersedes(KeySupersedes {
+                key: PublicKey::from_exact([5; 32]),
+                superseded: PublicKey::from_exact([6; 32]),
+            }),
+            Claim::ReachableVia(ReachableVia {
+                entity: entity(EntityType::Endpoint, 7),
+                address: TransportAddress(Bytes::from_static(b"tcp:192.0.2.1:4242")),
+            }),
+            Claim::AgreementKey(AgreementKey {
+                entity: entity(EntityType::Endpoint, 8),
+                key: ecdh::PublicKey::from_exact([9; 32]),
+            }),
+        ]))
+    }
+
+    #[test]
+    fn claim_round_trip() {
+        let claims = sample_claims();
+        for claim in claims.0.iter() {
+            let buffer = util::serialize(claim).unwrap();
+            assert_eq!(claim.wire_size(WireFormat::V1), buffer.len());
+            let decoded = util::deserialize::<Claim>(buffer.into()).unwrap();
+            assert_eq!(claim, &decoded);
+        }
+
+        let buffer = util::serialize(&claims).unwrap();
+        assert_eq!(claims.wire_size(WireFormat::V1), buffer.len());
+        let decoded = util::deserialize::<Claims>(buffer.into()).unwrap();
+        assert_eq!(claims, decoded);
+    }
+
+    #[test]
+    fn claim_skip() {
+        let claims = sample_claims();
+        let mut buffer = Bytes::from(util::serialize(&claims).unwrap());
+        Claims::wire_skip(&mut buffer, &mut DecodeContext::default()).unwrap();
+        assert!(buffer.is_empty());
+    }
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
-pub struct Claim;
+    #[test]
+    fn claim_unknown_code() {
+        let mut buffer = util::serialize(&sample_claims().0[0]).unwrap();
+        buffer[0..2].copy_from_slice(&0xffffu16.to_be_bytes());
+        assert!(util::deserialize::<Claim>(buffer.into()).is_err());
+    }
 
-impl_serializable_todo!(Claim);
-impl_printable_todo!(Claim);
-impl_debug_for_printable!(Claim);
-impl_display_for_printable!(Claim);
+    #[test]
+    fn claim_print() {
+        let claim = Claim::KeySupersedes(KeySupersedes {
+            key: PublicKey::from_exact([0; 32]),
+            superseded: PublicKey::from_exact([0xff; 32]),
+        });
+        assert_eq!(
+            "KeySupersedes({key: \"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=\", superseded: \"//////////////////////////////////////////8=\"})",
+            claim.as_printed()
+        );
+        assert_eq!(Claim::NAME_KEY_SUPERSEDES, claim.name());
+        assert_eq!(Claim::CODE_KEY_SUPERSEDES, claim.code());
+    }
+}
@@ -0,0 +1,118 @@
+use core::sync::atomic::{AtomicI64, Ordering};
+
+use super::{Duration, Instant};
+
+/// A source of the current [`Instant`].  Code that needs the time should take a `&dyn Clock`
+/// rather than reading the system clock, so that tests and simulations can control it.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The operating system's wall clock.  It can jump backwards or forwards when the system time is
+/// adjusted; use [`MonotonicClock`] where that matters.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        std::time::SystemTime::now().into()
+    }
+}
+
+/// Reads the wall clock once, when created, and then advances with the operating system's
+/// monotonic clock, so that it never goes backwards.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+pub struct MonotonicClock {
+    start: Instant,
+    anchor: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self::starting_at(SystemClock.now())
+    }
+
+    pub fn starting_at(start: Instant) -> Self {
+        Self { start, anchor: std::time::Instant::now() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for MonotonicClock {
+    fn now(&self) -> Instant {
+        self.start + Duration::from(self.anchor.elapsed())
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    nanoseconds: AtomicI64,
+}
+
+impl ManualClock {
+    pub fn new(now: Instant) -> Self {
+        Self { nanoseconds: AtomicI64::new(now.since_epoch().as_nanoseconds()) }
+    }
+
+    pub fn set(&self, now: Instant) {
+        self.nanoseconds.store(now.since_epoch().as_nanoseconds(), Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.set(self.now() + duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        let nanoseconds = self.nanoseconds.load(Ordering::Relaxed);
+        Instant::from_since_epoch(Duration::from_nanoseconds(nanoseconds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new(Instant::ZERO + Duration::from_days(1));
+        assert_eq!(Instant::ZERO + Duration::from_days(1), clock.now());
+        clock.advance(Duration::from_seconds(5));
+        assert_eq!(Instant::ZERO + Duration::from_days(1) + Duration::from_seconds(5), clock.now());
+        clock.set(Instant::ZERO);
+        assert_eq!(Instant::ZERO, (&clock as &dyn Clock).now());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn system_clocks_use_unix_epoch() {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let unix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let now = SystemClock.now().since_epoch().as_seconds();
+        assert!((now - unix).abs() <= 1);
+        // 2020-01-01T00:00:00Z.
+        assert!(now > 1_577_836_800);
+
+        let clock = MonotonicClock::starting_at(Instant::ZERO);
+        let first = clock.now();
+        assert!(first >= Instant::ZERO && first < Instant::ZERO + Duration::from_seconds(1));
+        assert!(clock.now() >= first);
+
+        let before = UNIX_EPOCH - std::time::Duration::from_secs(3);
+        assert_eq!(Instant::ZERO - Duration::from_seconds(3), Instant::from(before));
+    }
+}
@@ -5,6 +5,7 @@ use dandelion_wire::UUID;
 // ROOT_UUID.sha1("messages"): 829c1fcd-89de-5737-9eb4-8ed9d70bf6c8
 // ROOT_UUID.sha1("attestation"): ce158e1f-8b3c-533a-a69b-26b9374f9f83
 // ROOT_UUID.sha1("block"): c217ee93-3d40-5c24-8b98-7b212bb15d2c
+// ROOT_UUID.sha1("manifest"): f311fb4d-6902-57ce-bc31-ff7841166b33
 
 pub const ENVELOPE_TYPE: UUID = UUID([
     0xd1, 0xb7, 0xd8, 0x7a, 0x56, 0x7e, 0x55, 0x53, 0xa1, 0x18, 0xfc, 0xc7, 0xa2, 0xc6, 0xa1, 0xce,
@@ -21,3 +22,7 @@ pub const ATTESTATION_TYPE: UUID = UUID([
 pub const BLOCK_TYPE: UUID = UUID([
     0xc2, 0x17, 0xee, 0x93, 0x3d, 0x40, 0x5c, 0x24, 0x8b, 0x98, 0x7b, 0x21, 0x2b, 0xb1, 0x5d, 0x2c,
 ]);
+
+pub const MANIFEST_TYPE: UUID = UUID([
+    0xf3, 0x11, 0xfb, 0x4d, 0x69, 0x02, 0x57, 0xce, 0xbc, 0x31, 0xff, 0x78, 0x41, 0x16, 0x6b, 0x33,
+]);
@@ -1,26 +1,228 @@
-use dandelion_wire::cryptography::sig::PublicKey;
-use dandelion_wire::{Encrypted, Signable, Typed, UUID};
+use dandelion_wire::bytes::BytesMut;
+use dandelion_wire::cryptography::cipher::{Key, Nonce};
+use dandelion_wire::cryptography::digest::Digest;
+use dandelion_wire::cryptography::hkdf::Seed;
+use dandelion_wire::cryptography::{ecdh, sig};
+use dandelion_wire::rand_core::CryptoRngCore;
+use dandelion_wire::{
+    BaseSerializable,
+    Encryptable,
+    Encrypted,
+    ErrorKind,
+    PublicBytes,
+    Result,
+    SecretBytes,
+    Serializable,
+    Signable,
+    Signed,
+    Typed,
+    WireFormat,
+    UUID,
+};
 
-use super::Entity;
+use super::{Entity, Identity, Messages, PublicIdentity};
 
-#[derive(Clone)]
+#[derive(Clone, Serializable, Printable)]
 pub struct Envelope {
     pub sender: Entity,
     pub recipient: Entity,
     pub payload: Encrypted,
 }
 
+impl Envelope {
+    pub fn seal(
+        sender: &Identity,
+        recipient: &PublicIdentity,
+        messages: &Messages,
+        rng: &mut dyn CryptoRngCore,
+    ) -> Result<Signed> {
+        let nonce = Nonce::generate(rng);
+        let key = derive_key(sender.agreement_key(), recipient.agreement_key, nonce)?;
+        let parties = Parties { sender: sender.entity(), recipient: recipient.entity };
+        let payload = messages.encrypt(&key, nonce, parties)?;
+        let Parties { sender: from, recipient: to } = parties;
+        Self { sender: from, recipient: to, payload }.seal(sender.signing_key())
+    }
+
+    /// Verifies and decrypts an envelope whose sender uses its derived key-agreement key.
+    pub fn open(signed: &Signed, recipient: &Identity) -> Result<Messages> {
+        let envelope = Self::unseal(signed)?;
+        let sender = PublicIdentity::derive(envelope.sender)?;
+        envelope.decrypt(recipient, &sender)
+    }
+
+    pub fn decrypt(&self, recipient: &Identity, sender: &PublicIdentity) -> Result<Messages> {
+        if self.recipient != recipient.entity() || self.sender != sender.entity {
+            return Err(ErrorKind::Invalid { type_name: "Envelope" }.into());
+        }
+        let key = derive_key(recipient.agreement_key(), sender.agreement_key, self.payload.nonce)?;
+        let parties = Parties { sender: self.sender, recipient: self.recipient };
+        Messages::decrypt(&self.payload, &key, parties)
+    }
+
+    /// Panics if the envelope is too large to serialize, which [`Envelope::seal`] and decoding
+    /// both rule out.
+    pub fn compute_id(&self) -> EnvelopeID {
+        let mut buffer = BytesMut::with_capacity(self.wire_size(WireFormat::V1));
+        self.wire_write(&mut buffer, WireFormat::V1);
+        EnvelopeID(Digest::compute(Self::TYPE_UUID, buffer))
+    }
+}
+
+/// Identifies an [`Envelope`] by the hash of its serialized form.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable, Printable)]
+#[wire(fixed)]
+#[repr(transparent)]
+pub struct EnvelopeID(pub Digest);
+
+fn derive_key(
+    agreement_key: &ecdh::PrivateKey,
+    partner_key: ecdh::PublicKey,
+    nonce: Nonce,
+) -> Result<Key> {
+    let shared = agreement_key.diffie_hellman(partner_key)?;
+    let seed = Seed::from_key_material(nonce.as_slice(), shared.expose());
+    Ok(Key::from_shared_secret(seed.generate(Envelope::TYPE_UUID.as_slice())))
+}
+
+#[derive(Clone, Copy, Serializable)]
+#[wire(fixed)]
+struct Parties {
+    sender: Entity,
+    recipient: Entity,
+}
+
 impl Typed for Envelope {
     const TYPE_UUID: UUID = crate::constants::ENVELOPE_TYPE;
 }
 
 impl Signable for Envelope {
-    fn signer(&self) -> PublicKey {
+    fn signer(&self) -> sig::PublicKey {
         self.sender.public_key
     }
 }
 
-impl_serializable_for_struct!(Envelope { sender: Entity, recipient: Entity, payload: Encrypted });
-impl_printable_for_struct!(Envelope { sender, recipient, payload });
-impl_debug_for_printable!(Envelope);
-impl_display_for_printable!(Envelope);
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+
+    use dandelion_wire::bytes::BytesMut;
+    use dandelion_wire::cryptography::digest::Digest;
+    use dandelion_wire::rand_core::OsRng;
+    use dandelion_wire::{util, FixedSizeSerializable, Printable};
+
+    use super::*;
+    use crate::{BlockID, EntityType, Message};
+
+    fn party(entity_type: EntityType, fill: u8) -> Identity {
+        Identity::new(entity_type, sig::PrivateKey::from_exposed([fill; 32]))
+    }
+
+    fn seal(sender: &Identity, recipient: &Identity, messages: &Messages) -> Signed {
+        Envelope::seal(sender, &recipient.public(), messages, &mut OsRng).unwrap()
+    }
+
+    fn sample_messages() -> Messages {
+        Messages(Vec::from([
+            Message::Padding(3),
+            Message::DontWantBlock(BlockID(Digest::from_exact([7; 32]))),
+        ]))
+    }
+
+    #[test]
+    fn envelope_round_trip() {
+        let alice = party(EntityType::Endpoint, 1);
+        let bob = party(EntityType::Endpoint, 2);
+        let signed = seal(&alice, &bob, &sample_messages());
+
+        let opened = Envelope::open(&signed, &bob).unwrap();
+        assert_eq!(sample_messages().as_printed(), opened.as_printed());
+    }
+
+    #[test]
+    fn envelope_round_trip_published_key() {
+        let alice = Identity::with_agreement_key(
+            EntityType::Endpoint,
+            sig::PrivateKey::from_exposed([1; 32]),
+            ecdh::PrivateKey::from_exposed([0x81; 32]),
+        );
+        let bob = party(EntityType::Endpoint, 2);
+        let signed = seal(&alice, &bob, &sample_messages());
+
+        assert!(Envelope::open(&signed, &bob).is_err());
+        let envelope = Envelope::unseal(&signed).unwrap();
+        let opened = envelope.decrypt(&bob, &alice.public()).unwrap();
+        assert_eq!(sample_messages().as_printed(), opened.as_printed());
+    }
+
+    #[test]
+    fn envelope_rejects_wrong_recipient() {
+        let alice = party(EntityType::Endpoint, 1);
+        let bob = party(EntityType::Endpoint, 2);
+        let eve = party(EntityType::Endpoint, 3);
+        let signed = seal(&alice, &bob, &sample_messages());
+
+        assert!(Envelope::open(&signed, &eve).is_err());
+
+        let mut envelope = Envelope::unseal(&signed).unwrap();
+        envelope.recipient = eve.entity();
+        assert!(envelope.decrypt(&eve, &alice.public()).is_err());
+    }
+
+    #[test]
+    fn envelope_binds_parties() {
+        let alice = party(EntityType::Endpoint, 1);
+        let bob = Identity::new(EntityType::Node, sig::PrivateKey::from_exposed([2; 32]));
+        let signed = seal(&alice, &bob, &sample_messages());
+
+        let mut envelope = Envelope::unseal(&signed).unwrap();
+        envelope.recipient.entity_type = EntityType::Endpoint;
+        let bob = party(EntityType::Endpoint, 2);
+        assert!(envelope.decrypt(&bob, &alice.public()).is_err());
+    }
+
+    #[test]
+    fn envelope_applies_batch_limits() {
+        let alice = party(EntityType::Endpoint, 1);
+        let bob = party(EntityType::Endpoint, 2);
+        let len = Messages::DECODE_LIMITS.max_collection_len + 1;
+        let signed =
+            seal(&alice, &bob, &Messages(Vec::from_iter((0..len).map(|_| Message::Padding(0)))));
+
+        let err = Envelope::open(&signed, &bob).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::TooLarge { .. }));
+    }
+
+    #[test]
+    fn envelope_uses_fresh_nonce() {
+        let alice = party(EntityType::Endpoint, 1);
+        let bob = party(EntityType::Endpoint, 2);
+        let first = Envelope::unseal(&seal(&alice, &bob, &sample_messages())).unwrap();
+        let second = Envelope::unseal(&seal(&alice, &bob, &sample_messages())).unwrap();
+        assert_ne!(first.payload.nonce, second.payload.nonce);
+        assert_ne!(first.payload.ciphertext, second.payload.ciphertext);
+    }
+
+    #[test]
+    fn envelope_decode_errors_name_the_field() {
+        let alice = party(EntityType::Endpoint, 1);
+        let bob = party(EntityType::Endpoint, 2);
+        let mut raw = BytesMut::from(seal(&alice, &bob, &sample_messages()).payload);
+
+        let err = util::deserialize::<Envelope>(raw.clone().freeze().slice(..10)).unwrap_err();
+        assert_eq!(ErrorKind::Truncated { needed: 32, remaining: 8 }, err.kind());
+        assert_eq!(
+            Vec::from(["Envelope.sender", "Entity.public_key"]),
+            err.path().iter().collect::<Vec<_>>()
+        );
+
+        raw[Entity::WIRE_SIZE..][..2].copy_from_slice(&[0xff, 0xff]);
+        let err = util::deserialize::<Envelope>(raw.freeze()).unwrap_err();
+        assert_eq!(ErrorKind::UnknownCode { type_name: "EntityType", code: 0xffff }, err.kind());
+        assert_eq!(
+            "Envelope.recipient > Entity.entity_type: unknown EntityType code 0xffff",
+            err.to_string()
+        );
+    }
+}
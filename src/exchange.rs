@@ -0,0 +1,393 @@
+use alloc::vec::Vec;
+
+use dandelion_wire::{util, ErrorKind, FixedSizeSerializable, Result, Serializable, WireFormat};
+
+use super::block::BLOCK_SIZE;
+use super::message::DesireBlockID;
+use super::store::BlockStore;
+use super::{BlockID, Entity, Message};
+
+/// The wire size of a [`Message::HaveBlock`] carrying a full-sized block in [`WireFormat::V1`],
+/// which is never smaller than later formats for a block.
+pub const MAX_HAVE_BLOCK_COST: usize = u16::WIRE_SIZE.strict_add(util::varlen_wire_size(
+    WireFormat::V1,
+    util::varlen_wire_size(WireFormat::V1, BLOCK_SIZE),
+));
+
+pub const DEFAULT_BUDGET: usize = 16 * MAX_HAVE_BLOCK_COST;
+pub const DEFAULT_MAX_WANTS: usize = 1024;
+
+/// A transport-agnostic block exchange.  Feed it every block-related [`Message`] received from a
+/// peer with [`Exchange::receive`], and send whatever [`Exchange::poll`] returns for that peer.
+///
+/// Blocks are served highest [`Priority`](crate::Priority) first, in arrival order within a
+/// priority, and only while the peer's byte budget covers the wire size of the next
+/// [`Message::HaveBlock`] in the peer's [`WireFormat`]; top it up with [`Exchange::grant`].
+pub struct Exchange {
+    pub initial_budget: usize,
+    pub max_wants: usize,
+    wants: Vec<DesireBlockID>,
+    peers: Vec<Peer>,
+}
+
+struct Peer {
+    entity: Entity,
+    format: WireFormat,
+    budget: usize,
+    wants: Vec<DesireBlockID>,
+    outbox: Vec<Message>,
+}
+
+impl Default for Exchange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Exchange {
+    pub fn new() -> Self {
+        Self {
+            initial_budget: DEFAULT_BUDGET,
+            max_wants: DEFAULT_MAX_WANTS,
+            wants: Vec::new(),
+            peers: Vec::new(),
+        }
+    }
+
+    /// Starts tracking `entity` and queues our current want list for it.
+    pub fn connect(&mut self, entity: Entity) {
+        if self.peer(&entity).is_ok() {
+            return;
+        }
+        let outbox = self.wants.iter().map(|desire| Message::WantBlock(*desire)).collect();
+        let budget = self.initial_budget;
+        let format = WireFormat::V1;
+        self.peers.push(Peer { entity, format, budget, wants: Vec::new(), outbox });
+    }
+
+    pub fn disconnect(&mut self, entity: &Entity) {
+        self.peers.retain(|peer| peer.entity != *entity);
+    }
+
+    pub fn grant(&mut self, entity: &Entity, bytes: usize) -> Result<()> {
+        let peer = self.peer_mut(entity)?;
+        peer.budget = peer.budget.saturating_add(bytes);
+        Ok(())
+    }
+
+    pub fn budget(&self, entity: &Entity) -> Result<usize> {
+        Ok(self.peer(entity)?.budget)
+    }
+
+    /// Starts out as [`WireFormat::V1`].
+    pub fn format(&self, entity: &Entity) -> Result<WireFormat> {
+        Ok(self.peer(entity)?.format)
+    }
+
+    /// Prices blocks sent to `entity` in `format`, e.g. once its link has negotiated a protocol
+    /// version.
+    pub fn set_format(&mut self, entity: &Entity, format: WireFormat) -> Result<()> {
+        self.peer_mut(entity)?.format = format;
+        Ok(())
+    }
+
+    /// Our own outstanding wants.
+    pub fn wants(&self) -> &[DesireBlockID] {
+        &self.wants
+    }
+
+    /// The blocks `entity` has asked for, in the order they would be served.
+    pub fn wants_of(&self, entity: &Entity) -> Result<Vec<DesireBlockID>> {
+        let mut wants = self.peer(entity)?.wants.clone();
+        sort_for_service(&mut wants);
+        Ok(wants)
+    }
+
+    /// Asks every peer for a block, or changes the priority of an existing request.
+    pub fn want(&mut self, desire: DesireBlockID) {
+        upsert(&mut self.wants, desire);
+        for peer in &mut self.peers {
+            peer.outbox.push(Message::WantBlock(desire));
+        }
+    }
+
+    pub fn cancel(&mut self, block_id: &BlockID) {
+        if remove(&mut self.wants, block_id) {
+            self.broadcast_cancel(block_id, None);
+        }
+    }
+
+    /// Handles a message from `entity`.  Returns the ID of a block that was wanted and has now
+    /// been stored; unsolicited blocks and non-block messages are ignored.
+    pub fn receive(
+        &mut self,
+        entity: &Entity,
+        message: &Message,
+        store: &mut dyn BlockStore,
+    ) -> Result<Option<BlockID>> {
+        let max_wants = self.max_wants;
+        match message {
+            Message::WantBlock(desire) => {
+                let peer = self.peer_mut(entity)?;
+                let known = peer.wants.iter().any(|want| want.block_id == desire.block_id);
+                if known || peer.wants.len() < max_wants {
+                    upsert(&mut peer.wants, *desire);
+                }
+                Ok(None)
+            },
+            Message::DontWantBlock(block_id) => {
+                remove(&mut self.peer_mut(entity)?.wants, block_id);
+                Ok(None)
+            },
+            Message::HaveBlock(block) => {
+                self.peer(entity)?;
+                let block_id = block.compute_id();
+                if !self.wants.iter().any(|want| want.block_id == block_id) {
+                    return Ok(None);
+                }
+                store.insert(&block_id, block)?;
+                remove(&mut self.wants, &block_id);
+                self.broadcast_cancel(&block_id, Some(entity));
+                Ok(Some(block_id))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Drains the messages due to `entity`: queued want-list changes first, then as many
+    /// requested blocks from `store` as its budget allows.  If `store` fails, nothing is drained.
+    pub fn poll(&mut self, entity: &Entity, store: &dyn BlockStore) -> Result<Vec<Message>> {
+        let peer = self.peer_mut(entity)?;
+        sort_for_service(&mut peer.wants);
+        let mut budget = peer.budget;
+        let mut blocks = Vec::new();
+        let mut served = Vec::new();
+        for (index, want) in peer.wants.iter().enumerate() {
+            let Some(block) = store.get(&want.block_id)? else {
+                continue;
+            };
+            let message = Message::HaveBlock(block);
+            let cost = message.wire_size(peer.format);
+            if cost > budget {
+                break;
+            }
+            budget = budget.strict_sub(cost);
+            blocks.push(message);
+            served.push(index);
+        }
+        for index in served.into_iter().rev() {
+            peer.wants.remove(index);
+        }
+        peer.budget = budget;
+        let mut messages = core::mem::take(&mut peer.outbox);
+        messages.append(&mut blocks);
+        Ok(messages)
+    }
+
+    fn broadcast_cancel(&mut self, block_id: &BlockID, except: Option<&Entity>) {
+        for peer in &mut self.peers {
+            if Some(&peer.entity) != except {
+                peer.outbox.push(Message::DontWantBlock(*block_id));
+            }
+        }
+    }
+
+    fn peer(&self, entity: &Entity) -> Result<&Peer> {
+        self.peers.iter().find(|peer| peer.entity == *entity).ok_or(ErrorKind::UnknownPeer.into())
+    }
+
+    fn peer_mut(&mut self, entity: &Entity) -> Result<&mut Peer> {
+        self.peers
+            .iter_mut()
+            .find(|peer| peer.entity == *entity)
+            .ok_or(ErrorKind::UnknownPeer.into())
+    }
+}
+
+/// Highest priority first; the sort is stable, so arrival order is kept within a priority.
+fn sort_for_service(wants: &mut [DesireBlockID]) {
+    wants.sort_by_key(|desire| core::cmp::Reverse(desire.priority.code()));
+}
+
+fn upsert(wants: &mut Vec<DesireBlockID>, desire: DesireBlockID) {
+    match wants.iter_mut().find(|want| want.block_id == desire.block_id) {
+        Some(want) => want.priority = desire.priority,
+        None => wants.push(desire),
+    }
+}
+
+fn remove(wants: &mut Vec<DesireBlockID>, block_id: &BlockID) -> bool {
+    let before = wants.len();
+    wants.retain(|want| want.block_id != *block_id);
+    wants.len() != before
+}
+
+#[cfg(test)]
+mod tests {
+    use dandelion_wire::cryptography::sig::PublicKey;
+    use dandelion_wire::PublicBytes;
+
+    use super::*;
+    use crate::block::filled_block;
+    use crate::store::MemoryBlockStore;
+    use crate::{Block, EntityType, Priority};
+
+    fn entity(fill: u8) -> Entity {
+        Entity { entity_type: EntityType::Node, public_key: PublicKey::from_exact([fill; 32]) }
+    }
+
+    fn desire(block_id: BlockID, priority: Priority) -> Message {
+        Message::WantBlock(DesireBlockID { block_id, priority })
+    }
+
+    fn served(messages: &[Message]) -> Vec<BlockID> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                Message::HaveBlock(block) => Some(block.compute_id()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exchange_serves_by_priority_within_budget() {
+        let mut store = MemoryBlockStore::new();
+        let low = store.put(&filled_block(1)).unwrap();
+        let high = store.put(&filled_block(2)).unwrap();
+        let medium = store.put(&filled_block(3)).unwrap();
+        let missing = filled_block(4).compute_id();
+
+        let peer = entity(9);
+        let mut exchange = Exchange::new();
+        exchange.initial_budget = 2 * MAX_HAVE_BLOCK_COST;
+        exchange.connect(peer);
+        for message in [
+            desire(low, Priority::Low),
+            desire(missing, Priority::High),
+            desire(high, Priority::High),
+            desire(medium, Priority::Medium),
+        ] {
+            assert_eq!(None, exchange.receive(&peer, &message, &mut store).unwrap());
+        }
+
+        let messages = exchange.poll(&peer, &store).unwrap();
+        assert_eq!(Vec::from([high, medium]), served(&messages));
+        assert_eq!(0, exchange.budget(&peer).unwrap());
+        assert!(exchange.poll(&peer, &store).unwrap().is_empty());
+
+        exchange.grant(&peer, MAX_HAVE_BLOCK_COST).unwrap();
+        assert_eq!(Vec::from([low]), served(&exchange.poll(&peer, &store).unwrap()));
+        assert_eq!(1, exchange.wants_of(&peer).unwrap().len());
+    }
+
+    #[test]
+    fn exchange_cancels_on_dont_want() {
+        let mut store = MemoryBlockStore::new();
+        let first = store.put(&filled_block(1)).unwrap();
+        let second = store.put(&filled_block(2)).unwrap();
+
+        let peer = entity(9);
+        let mut exchange = Exchange::new();
+        exchange.connect(peer);
+        exchange.receive(&peer, &desire(first, Priority::Least), &mut store).unwrap();
+        exchange.receive(&peer, &desire(second, Priority::Least), &mut store).unwrap();
+        exchange.receive(&peer, &Message::DontWantBlock(first), &mut store).unwrap();
+
+        assert_eq!(Vec::from([second]), served(&exchange.poll(&peer, &store).unwrap()));
+        assert!(exchange.receive(&entity(8), &Message::DontWantBlock(first), &mut store).is_err());
+    }
+
+    #[test]
+    fn exchange_between_peers() {
+        let (alice, bob, carol) = (entity(1), entity(2), entity(3));
+        let block = filled_block(7);
+        let block_id = block.compute_id();
+
+        let mut alice_store = MemoryBlockStore::new();
+        let mut alice_exchange = Exchange::new();
+        alice_exchange.want(DesireBlockID { block_id, priority: Priority::Medium });
+        alice_exchange.connect(bob);
+        alice_exchange.connect(carol);
+
+        let mut bob_store = MemoryBlockStore::new();
+        bob_store.put(&block).unwrap();
+        let mut bob_exchange = Exchange::new();
+        bob_exchange.connect(alice);
+
+        for message in alice_exchange.poll(&bob, &alice_store).unwrap() {
+            bob_exchange.receive(&alice, &message, &mut bob_store).unwrap();
+        }
+        let unsolicited = Message::HaveBlock(filled_block(8));
+        assert_eq!(None, alice_exchange.receive(&bob, &unsolicited, &mut alice_store).unwrap());
+
+        let mut received = Vec::new();
+        for message in bob_exchange.poll(&alice, &bob_store).unwrap() {
+            received.push(alice_exchange.receive(&bob, &message, &mut alice_store).unwrap());
+        }
+        assert_eq!(Vec::from([Some(block_id)]), received);
+        assert!(alice_store.contains(&block_id).unwrap());
+        assert!(alice_exchange.wants().is_empty());
+        assert_eq!(1, alice_store.len());
+
+        let to_carol = alice_exchange.poll(&carol, &alice_store).unwrap();
+        assert!(
+            matches!(to_carol.as_slice(), [Message::WantBlock(_), Message::DontWantBlock(id)] if *id == block_id)
+        );
+    }
+
+    /// Fails every operation, as a disk might.
+    struct BrokenStore;
+
+    impl BlockStore for BrokenStore {
+        fn contains(&self, _: &BlockID) -> Result<bool> {
+            Err(ErrorKind::Io.into())
+        }
+        fn get(&self, _: &BlockID) -> Result<Option<Block>> {
+            Err(ErrorKind::Io.into())
+        }
+        fn insert(&mut self, _: &BlockID, _: &Block) -> Result<()> {
+            Err(ErrorKind::Io.into())
+        }
+        fn remove(&mut self, _: &BlockID) -> Result<bool> {
+            Err(ErrorKind::Io.into())
+        }
+        fn ids(&self) -> Result<Vec<BlockID>> {
+            Err(ErrorKind::Io.into())
+        }
+    }
+
+    #[test]
+    fn exchange_survives_store_errors() {
+        let peer = entity(9);
+        let block = filled_block(1);
+        let block_id = block.compute_id();
+        let mut exchange = Exchange::new();
+        exchange.want(DesireBlockID { block_id, priority: Priority::Medium });
+        exchange.connect(peer);
+        exchange.receive(&peer, &desire(block_id, Priority::Low), &mut BrokenStore).unwrap();
+
+        assert!(exchange.poll(&peer, &BrokenStore).is_err());
+        assert!(exchange.receive(&peer, &Message::HaveBlock(block), &mut BrokenStore).is_err());
+        assert_eq!(1, exchange.wants().len());
+        assert_eq!(1, exchange.wants_of(&peer).unwrap().len());
+        let messages = exchange.poll(&peer, &MemoryBlockStore::new()).unwrap();
+        assert!(matches!(messages.as_slice(), [Message::WantBlock(_)]));
+    }
+
+    #[test]
+    fn exchange_prices_in_peer_format() {
+        let mut store = MemoryBlockStore::new();
+        let block_id = store.put(&Block::from_slice(&[1; 10]).unwrap()).unwrap();
+        let peer = entity(9);
+        let mut exchange = Exchange::new();
+        exchange.connect(peer);
+        exchange.set_format(&peer, WireFormat::V2).unwrap();
+        exchange.receive(&peer, &desire(block_id, Priority::Low), &mut store).unwrap();
+
+        let messages = exchange.poll(&peer, &store).unwrap();
+        let cost = messages[0].wire_size(WireFormat::V2);
+        assert!(cost < messages[0].wire_size(WireFormat::V1));
+        assert_eq!(DEFAULT_BUDGET - cost, exchange.budget(&peer).unwrap());
+    }
+}
@@ -0,0 +1,184 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::any::Any;
+
+use dandelion_wire::bytes::{BufMut, Bytes};
+use dandelion_wire::{util, ErrorKind, Printable, Result, Serializable, WireFormat};
+
+use super::{Message, Messages};
+
+/// A message type defined outside this crate.  Implement this, then register the type with a
+/// [`MessageRegistry`] so that peers' messages of that type decode to [`Message::Extension`].
+pub trait Extension: Serializable + Printable + Send + Sync + 'static {
+    /// Must not collide with [`Message::ALL_CODES`] or with another extension.
+    const CODE: u16;
+    const NAME: &'static str;
+}
+
+/// The object-safe side of [`Extension`], as carried by [`Message::Extension`].
+pub trait ExtensionMessage: Printable + Send + Sync {
+    fn code(&self) -> u16;
+    fn name(&self) -> &'static str;
+    fn payload_size(&self, format: WireFormat) -> usize;
+    fn write_payload(&self, buffer: &mut dyn BufMut, format: WireFormat);
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Extension> ExtensionMessage for T {
+    fn code(&self) -> u16 {
+        T::CODE
+    }
+
+    fn name(&self) -> &'static str {
+        T::NAME
+    }
+
+    fn payload_size(&self, format: WireFormat) -> usize {
+        self.wire_size(format)
+    }
+
+    fn write_payload(&self, buffer: &mut dyn BufMut, format: WireFormat) {
+        self.wire_write(buffer, format)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl dyn ExtensionMessage {
+    pub fn downcast_ref<T: Extension>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+}
+
+type Decoder = fn(Bytes, WireFormat) -> Result<Arc<dyn ExtensionMessage>>;
+
+/// Decoders for extension message codes.  Decoding a [`Messages`] batch never needs one: codes it
+/// does not know become [`Message::Unknown`], which [`MessageRegistry::resolve`] can decode later
+/// and which otherwise re-serialize byte for byte.
+#[derive(Clone, Default)]
+pub struct MessageRegistry {
+    decoders: BTreeMap<u16, (&'static str, Decoder)>,
+}
+
+impl MessageRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails if `T::CODE` belongs to this crate or to an extension that is already registered.
+    pub fn register<T: Extension>(&mut self) -> Result<()> {
+        if Message::ALL_CODES.contains(&T::CODE) || self.decoders.contains_key(&T::CODE) {
+            return Err(ErrorKind::InvalidState.into());
+        }
+        self.decoders.insert(T::CODE, (T::NAME, decode::<T>));
+        Ok(())
+    }
+
+    /// The name registered for `code`, if any.
+    pub fn name(&self, code: u16) -> Option<&'static str> {
+        self.decoders.get(&code).map(|(name, _)| *name)
+    }
+
+    /// Decodes a [`Message::Unknown`] whose code is registered, from a batch received in
+    /// `format`.  Every other message, and any payload the extension cannot decode, is returned
+    /// unchanged, so that one malformed extension does not cost the rest of its batch.
+    pub fn resolve(&self, message: Message, format: WireFormat) -> Message {
+        match message {
+            Message::Unknown { code, payload } => match self.decoders.get(&code) {
+                Some((_, decoder)) => match decoder(payload.clone(), format) {
+                    Ok(extension) => Message::Extension(extension),
+                    Err(_) => Message::Unknown { code, payload },
+                },
+                None => Message::Unknown { code, payload },
+            },
+            message => message,
+        }
+    }
+
+    pub fn resolve_all(&self, messages: Messages, format: WireFormat) -> Messages {
+        Messages(messages.0.into_iter().map(|message| self.resolve(message, format)).collect())
+    }
+}
+
+fn decode<T: Extension>(payload: Bytes, format: WireFormat) -> Result<Arc<dyn ExtensionMessage>> {
+    Ok(Arc::new(util::deserialize_as::<T>(payload, format)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use dandelion_wire::cryptography::digest::Digest;
+    use dandelion_wire::PublicBytes;
+
+    use super::*;
+    use crate::BlockID;
+
+    #[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable)]
+    struct Ping {
+        nonce: u64,
+    }
+
+    impl_debug_for_printable!(Ping);
+
+    impl Printable for Ping {
+        fn print(&self, writer: &mut dyn alloc::fmt::Write) -> alloc::fmt::Result {
+            write!(writer, "{{nonce: {}}}", self.nonce)
+        }
+    }
+
+    impl Extension for Ping {
+        const CODE: u16 = 0x8000;
+        const NAME: &'static str = "Ping";
+    }
+
+    fn batch() -> Messages {
+        Messages(Vec::from([
+            Message::Padding(2),
+            Message::Extension(Arc::new(Ping { nonce: 7 })),
+            Message::DontWantBlock(BlockID(Digest::from_exact([7; 32]))),
+        ]))
+    }
+
+    #[test]
+    fn unknown_messages_round_trip() {
+        let raw = util::serialize(&batch()).unwrap().freeze();
+        let decoded = util::deserialize::<Messages>(raw.clone()).unwrap();
+        let Message::Unknown { code, payload } = &decoded.0[1] else {
+            panic!("{decoded}");
+        };
+        assert_eq!(Ping::CODE, *code);
+        assert_eq!(util::serialize(&Ping { nonce: 7 }).unwrap().freeze(), *payload);
+        assert_eq!(raw, util::serialize(&decoded).unwrap().freeze());
+
+        assert_eq!(r#"Unknown({code: 32768, payload: "AAAAAAAAAAc="})"#, decoded.0[1].as_printed());
+    }
+
+    #[test]
+    fn registry_resolves_extensions() {
+        let mut registry = MessageRegistry::new();
+        registry.register::<Ping>().unwrap();
+        assert!(registry.register::<Ping>().is_err());
+        assert_eq!(Some("Ping"), registry.name(Ping::CODE));
+
+        let raw = util::serialize(&batch()).unwrap().freeze();
+        let decoded = util::deserialize::<Messages>(raw.clone()).unwrap();
+        let resolved = registry.resolve_all(decoded, WireFormat::V1);
+        let Message::Extension(ping) = &resolved.0[1] else {
+            panic!("{resolved}");
+        };
+        assert_eq!(Some(&Ping { nonce: 7 }), ping.downcast_ref::<Ping>());
+        assert_eq!("Ping", resolved.0[1].name());
+        assert_eq!("Ping({nonce: 7})", resolved.0[1].as_printed());
+        assert_eq!(raw, util::serialize(&resolved).unwrap().freeze());
+
+        let truncated = Message::Unknown { code: Ping::CODE, payload: Bytes::from_static(&[1]) };
+        let mut batch = util::deserialize::<Messages>(raw).unwrap();
+        batch.0.insert(0, truncated);
+        let resolved = registry.resolve_all(batch, WireFormat::V1);
+        assert!(matches!(&resolved.0[0], Message::Unknown { payload, .. } if payload[..] == [1]));
+        assert!(matches!(&resolved.0[2], Message::Extension(_)));
+    }
+}
@@ -0,0 +1,140 @@
+use dandelion_wire::cryptography::{ecdh, sig};
+use dandelion_wire::rand_core::CryptoRngCore;
+use dandelion_wire::zeroize::Zeroize;
+use dandelion_wire::{Result, SecretBytes};
+
+use super::claim::AgreementKey;
+use super::{Claim, Entity, EntityType, TrustStore};
+
+/// An [`Entity`] together with the X25519 key used to encrypt envelopes addressed to it.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable, Printable)]
+#[wire(fixed)]
+pub struct PublicIdentity {
+    pub entity: Entity,
+    pub agreement_key: ecdh::PublicKey,
+}
+
+impl PublicIdentity {
+    /// Uses the key-agreement key derived from the entity's signing key.
+    pub fn derive(entity: Entity) -> Result<Self> {
+        let agreement_key = ecdh::PublicKey::from_signing_key(entity.public_key)?;
+        Ok(Self { entity, agreement_key })
+    }
+
+    /// Prefers a key-agreement key published in `store`, falling back to the derived key.
+    pub fn resolve(entity: Entity, store: &TrustStore) -> Result<Self> {
+        match store.agreement_key_of(&entity) {
+            Some(agreement_key) => Ok(Self { entity, agreement_key }),
+            None => Self::derive(entity),
+        }
+    }
+}
+
+/// The private keys of a local entity.  Both keys are zeroized on drop.
+#[derive(Clone, Serializable, Printable)]
+#[wire(fixed)]
+pub struct Identity {
+    entity_type: EntityType,
+    signing_key: sig::PrivateKey,
+    agreement_key: ecdh::PrivateKey,
+}
+
+impl Identity {
+    /// Uses a key-agreement key derived from `signing_key`.
+    pub fn new(entity_type: EntityType, signing_key: sig::PrivateKey) -> Self {
+        let agreement_key = ecdh::PrivateKey::from_signing_key(&signing_key);
+        Self { entity_type, signing_key, agreement_key }
+    }
+
+    /// Uses an independent key-agreement key, which must be published with
+    /// [`Identity::agreement_claim`] before peers can encrypt to it.
+    pub fn with_agreement_key(
+        entity_type: EntityType,
+        signing_key: sig::PrivateKey,
+        agreement_key: ecdh::PrivateKey,
+    ) -> Self {
+        Self { entity_type, signing_key, agreement_key }
+    }
+
+    pub fn generate(entity_type: EntityType, rng: &mut dyn CryptoRngCore) -> Self {
+        let mut raw = sig::RawPrivateKey::default();
+        rng.fill_bytes(&mut raw);
+        let signing_key = sig::PrivateKey::from_exposed(raw);
+        raw.zeroize();
+        Self::new(entity_type, signing_key)
+    }
+
+    pub fn entity_type(&self) -> EntityType {
+        self.entity_type
+    }
+
+    pub fn entity(&self) -> Entity {
+        Entity { entity_type: self.entity_type, public_key: self.signing_key.public_key() }
+    }
+
+    pub fn public(&self) -> PublicIdentity {
+        PublicIdentity { entity: self.entity(), agreement_key: self.agreement_key.public_key() }
+    }
+
+    pub fn signing_key(&self) -> &sig::PrivateKey {
+        &self.signing_key
+    }
+
+    pub fn agreement_key(&self) -> &ecdh::PrivateKey {
+        &self.agreement_key
+    }
+
+    pub fn agreement_claim(&self) -> Claim {
+        let PublicIdentity { entity, agreement_key: key } = self.public();
+        Claim::AgreementKey(AgreementKey { entity, key })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use dandelion_wire::rand_core::OsRng;
+    use dandelion_wire::{util, Printable, Signable};
+
+    use super::*;
+    use crate::{Attestation, AttestationVerifier, Claims, Instant};
+
+    #[test]
+    fn identity_derives_public_identity() {
+        let identity = Identity::generate(EntityType::Node, &mut OsRng);
+        let derived = PublicIdentity::derive(identity.entity()).unwrap();
+        assert_eq!(identity.public(), derived);
+    }
+
+    #[test]
+    fn identity_resolves_published_key() {
+        let identity = Identity::with_agreement_key(
+            EntityType::Endpoint,
+            sig::PrivateKey::from_exposed([1; 32]),
+            ecdh::PrivateKey::from_exposed([2; 32]),
+        );
+        let entity = identity.entity();
+        let mut store = TrustStore::new();
+        assert_ne!(identity.public(), PublicIdentity::resolve(entity, &store).unwrap());
+
+        let claims = Claims(Vec::from([identity.agreement_claim()]));
+        let signed = Attestation { attestor: entity, time: Instant::ZERO, claims }
+            .seal(identity.signing_key())
+            .unwrap();
+        store.insert(&AttestationVerifier::default(), signed, Instant::ZERO).unwrap();
+        assert_eq!(identity.public(), PublicIdentity::resolve(entity, &store).unwrap());
+    }
+
+    #[test]
+    fn identity_round_trip_redacts() {
+        let identity = Identity::new(EntityType::Zone, sig::PrivateKey::from_exposed([3; 32]));
+        let decoded =
+            util::deserialize::<Identity>(util::serialize(&identity).unwrap().into()).unwrap();
+        assert_eq!(identity.public(), decoded.public());
+        assert_eq!(
+            "{entity_type: Zone, signing_key: <redacted>, agreement_key: <redacted>}",
+            identity.as_printed()
+        );
+    }
+}
@@ -2,28 +2,61 @@
 #![feature(strict_overflow_ops, const_strict_overflow_ops)]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
 #[macro_use]
 extern crate dandelion_wire;
 
-#[macro_use]
-mod macros;
-
 pub mod attestation;
 pub mod block;
+pub mod capabilities;
+pub mod channel;
+pub mod chunker;
+pub mod civil;
 pub mod claim;
+pub mod clock;
 pub mod constants;
 pub mod entity;
 pub mod envelope;
+pub mod exchange;
+pub mod extension;
+pub mod identity;
+pub mod manifest;
 pub mod message;
+pub mod padding;
 pub mod priority;
+pub mod rng;
+pub mod router;
+pub mod store;
+pub mod stream;
 pub mod time;
+pub mod trust;
+pub mod verify;
 
 pub use attestation::Attestation;
 pub use block::{Block, BlockID};
+pub use capabilities::{Capabilities, CipherSuite};
+pub use channel::Channel;
+pub use chunker::{Chunker, Chunking};
+pub use civil::{Date, DateTime};
 pub use claim::{Claim, Claims};
+pub use clock::{Clock, ManualClock};
+#[cfg(feature = "std")]
+pub use clock::{MonotonicClock, SystemClock};
 pub use entity::{Entity, EntityType};
-pub use envelope::Envelope;
+pub use envelope::{Envelope, EnvelopeID};
+pub use exchange::Exchange;
+pub use extension::{Extension, ExtensionMessage, MessageRegistry};
+pub use identity::{Identity, PublicIdentity};
+pub use manifest::{Manifest, Reassembler};
 pub use message::{Message, Messages};
+pub use padding::{MessagesBuilder, PaddingPolicy};
 pub use priority::Priority;
+pub use rng::SplitMix64;
+pub use router::Router;
+pub use store::BlockStore;
+pub use stream::{Decoded, MessageDecoder};
 pub use time::{Duration, Instant};
+pub use trust::TrustStore;
+pub use verify::{AttestationVerifier, ClaimPolicy, DefaultClaimPolicy, Rejection};
@@ -0,0 +1,368 @@
+use alloc::collections::BTreeSet;
+use alloc::fmt;
+use alloc::vec::Vec;
+
+use dandelion_wire::cryptography::digest::RawDigest;
+use dandelion_wire::{
+    util,
+    BaseSerializable,
+    DecodeContext,
+    DecodeLimits,
+    Error,
+    ErrorKind,
+    FixedSizeSerializable,
+    Printable,
+    PublicBytes,
+    Result,
+    Typed,
+    WireFormat,
+    UUID,
+};
+
+use super::block::BLOCK_SIZE;
+use super::chunker::Chunker;
+use super::store::BlockStore;
+use super::{Block, BlockID};
+
+/// The largest number of links that fit in one manifest block.
+pub const MAX_LINKS: usize = (BLOCK_SIZE
+    - UUID::WIRE_SIZE
+    - u64::WIRE_SIZE
+    - u8::WIRE_SIZE
+    - util::len_wire_size(WireFormat::V1, BLOCK_SIZE))
+    / Link::WIRE_SIZE;
+
+/// Manifests nest at most this deep, which is far more than any realistic payload needs.
+pub const MAX_DEPTH: u8 = 8;
+
+/// The largest payload a [`Reassembler`] accepts by default.
+pub const DEFAULT_MAX_PAYLOAD_LEN: u64 = 1 << 30;
+
+/// The most links a [`Reassembler`] follows in one walk by default: enough for a payload of
+/// [`DEFAULT_MAX_PAYLOAD_LEN`] in chunks of 1 KiB.
+pub const DEFAULT_MAX_VISITED_LINKS: usize = 1 << 20;
+
+const INVALID_MANIFEST: Error = Error::new(ErrorKind::Invalid { type_name: "Manifest" });
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable)]
+#[wire(fixed)]
+pub struct Link {
+    pub block_id: BlockID,
+    /// Payload bytes covered by the linked block (and, for a manifest, all of its descendants).
+    pub len: u64,
+}
+
+/// One node of the Merkle tree describing a payload larger than a single [`Block`].  At depth 0
+/// the links point at data blocks; at depth N they point at manifests of depth N-1.  Manifest
+/// blocks are always in [`WireFormat::V1`], so that a payload has the same ID on every link.
+#[derive(Clone, Hash, PartialEq, Eq, Serializable)]
+pub struct Manifest {
+    pub len: u64,
+    pub depth: u8,
+    pub links: Vec<Link>,
+}
+
+impl Typed for Manifest {
+    const TYPE_UUID: UUID = crate::constants::MANIFEST_TYPE;
+}
+
+impl Manifest {
+    /// Splits `data` with `chunker`, stores the data blocks and manifests, and returns the ID of
+    /// the root manifest.
+    pub fn build(data: &[u8], chunker: &Chunker, store: &mut dyn BlockStore) -> Result<BlockID> {
+        let mut links = Vec::new();
+        for range in chunker.split(data) {
+            let block_id = store.put(&Block::from_slice(&data[range.clone()])?)?;
+            links.push(Link { block_id, len: range.len() as u64 });
+        }
+        if links.is_empty() {
+            let manifest = Self { len: 0, depth: 0, links };
+            return store.put(&manifest.to_block()?);
+        }
+        let mut depth = 0;
+        loop {
+            let mut parents = Vec::new();
+            for chunk in links.chunks(MAX_LINKS) {
+                let len = chunk.iter().map(|link| link.len).sum();
+                let manifest = Self { len, depth, links: Vec::from(chunk) };
+                parents.push(Link { block_id: store.put(&manifest.to_block()?)?, len });
+            }
+            if let [root] = parents.as_slice() {
+                return Ok(root.block_id);
+            }
+            links = parents;
+            depth = depth.strict_add(1);
+        }
+    }
+
+    pub fn to_block(&self) -> Result<Block> {
+        if self.links.len() > MAX_LINKS {
+            return Err(ErrorKind::TooLarge { len: self.links.len(), max: MAX_LINKS }.into());
+        }
+        let mut raw = util::serialize(&Self::TYPE_UUID)?;
+        self.wire_write(&mut raw, WireFormat::V1);
+        Block::new(raw.freeze())
+    }
+
+    /// Fails unless `block` holds exactly one well-formed manifest.
+    pub fn from_block(block: &Block) -> Result<Self> {
+        let mut buffer = block.as_slice();
+        let limits = DecodeLimits { max_collection_len: MAX_LINKS, ..DecodeLimits::default() };
+        let mut context = DecodeContext::with_limits(WireFormat::V1, limits);
+        Self::TYPE_UUID.wire_verify(&mut buffer, &mut context)?;
+        let manifest = Self::wire_read(&mut buffer, &mut context)?;
+        if !buffer.is_empty() {
+            return Err(ErrorKind::TrailingBytes { remaining: buffer.len() }.into());
+        }
+        let sum = manifest.links.iter().try_fold(0u64, |sum, link| sum.checked_add(link.len));
+        if manifest.depth > MAX_DEPTH || sum != Some(manifest.len) {
+            return Err(INVALID_MANIFEST);
+        }
+        // No chunker produces empty chunks, and they would let a tree grow without its length.
+        if manifest.links.iter().any(|link| link.len == 0) {
+            return Err(INVALID_MANIFEST);
+        }
+        if manifest.depth == 0 && manifest.links.iter().any(|link| link.len > BLOCK_SIZE as u64) {
+            return Err(INVALID_MANIFEST);
+        }
+        Ok(manifest)
+    }
+}
+
+/// Rebuilds a payload from the tree rooted at a manifest, verifying every manifest and chunk
+/// against the links that lead to it.
+///
+/// The tree usually comes from peers, so the work is bounded: a root declaring more than
+/// `max_len` bytes is rejected before anything else is fetched, and a walk fails with
+/// [`ErrorKind::TooLarge`] once it has followed `max_links` links, however many of them repeat.
+pub struct Reassembler {
+    root: BlockID,
+    pub max_len: u64,
+    pub max_links: usize,
+}
+
+impl Reassembler {
+    /// Uses [`DEFAULT_MAX_PAYLOAD_LEN`] and [`DEFAULT_MAX_VISITED_LINKS`].
+    pub fn new(root: BlockID) -> Self {
+        Self { root, max_len: DEFAULT_MAX_PAYLOAD_LEN, max_links: DEFAULT_MAX_VISITED_LINKS }
+    }
+
+    pub fn root(&self) -> BlockID {
+        self.root
+    }
+
+    /// Blocks that must be fetched before the next step can make progress, e.g. by passing them
+    /// to [`crate::Exchange::want`], each listed once.  Empty once [`Reassembler::assemble`] can
+    /// succeed.
+    pub fn missing(&self, store: &dyn BlockStore) -> Result<Vec<BlockID>> {
+        let (mut missing, mut listed) = (Vec::new(), BTreeSet::new());
+        let mut walk = Walk::new(self, store, true);
+        walk.root(self.root, &mut |link, block| {
+            if block.is_none() && listed.insert(link.block_id.0.into_exact()) {
+                missing.push(link.block_id);
+            }
+            Ok(())
+        })?;
+        Ok(missing)
+    }
+
+    pub fn assemble(&self, store: &dyn BlockStore) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut walk = Walk::new(self, store, false);
+        walk.root(self.root, &mut |link, block| {
+            let block = block.ok_or(ErrorKind::NotFound)?;
+            if block.len() as u64 != link.len {
+                return Err(INVALID_MANIFEST);
+            }
+            data.extend_from_slice(block.as_slice());
+            Ok(())
+        })?;
+        Ok(data)
+    }
+}
+
+/// One bounded pass over a tree.
+struct Walk<'a> {
+    store: &'a dyn BlockStore,
+    max_len: u64,
+    max_links: usize,
+    visited: usize,
+    /// The manifests already walked, when each subtree only needs visiting once.
+    seen: Option<BTreeSet<RawDigest>>,
+}
+
+type Visit<'v> = dyn FnMut(&Link, Option<&Block>) -> Result<()> + 'v;
+
+impl<'a> Walk<'a> {
+    fn new(reassembler: &Reassembler, store: &'a dyn BlockStore, once: bool) -> Self {
+        Self {
+            store,
+            max_len: reassembler.max_len,
+            max_links: reassembler.max_links,
+            visited: 0,
+            seen: once.then(BTreeSet::new),
+        }
+    }
+
+    /// Calls `visit` for every data link in order, with the block if the store has it.  Subtrees
+    /// under a missing manifest are reported as that manifest's link.
+    fn root(&mut self, root: BlockID, visit: &mut Visit) -> Result<()> {
+        let Some(block) = self.store.get(&root)? else {
+            return visit(&Link { block_id: root, len: 0 }, None);
+        };
+        let manifest = Manifest::from_block(&block)?;
+        if manifest.len > self.max_len {
+            let len = usize::try_from(manifest.len).unwrap_or(usize::MAX);
+            let max = usize::try_from(self.max_len).unwrap_or(usize::MAX);
+            return Err(ErrorKind::TooLarge { len, max }.into());
+        }
+        self.manifest(&manifest, visit)
+    }
+
+    fn manifest(&mut self, manifest: &Manifest, visit: &mut Visit) -> Result<()> {
+        for link in &manifest.links {
+            self.visited = self.visited.strict_add(1);
+            if self.visited > self.max_links {
+                let (len, max) = (self.visited, self.max_links);
+                return Err(ErrorKind::TooLarge { len, max }.into());
+            }
+            let block = self.store.get(&link.block_id)?;
+            match (manifest.depth, block) {
+                (0, block) => visit(link, block.as_ref())?,
+                (_, None) => visit(link, None)?,
+                (depth, Some(block)) => {
+                    let raw = link.block_id.0.into_exact();
+                    if self.seen.as_mut().is_some_and(|seen| !seen.insert(raw)) {
+                        continue;
+                    }
+                    let child = Manifest::from_block(&block)?;
+                    if child.depth.strict_add(1) != depth || child.len != link.len {
+                        return Err(INVALID_MANIFEST);
+                    }
+                    self.manifest(&child, visit)?;
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Printable for Link {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        write!(writer, "{{block_id: {}, len: {}}}", self.block_id, self.len)
+    }
+}
+
+impl Printable for Manifest {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        write!(writer, "{{len: {}, depth: {}, links: ", self.len, self.depth)?;
+        self.links.print(writer)?;
+        writer.write_char('}')
+    }
+}
+
+impl_debug_for_printable!(Link);
+impl_display_for_printable!(Link);
+
+impl_debug_for_printable!(Manifest);
+impl_display_for_printable!(Manifest);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunker::Chunking;
+    use crate::rng::sample;
+    use crate::store::MemoryBlockStore;
+
+    #[test]
+    fn manifest_round_trip() {
+        let chunker = Chunker::new(Chunking::FIXED).unwrap();
+        let data = sample(BLOCK_SIZE * 2 + 12345);
+        let mut store = MemoryBlockStore::new();
+        let root = Manifest::build(&data, &chunker, &mut store).unwrap();
+        assert_eq!(4, store.len());
+
+        let manifest = Manifest::from_block(&store.get(&root).unwrap().unwrap()).unwrap();
+        assert_eq!(data.len() as u64, manifest.len);
+        assert_eq!(3, manifest.links.len());
+        let tail = store.get(&manifest.links[2].block_id).unwrap().unwrap();
+        assert_eq!(12345, tail.len());
+
+        let reassembler = Reassembler::new(root);
+        assert!(reassembler.missing(&store).unwrap().is_empty());
+        assert_eq!(data, reassembler.assemble(&store).unwrap());
+    }
+
+    #[test]
+    fn manifest_empty_payload() {
+        let chunker = Chunker::new(Chunking::CONTENT_DEFINED).unwrap();
+        let mut store = MemoryBlockStore::new();
+        let root = Manifest::build(&[], &chunker, &mut store).unwrap();
+        assert!(Reassembler::new(root).assemble(&store).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reassembler_reports_missing_blocks() {
+        let chunker = Chunker::new(Chunking::Fixed { size: 1000 }).unwrap();
+        let data = sample(2500);
+        let mut source = MemoryBlockStore::new();
+        let root = Manifest::build(&data, &chunker, &mut source).unwrap();
+
+        let reassembler = Reassembler::new(root);
+        let mut store = MemoryBlockStore::new();
+        assert_eq!(Vec::from([root]), reassembler.missing(&store).unwrap());
+        assert!(reassembler.assemble(&store).is_err());
+
+        store.insert(&root, &source.get(&root).unwrap().unwrap()).unwrap();
+        let missing = reassembler.missing(&store).unwrap();
+        assert_eq!(3, missing.len());
+        for id in missing {
+            store.insert(&id, &source.get(&id).unwrap().unwrap()).unwrap();
+        }
+        assert_eq!(data, reassembler.assemble(&store).unwrap());
+    }
+
+    #[test]
+    fn manifest_rejects_inconsistent_lengths() {
+        let link = Link { block_id: sample_block_id(), len: 10 };
+        let manifest = Manifest { len: 11, depth: 0, links: Vec::from([link]) };
+        assert!(Manifest::from_block(&manifest.to_block().unwrap()).is_err());
+        let manifest = Manifest { len: 10, ..manifest };
+        assert_eq!(manifest, Manifest::from_block(&manifest.to_block().unwrap()).unwrap());
+        assert!(Manifest::from_block(&Block::from_slice(b"not a manifest").unwrap()).is_err());
+    }
+
+    #[test]
+    fn reassembler_bounds_repeated_links() {
+        let chunk = Block::from_slice(b"chunk").unwrap();
+        let mut store = MemoryBlockStore::new();
+        let mut repeat = |manifest: &Manifest| {
+            let block = manifest.to_block().unwrap();
+            let id = block.compute_id();
+            store.insert(&id, &block).unwrap();
+            id
+        };
+        // Each level links the level below 100 times, so the whole tree spells out 10^8 chunks.
+        let mut link = Link { block_id: chunk.compute_id(), len: chunk.len() as u64 };
+        for depth in 0..4 {
+            let links = Vec::from([link; 100]);
+            let manifest = Manifest { len: link.len * 100, depth, links };
+            link = Link { block_id: repeat(&manifest), len: manifest.len };
+        }
+
+        let mut reassembler = Reassembler::new(link.block_id);
+        assert_eq!(Vec::from([chunk.compute_id()]), reassembler.missing(&store).unwrap());
+        store.insert(&chunk.compute_id(), &chunk).unwrap();
+        assert!(reassembler.missing(&store).unwrap().is_empty());
+        let error = reassembler.assemble(&store).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::TooLarge { .. }));
+
+        reassembler.max_len = link.len - 1;
+        let error = reassembler.missing(&store).unwrap_err();
+        assert_eq!(ErrorKind::TooLarge { len: 500_000_000, max: 499_999_999 }, error.kind());
+    }
+
+    fn sample_block_id() -> BlockID {
+        Block::from_slice(b"chunk").unwrap().compute_id()
+    }
+}
@@ -1,23 +1,13 @@
-use alloc::fmt;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
-use dandelion_wire::bytes::{Buf, BufMut};
-use dandelion_wire::{
-    util,
-    BaseSerializable,
-    Encryptable,
-    Error,
-    FixedSizeSerializable,
-    Printable,
-    Result,
-    Serializable,
-    Typed,
-    UUID,
-};
+use dandelion_wire::bytes::Bytes;
+use dandelion_wire::{DecodeLimits, Encryptable, Printable, Serializable, Typed, UUID};
 
-use super::{Attestation, Block, BlockID, Envelope, Priority};
+use super::capabilities::DEFAULT_MAX_BATCH_SIZE;
+use super::{Attestation, Block, BlockID, Capabilities, Envelope, ExtensionMessage, Priority};
 
-#[derive(Clone)]
+#[derive(Clone, Serializable, Printable)]
 #[repr(transparent)]
 pub struct Messages(pub Vec<Message>);
 
@@ -25,150 +15,160 @@ impl Typed for Messages {
     const TYPE_UUID: UUID = crate::constants::MESSAGES_TYPE;
 }
 
-impl Encryptable for Messages {}
+impl Encryptable for Messages {
+    const DECODE_LIMITS: DecodeLimits = Messages::DECODE_LIMITS;
+}
 
-impl_serializable_for_wrapper!(Messages, wraps Vec<Message>);
-impl_printable_for_wrapper!(Messages);
-impl_debug_for_printable!(Messages);
-impl_display_for_printable!(Messages);
+impl Messages {
+    /// Limits for decoding a batch from a peer that accepts batches of the default size.
+    pub const DECODE_LIMITS: DecodeLimits = Self::decode_limits(DEFAULT_MAX_BATCH_SIZE);
+
+    /// Limits for decoding a batch of at most `max_batch_size` bytes, as framed by a
+    /// [`crate::Channel`].  The batch counts once for its own frame, once for each message's
+    /// frame and once more for byte contents such as blocks.
+    pub const fn decode_limits(max_batch_size: usize) -> DecodeLimits {
+        DecodeLimits {
+            max_total_bytes: max_batch_size.saturating_mul(3),
+            max_collection_len: 1 << 16,
+            max_depth: 4,
+        }
+    }
+}
 
-#[derive(Clone)]
+#[derive(Clone, Serializable, Printable)]
 pub enum Message {
+    #[wire(code = 0x0000, padding)]
     Padding(usize),
+    #[wire(code = 0x0001)]
     Attestation(Attestation),
+    #[wire(code = 0x0002)]
     Envelope(Envelope),
+    /// An [`Envelope`] in the Dandelion++ stem phase; see [`crate::Router`].
+    #[wire(code = 0x0003)]
+    StemEnvelope(Envelope),
+    /// Sent first on every link; see [`Capabilities::negotiate`].
+    #[wire(code = 0x0004)]
+    Hello(Capabilities),
+    #[wire(code = 0x0100)]
     HaveBlock(Block),
+    #[wire(code = 0x0101)]
     WantBlock(DesireBlockID),
+    #[wire(code = 0x0102)]
     DontWantBlock(BlockID),
+    /// A message with a code this crate does not know, e.g. from a newer peer.  It is kept so that
+    /// it re-serializes byte for byte; see [`crate::MessageRegistry::resolve`].
+    #[wire(other)]
+    Unknown { code: u16, payload: Bytes },
+    /// A message type defined by another crate; see [`crate::Extension`].
+    #[wire(dynamic)]
+    Extension(Arc<dyn ExtensionMessage>),
 }
 
-pub mod codes {
-    pub const PADDING: u16 = 0x0000;
-    pub const ATTESTATION: u16 = 0x0001;
-    pub const ENVELOPE: u16 = 0x0002;
-    pub const HAVE_BLOCK: u16 = 0x0100;
-    pub const WANT_BLOCK: u16 = 0x0101;
-    pub const DONT_WANT_BLOCK: u16 = 0x0102;
-}
-
-pub mod names {
-    pub const PADDING: &str = "Padding";
-    pub const ATTESTATION: &str = "Attestation";
-    pub const ENVELOPE: &str = "Envelope";
-    pub const HAVE_BLOCK: &str = "HaveBlock";
-    pub const WANT_BLOCK: &str = "WantBlock";
-    pub const DONT_WANT_BLOCK: &str = "DontWantBlock";
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable, Printable)]
+#[wire(fixed)]
+pub struct DesireBlockID {
+    pub block_id: BlockID,
+    pub priority: Priority,
 }
 
-impl Message {
-    pub fn code(&self) -> u16 {
-        match self {
-            Self::Padding(_) => codes::PADDING,
-            Self::Attestation(_) => codes::ATTESTATION,
-            Self::Envelope(_) => codes::ENVELOPE,
-            Self::HaveBlock(_) => codes::HAVE_BLOCK,
-            Self::WantBlock(_) => codes::WANT_BLOCK,
-            Self::DontWantBlock(_) => codes::DONT_WANT_BLOCK,
-        }
-    }
-    pub fn name(&self) -> &'static str {
-        match self {
-            Self::Padding(_) => names::PADDING,
-            Self::Attestation(_) => names::ATTESTATION,
-            Self::Envelope(_) => names::ENVELOPE,
-            Self::HaveBlock(_) => names::HAVE_BLOCK,
-            Self::WantBlock(_) => names::WANT_BLOCK,
-            Self::DontWantBlock(_) => names::DONT_WANT_BLOCK,
+#[cfg(test)]
+mod tests {
+    use dandelion_wire::cryptography::digest::Digest;
+    use dandelion_wire::{util, PublicBytes, WireFormat};
+
+    use super::*;
+
+    /// Pins the wire format: every message is a code, a length and then the nested value.
+    #[test]
+    fn message_wire_format() {
+        let framed = |code: u16, inner: &[u8]| {
+            let mut raw = Vec::from(code.to_be_bytes());
+            raw.extend((inner.len() as u32).to_be_bytes());
+            raw.extend(inner);
+            raw
+        };
+        let block_id = BlockID(Digest::from_exact([7; 32]));
+        let desire = [[7; 32].as_slice(), &[3]].concat();
+        let capabilities = [
+            [0, 1, 0, 2].as_slice(),
+            &[0, 0, 0, 8, 0, 0, 0, 1, 0, 2, 0, 3, 0, 4, 1, 0, 1, 1, 1, 2],
+            &[0, 0, 0, 2, 0, 0, 0, 1],
+            &[0, 0x40, 0, 0],
+        ]
+        .concat();
+        let cases = [
+            (Message::Padding(2), framed(0x0000, &[0, 0])),
+            (Message::Hello(Capabilities::local()), framed(0x0004, &capabilities)),
+            (
+                Message::HaveBlock(Block::from_slice(b"abc").unwrap()),
+                framed(0x0100, b"\0\0\0\x03abc"),
+            ),
+            (
+                Message::WantBlock(DesireBlockID { block_id, priority: Priority::High }),
+                framed(0x0101, &desire),
+            ),
+            (Message::DontWantBlock(block_id), framed(0x0102, &[7; 32])),
+            (
+                Message::Unknown { code: 0x8000, payload: Bytes::from_static(b"xyz") },
+                framed(0x8000, b"xyz"),
+            ),
+        ];
+        for (message, expected) in cases {
+            let raw = util::serialize(&message).unwrap();
+            assert_eq!(expected, raw.as_ref(), "{message}");
+            assert_eq!(expected.len(), message.wire_size(WireFormat::V1));
+            let decoded = util::deserialize::<Message>(raw.freeze()).unwrap();
+            assert_eq!(message.as_printed(), decoded.as_printed());
         }
     }
-}
 
-impl BaseSerializable for Message {
-    fn wire_write(&self, buffer: &mut dyn BufMut) {
-        match self {
-            Self::Padding(len) => {
-                codes::PADDING.wire_write(buffer);
-                util::varlen_fill(buffer, 0, *len);
-            },
-            Self::Attestation(att) => {
-                codes::ATTESTATION.wire_write(buffer);
-                util::nested_write(buffer, att);
-            },
-            Self::Envelope(env) => {
-                codes::ENVELOPE.wire_write(buffer);
-                util::nested_write(buffer, env);
-            },
-            Self::HaveBlock(block) => {
-                codes::HAVE_BLOCK.wire_write(buffer);
-                util::nested_write(buffer, block);
-            },
-            Self::WantBlock(desire) => {
-                codes::WANT_BLOCK.wire_write(buffer);
-                util::nested_write(buffer, desire);
-            },
-            Self::DontWantBlock(id) => {
-                codes::DONT_WANT_BLOCK.wire_write(buffer);
-                util::nested_write(buffer, id);
-            },
-        }
-    }
-    fn wire_read(buffer: &mut dyn Buf) -> Result<Self> {
-        use util::{nested_read, varlen_skip};
-        let code = u16::wire_read(buffer)?;
-        match code {
-            codes::PADDING => Ok(Self::Padding(varlen_skip(buffer)?)),
-            codes::ATTESTATION => Ok(Self::Attestation(nested_read::<Attestation>(buffer)?)),
-            codes::ENVELOPE => Ok(Self::Envelope(nested_read::<Envelope>(buffer)?)),
-            codes::HAVE_BLOCK => Ok(Self::HaveBlock(nested_read::<Block>(buffer)?)),
-            codes::WANT_BLOCK => Ok(Self::WantBlock(nested_read::<DesireBlockID>(buffer)?)),
-            codes::DONT_WANT_BLOCK => Ok(Self::DontWantBlock(nested_read::<BlockID>(buffer)?)),
-            _ => Err(Error),
+    /// The same messages with varint lengths, as used from protocol version 2.
+    #[test]
+    fn message_wire_format_v2() {
+        let framed = |code: u16, inner: &[u8]| {
+            assert!(inner.len() < 64);
+            let mut raw = Vec::from(code.to_be_bytes());
+            raw.push(inner.len() as u8);
+            raw.extend(inner);
+            raw
+        };
+        let block_id = BlockID(Digest::from_exact([7; 32]));
+        let capabilities = [
+            [0, 1, 0, 2].as_slice(),
+            &[8, 0, 0, 0, 1, 0, 2, 0, 3, 0, 4, 1, 0, 1, 1, 1, 2],
+            &[2, 0, 0, 0, 1],
+            &[0x80, 0x40, 0, 0],
+        ]
+        .concat();
+        let cases = [
+            (Message::Padding(2), framed(0x0000, &[0, 0])),
+            (Message::Hello(Capabilities::local()), framed(0x0004, &capabilities)),
+            (Message::HaveBlock(Block::from_slice(b"abc").unwrap()), framed(0x0100, b"\x03abc")),
+            (Message::DontWantBlock(block_id), framed(0x0102, &[7; 32])),
+            (
+                Message::Unknown { code: 0x8000, payload: Bytes::from_static(b"xyz") },
+                framed(0x8000, b"xyz"),
+            ),
+        ];
+        for (message, expected) in cases {
+            let raw = util::serialize_as(&message, WireFormat::V2).unwrap();
+            assert_eq!(expected, raw.as_ref(), "{message}");
+            assert_eq!(expected.len(), message.wire_size(WireFormat::V2));
+            let decoded = util::deserialize_as::<Message>(raw.freeze(), WireFormat::V2).unwrap();
+            assert_eq!(message.as_printed(), decoded.as_printed());
         }
     }
-    fn wire_skip(buffer: &mut dyn Buf) -> Result<()> {
-        u16::wire_skip(buffer)?;
-        let _ = util::varlen_skip(buffer)?;
-        Ok(())
-    }
-}
 
-impl Serializable for Message {
-    fn wire_size(&self) -> usize {
-        use util::{nested_wire_size, varlen_wire_size};
-        u16::WIRE_SIZE.strict_add(match self {
-            Self::Padding(len) => varlen_wire_size(*len),
-            Self::Attestation(att) => nested_wire_size(att),
-            Self::Envelope(env) => nested_wire_size(env),
-            Self::HaveBlock(block) => nested_wire_size(block),
-            Self::WantBlock(desire) => nested_wire_size(desire),
-            Self::DontWantBlock(id) => nested_wire_size(id),
-        })
+    #[test]
+    fn decode_shares_blocks() {
+        let block = Block::from_slice(&[7; 1000]).unwrap();
+        let messages = Messages(Vec::from([Message::HaveBlock(block.clone())]));
+        let raw = util::serialize(&messages).unwrap().freeze();
+        let decoded = util::deserialize::<Messages>(raw.clone()).unwrap();
+        let Message::HaveBlock(decoded) = &decoded.0[0] else {
+            panic!("{decoded}");
+        };
+        assert_eq!(block.as_slice(), decoded.as_slice());
+        assert!(raw.as_ptr_range().contains(&decoded.as_slice().as_ptr()));
     }
 }
-
-impl Printable for Message {
-    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
-        match self {
-            Self::Padding(len) => write!(writer, "Padding({})", len),
-            Self::Attestation(att) => write!(writer, "Attestation({})", att),
-            Self::Envelope(env) => write!(writer, "Envelope({})", env),
-            Self::HaveBlock(block) => write!(writer, "HaveBlock({})", block),
-            Self::WantBlock(desire) => write!(writer, "WantBlock({})", desire),
-            Self::DontWantBlock(id) => write!(writer, "DontWantBlock({})", id),
-        }
-    }
-}
-
-impl_debug_for_printable!(Message);
-impl_display_for_printable!(Message);
-
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
-pub struct DesireBlockID {
-    pub block_id: BlockID,
-    pub priority: Priority,
-}
-
-impl_serializable_for_struct!(DesireBlockID { block_id: BlockID, priority: Priority }, fixed size);
-impl_printable_for_struct!(DesireBlockID { block_id, priority });
-impl_debug_for_printable!(DesireBlockID);
-impl_display_for_printable!(DesireBlockID);
@@ -0,0 +1,249 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use dandelion_wire::{util, ErrorKind, Result, Serializable, WireFormat};
+
+use super::{Message, Messages};
+
+pub const DEFAULT_MIN_SIZE: usize = 1024;
+
+/// How a link hides the true size of each [`Messages`] batch.  Padding is appended as a single
+/// [`Message::Padding`] so that the serialized batch lands exactly on a permitted size; since
+/// encryption adds a fixed overhead, the ciphertext length then reveals only the size class.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum PaddingPolicy {
+    /// Leaves batches unpadded.  Only suitable where length leaks are acceptable.
+    None,
+    /// Rounds up to the next power of two, and to at least `min` bytes.
+    PowerOfTwo { min: usize },
+    /// Rounds up to the smallest listed size, or beyond the largest to a multiple of it.  Sizes
+    /// may be listed in any order; a list whose largest size is zero permits nothing.
+    SizeClasses(Vec<usize>),
+    /// Every batch is exactly `size` bytes.  Use with [`MessagesBuilder`] sending one batch per
+    /// tick, so that the timing of traffic leaks nothing either.
+    ConstantRate { size: usize },
+}
+
+impl Default for PaddingPolicy {
+    fn default() -> Self {
+        Self::PowerOfTwo { min: DEFAULT_MIN_SIZE }
+    }
+}
+
+impl PaddingPolicy {
+    /// The smallest permitted batch size that is at least `size`.
+    pub fn target(&self, size: usize) -> Option<usize> {
+        match self {
+            Self::None => Some(size),
+            Self::PowerOfTwo { min } => size.max(*min).checked_next_power_of_two(),
+            Self::SizeClasses(classes) => {
+                let largest = classes.iter().copied().max().filter(|&largest| largest > 0)?;
+                match classes.iter().copied().filter(|&class| class >= size).min() {
+                    Some(class) => Some(class),
+                    None => size.div_ceil(largest).checked_mul(largest),
+                }
+            },
+            Self::ConstantRate { size: rate } => (size <= *rate).then_some(*rate),
+        }
+    }
+
+    /// Appends padding so that `messages` serializes to a permitted size in `format`.  Fails if no
+    /// permitted size can hold the batch.
+    pub fn pad(&self, messages: &mut Messages, format: WireFormat) -> Result<()> {
+        let size = messages.wire_size(format);
+        let mut at_least = size;
+        loop {
+            let target = self.target(at_least).ok_or(ErrorKind::OutOfRange)?;
+            if target == size {
+                return Ok(());
+            }
+            // A gap smaller than an empty Padding message cannot be filled; try the next size.  With
+            // varint lengths the padding's own prefix and the batch's count can grow as well, so a
+            // few lengths short of the gap are tried too.
+            let overhead = Message::Padding(0).wire_size(format);
+            let gap = target.strict_sub(size);
+            for shortfall in 0..=2 * util::MAX_LEN_WIRE_SIZE {
+                let Some(len) = gap.checked_sub(overhead.strict_add(shortfall)) else {
+                    break;
+                };
+                messages.0.push(Message::Padding(len));
+                if messages.wire_size(format) == target {
+                    return Ok(());
+                }
+                messages.0.pop();
+            }
+            at_least = target.checked_add(1).ok_or(ErrorKind::OutOfRange)?;
+        }
+    }
+}
+
+/// Queues outgoing messages for one link and turns them into padded batches.
+pub struct MessagesBuilder {
+    policy: PaddingPolicy,
+    format: WireFormat,
+    queue: VecDeque<Message>,
+}
+
+impl MessagesBuilder {
+    /// Sizes batches in [`WireFormat::V1`] until told otherwise.
+    pub fn new(policy: PaddingPolicy) -> Self {
+        Self { policy, format: WireFormat::V1, queue: VecDeque::new() }
+    }
+
+    pub fn policy(&self) -> &PaddingPolicy {
+        &self.policy
+    }
+
+    pub fn format(&self) -> WireFormat {
+        self.format
+    }
+
+    /// Should match the [`Channel`](crate::Channel) the batches are written to.
+    pub fn set_format(&mut self, format: WireFormat) {
+        self.format = format;
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Fails if `message` could never fit in a batch under a constant-rate policy.
+    pub fn push(&mut self, message: Message) -> Result<()> {
+        if let PaddingPolicy::ConstantRate { .. } = self.policy {
+            self.policy.pad(&mut Messages(Vec::from([message.clone()])), self.format)?;
+        }
+        self.queue.push_back(message);
+        Ok(())
+    }
+
+    /// Produces the next padded batch.  Under a constant-rate policy this always returns a batch,
+    /// carrying as many queued messages as fit (possibly none); otherwise it drains the queue and
+    /// returns `None` if there was nothing to send.
+    pub fn next_batch(&mut self) -> Result<Option<Messages>> {
+        let mut messages = Messages(Vec::new());
+        match self.policy {
+            PaddingPolicy::ConstantRate { .. } => {
+                while let Some(message) = self.queue.pop_front() {
+                    messages.0.push(message);
+                    let mut trial = messages.clone();
+                    if self.policy.pad(&mut trial, self.format).is_err() {
+                        let message = messages.0.pop().unwrap();
+                        self.queue.push_front(message);
+                        break;
+                    }
+                }
+            },
+            _ if self.queue.is_empty() => return Ok(None),
+            _ => messages.0.extend(self.queue.drain(..)),
+        }
+        self.policy.pad(&mut messages, self.format)?;
+        Ok(Some(messages))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dandelion_wire::cryptography::digest::Digest;
+    use dandelion_wire::PublicBytes;
+
+    use super::*;
+    use crate::{Block, BlockID};
+
+    fn dont_want(fill: u8) -> Message {
+        Message::DontWantBlock(BlockID(Digest::from_exact([fill; 32])))
+    }
+
+    fn padded(policy: &PaddingPolicy, messages: Vec<Message>) -> usize {
+        let mut messages = Messages(messages);
+        policy.pad(&mut messages, WireFormat::V1).unwrap();
+        messages.wire_size(WireFormat::V1)
+    }
+
+    #[test]
+    fn padding_power_of_two() {
+        let policy = PaddingPolicy::PowerOfTwo { min: 64 };
+        assert_eq!(64, padded(&policy, Vec::new()));
+        assert_eq!(64, padded(&policy, Vec::from([dont_want(1)])));
+        let block = Block::from_slice(&[7; 100]).unwrap();
+        assert_eq!(128, padded(&policy, Vec::from([Message::HaveBlock(block)])));
+
+        // 61 bytes leaves a 3-byte gap to 64, too small for a Padding message.
+        let block = Block::from_slice(&[7; 47]).unwrap();
+        assert_eq!(
+            61,
+            Messages(Vec::from([Message::HaveBlock(block.clone())])).wire_size(WireFormat::V1)
+        );
+        assert_eq!(128, padded(&policy, Vec::from([Message::HaveBlock(block)])));
+    }
+
+    #[test]
+    fn padding_size_classes() {
+        let policy = PaddingPolicy::SizeClasses(Vec::from([100, 500]));
+        assert_eq!(100, padded(&policy, Vec::from([dont_want(1)])));
+        let block = Block::from_slice(&[7; 200]).unwrap();
+        assert_eq!(500, padded(&policy, Vec::from([Message::HaveBlock(block)])));
+        let block = Block::from_slice(&[7; 700]).unwrap();
+        assert_eq!(1000, padded(&policy, Vec::from([Message::HaveBlock(block)])));
+        assert!(PaddingPolicy::SizeClasses(Vec::new())
+            .pad(&mut Messages(Vec::new()), WireFormat::V1)
+            .is_err());
+    }
+
+    #[test]
+    fn padding_size_classes_unsorted() {
+        let policy = PaddingPolicy::SizeClasses(Vec::from([500, 0, 100]));
+        assert_eq!(Some(100), policy.target(50));
+        assert_eq!(Some(500), policy.target(101));
+        assert_eq!(Some(1000), policy.target(501));
+        assert_eq!(100, padded(&policy, Vec::from([dont_want(1)])));
+        assert_eq!(None, PaddingPolicy::SizeClasses(Vec::from([0])).target(5));
+    }
+
+    #[test]
+    fn padding_constant_rate() {
+        let mut builder = MessagesBuilder::new(PaddingPolicy::ConstantRate { size: 128 });
+        assert_eq!(128, builder.next_batch().unwrap().unwrap().wire_size(WireFormat::V1));
+
+        for fill in 0..5 {
+            builder.push(dont_want(fill)).unwrap();
+        }
+        let block = Block::from_slice(&[7; 200]).unwrap();
+        assert!(builder.push(Message::HaveBlock(block)).is_err());
+
+        let mut sent = 0;
+        while builder.pending() > 0 {
+            let batch = builder.next_batch().unwrap().unwrap();
+            assert_eq!(128, batch.wire_size(WireFormat::V1));
+            sent += batch.0.iter().filter(|m| matches!(m, Message::DontWantBlock(_))).count();
+        }
+        assert_eq!(5, sent);
+    }
+
+    #[test]
+    fn padding_builder_drains_queue() {
+        let mut builder = MessagesBuilder::new(PaddingPolicy::default());
+        assert!(builder.next_batch().unwrap().is_none());
+        builder.push(dont_want(1)).unwrap();
+        builder.push(dont_want(2)).unwrap();
+        let batch = builder.next_batch().unwrap().unwrap();
+        assert_eq!(DEFAULT_MIN_SIZE, batch.wire_size(WireFormat::V1));
+        assert_eq!(3, batch.0.len());
+        assert_eq!(0, builder.pending());
+    }
+
+    #[test]
+    fn padding_varint_lengths() {
+        let policy = PaddingPolicy::SizeClasses(Vec::from([40, 100, 20_000]));
+        for size in [40, 100, 20_000] {
+            for count in [0, 1, 63, 64] {
+                let mut messages = Messages((0..count).map(|_| Message::Padding(0)).collect());
+                if messages.wire_size(WireFormat::V2) > size {
+                    continue;
+                }
+                let target = policy.target(messages.wire_size(WireFormat::V2)).unwrap();
+                policy.pad(&mut messages, WireFormat::V2).unwrap();
+                assert_eq!(target, messages.wire_size(WireFormat::V2));
+            }
+        }
+    }
+}
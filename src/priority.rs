@@ -1,6 +1,10 @@
-impl_enum!(Priority repr u8 {
-    Least = [LEAST, "Least", 0],
-    Low = [LOW, "Low", 1],
-    Medium = [MEDIUM, "Medium", 2],
-    High = [HIGH, "High", 3],
-});
+use dandelion_wire::{Printable, Serializable};
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Serializable, Printable)]
+#[repr(u8)]
+pub enum Priority {
+    Least = 0,
+    Low = 1,
+    Medium = 2,
+    High = 3,
+}
@@ -0,0 +1,69 @@
+#[cfg(test)]
+use alloc::vec::Vec;
+
+use dandelion_wire::rand_core::{impls, Error, RngCore};
+
+/// SplitMix64.  Fast, seedable and identical on every platform, for anything that needs
+/// reproducible randomness: fixed tables, tests and simulations.  It is not cryptographically
+/// secure, so it deliberately does not implement `CryptoRng`.
+#[derive(Clone, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns the next value and the advanced generator, for use in `const` contexts.
+    pub const fn step(self) -> (u64, Self) {
+        let state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31), Self { state })
+    }
+}
+
+impl RngCore for SplitMix64 {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let (value, next) = self.clone().step();
+        *self = next;
+        value
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// A uniformly distributed value in `0..bound`, which must not be zero.  Plain `% bound` would
+/// favour small values whenever `bound` does not divide 2^64.
+pub fn below(rng: &mut dyn RngCore, bound: u64) -> u64 {
+    // Rejecting the 2^64 mod `bound` smallest values leaves a whole number of copies of the range.
+    let threshold = bound.wrapping_neg() % bound;
+    loop {
+        let value = rng.next_u64();
+        if value >= threshold {
+            return value % bound;
+        }
+    }
+}
+
+/// Reproducible pseudo-random bytes, for tests that need data without structure.
+#[cfg(test)]
+pub(crate) fn sample(len: usize) -> Vec<u8> {
+    let mut data = alloc::vec![0; len];
+    SplitMix64::new(1).fill_bytes(&mut data);
+    data
+}
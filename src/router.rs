@@ -0,0 +1,471 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+
+use dandelion_wire::cryptography::digest::{Digest, RawDigest};
+use dandelion_wire::rand_core::RngCore;
+use dandelion_wire::{ErrorKind, PublicBytes, Result};
+
+use super::rng::below;
+use super::{Duration, Entity, Envelope, EnvelopeID, Instant, Message};
+
+pub const DEFAULT_EPOCH_LENGTH: Duration = Duration::from_minutes(10);
+pub const DEFAULT_FLUFF_PERCENT: u32 = 10;
+pub const DEFAULT_EMBARGO: Duration = Duration::from_seconds(30);
+pub const DEFAULT_EMBARGO_JITTER: Duration = Duration::from_seconds(15);
+pub const DEFAULT_MEMORY: Duration = Duration::from_minutes(10);
+pub const DEFAULT_MAX_TRACKED: usize = 1 << 14;
+
+/// Each epoch picks up to this many outbound relays.  Inbound peers are spread across them, so
+/// that stems from different peers follow different lines through the graph.
+pub const RELAYS_PER_EPOCH: usize = 2;
+
+/// A Dandelion++ router for [`Envelope`]s.  Feed it every envelope-related [`Message`] received
+/// from a peer with [`Router::receive`], call [`Router::tick`] no later than
+/// [`Router::next_deadline`], and send whatever [`Router::poll`] returns for each peer.
+///
+/// Envelopes start in the stem phase ([`Message::StemEnvelope`]), passing along a single line of
+/// relays chosen afresh every epoch.  In each epoch a node becomes a diffuser with probability
+/// `fluff_percent`, and then fluffs every stem it receives: it switches to [`Message::Envelope`]
+/// and sends the envelope to all peers.  Every node on the stem arms an embargo timer and fluffs
+/// the envelope itself if it has not seen the fluff by the time it fires, so a relay that drops
+/// stems cannot black-hole them.
+///
+/// The router does no I/O and reads neither the clock nor the system RNG, so a network of them
+/// can be simulated deterministically.
+pub struct Router {
+    pub epoch_length: Duration,
+    pub fluff_percent: u32,
+    /// Embargo timers fire after this long plus up to `embargo_jitter`.
+    pub embargo: Duration,
+    pub embargo_jitter: Duration,
+    /// How long an envelope is remembered once fluffed, to suppress duplicates.
+    pub memory: Duration,
+    /// At most this many envelopes are remembered.  Beyond that, the one whose timer is due
+    /// soonest is let go early: fluffed if it is still being stemmed, and then forgotten.
+    pub max_tracked: usize,
+    peers: Vec<Peer>,
+    epoch: Option<Epoch>,
+    envelopes: BTreeMap<RawDigest, Tracked>,
+    /// Every tracked envelope's next timer, soonest first.
+    timers: BTreeSet<(Instant, RawDigest)>,
+}
+
+struct Peer {
+    entity: Entity,
+    outbox: Vec<Message>,
+}
+
+struct Epoch {
+    ends: Instant,
+    diffuser: bool,
+    relays: Vec<Entity>,
+    routes: Vec<(Entity, Entity)>,
+}
+
+struct Tracked {
+    envelope: Envelope,
+    phase: Phase,
+}
+
+#[derive(Clone, Copy)]
+enum Phase {
+    Stem { embargo: Instant },
+    Fluff { forget: Instant },
+}
+
+impl Phase {
+    fn timer(self) -> Instant {
+        match self {
+            Self::Stem { embargo } => embargo,
+            Self::Fluff { forget } => forget,
+        }
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self {
+            epoch_length: DEFAULT_EPOCH_LENGTH,
+            fluff_percent: DEFAULT_FLUFF_PERCENT,
+            embargo: DEFAULT_EMBARGO,
+            embargo_jitter: DEFAULT_EMBARGO_JITTER,
+            memory: DEFAULT_MEMORY,
+            max_tracked: DEFAULT_MAX_TRACKED,
+            peers: Vec::new(),
+            epoch: None,
+            envelopes: BTreeMap::new(),
+            timers: BTreeSet::new(),
+        }
+    }
+
+    pub fn connect(&mut self, entity: Entity) {
+        if self.peer(&entity).is_ok() {
+            return;
+        }
+        self.peers.push(Peer { entity, outbox: Vec::new() });
+    }
+
+    /// Stops tracking `entity`.  If it was one of our relays, the epoch ends early.
+    pub fn disconnect(&mut self, entity: &Entity) {
+        self.peers.retain(|peer| peer.entity != *entity);
+        if let Some(epoch) = &mut self.epoch {
+            if epoch.relays.contains(entity) {
+                self.epoch = None;
+            } else {
+                epoch.routes.retain(|(from, _)| from != entity);
+            }
+        }
+    }
+
+    /// Whether we are fluffing every stem we receive in the current epoch.
+    pub fn is_diffuser(&self) -> bool {
+        self.epoch.as_ref().is_some_and(|epoch| epoch.diffuser)
+    }
+
+    /// Whether `id` is still being stemmed under an embargo timer.
+    pub fn is_embargoed(&self, id: &EnvelopeID) -> bool {
+        self.tracked(id).is_some_and(|tracked| matches!(tracked.phase, Phase::Stem { .. }))
+    }
+
+    /// Starts propagating an envelope we created.  Our own envelopes are always stemmed, even in
+    /// a diffuser epoch.
+    pub fn originate(&mut self, envelope: Envelope, now: Instant, rng: &mut dyn RngCore) {
+        let id = envelope.compute_id();
+        if self.tracked(&id).is_some() {
+            return;
+        }
+        self.roll_epoch(now, rng);
+        self.stem(id, envelope, None, now, rng);
+    }
+
+    /// Handles a message from `entity`.  Returns the envelope the first time it is seen, so the
+    /// caller can check whether it is addressed to us; non-envelope messages are ignored.
+    pub fn receive(
+        &mut self,
+        entity: &Entity,
+        message: &Message,
+        now: Instant,
+        rng: &mut dyn RngCore,
+    ) -> Result<Option<Envelope>> {
+        self.peer(entity)?;
+        let (envelope, stem) = match message {
+            Message::StemEnvelope(envelope) => (envelope, true),
+            Message::Envelope(envelope) => (envelope, false),
+            _ => return Ok(None),
+        };
+        let id = envelope.compute_id();
+        let phase = self.tracked(&id).map(|tracked| tracked.phase);
+        match (phase, stem) {
+            (Some(Phase::Fluff { .. }), _) | (Some(Phase::Stem { .. }), true) => Ok(None),
+            (Some(Phase::Stem { .. }), false) => {
+                self.fluff(&id, Some(entity), now);
+                Ok(None)
+            },
+            (None, true) => {
+                self.roll_epoch(now, rng);
+                if self.is_diffuser() {
+                    self.track(id, envelope.clone(), Phase::Stem { embargo: now });
+                    self.fluff(&id, Some(entity), now);
+                } else {
+                    self.stem(id, envelope.clone(), Some(entity), now, rng);
+                }
+                Ok(Some(envelope.clone()))
+            },
+            (None, false) => {
+                self.track(id, envelope.clone(), Phase::Stem { embargo: now });
+                self.fluff(&id, Some(entity), now);
+                Ok(Some(envelope.clone()))
+            },
+        }
+    }
+
+    /// Rotates the epoch, fluffs envelopes whose embargo has expired, and forgets old ones.
+    pub fn tick(&mut self, now: Instant, rng: &mut dyn RngCore) {
+        self.roll_epoch(now, rng);
+        while let Some(&(timer, raw)) = self.timers.first() {
+            if timer > now {
+                break;
+            }
+            self.expire(raw, now);
+        }
+    }
+
+    /// The next time [`Router::tick`] has work to do, if any.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let timer = self.timers.first().map(|(timer, _)| *timer);
+        timer.into_iter().chain(self.epoch.as_ref().map(|epoch| epoch.ends)).min()
+    }
+
+    /// Drains the messages due to `entity`.
+    pub fn poll(&mut self, entity: &Entity) -> Result<Vec<Message>> {
+        Ok(core::mem::take(&mut self.peer_mut(entity)?.outbox))
+    }
+
+    fn roll_epoch(&mut self, now: Instant, rng: &mut dyn RngCore) {
+        if self.epoch.as_ref().is_some_and(|epoch| epoch.ends > now) {
+            return;
+        }
+        let diffuser = below(rng, 100) < u64::from(self.fluff_percent);
+        let mut candidates: Vec<Entity> = self.peers.iter().map(|peer| peer.entity).collect();
+        let mut relays = Vec::new();
+        while relays.len() < RELAYS_PER_EPOCH && !candidates.is_empty() {
+            relays.push(candidates.swap_remove(pick(rng, candidates.len())));
+        }
+        let ends = now + self.epoch_length;
+        self.epoch = Some(Epoch { ends, diffuser, relays, routes: Vec::new() });
+    }
+
+    /// The relay for stems arriving from `from`, or for our own if `None`.  Inbound peers are
+    /// mapped to a relay other than themselves where possible, and keep it for the epoch.
+    fn relay(&mut self, from: Option<&Entity>, rng: &mut dyn RngCore) -> Option<Entity> {
+        let epoch = self.epoch.as_mut()?;
+        let Some(from) = from else {
+            return epoch.relays.first().copied();
+        };
+        if let Some((_, relay)) = epoch.routes.iter().find(|(inbound, _)| inbound == from) {
+            return Some(*relay);
+        }
+        let others: Vec<Entity> =
+            epoch.relays.iter().filter(|relay| *relay != from).copied().collect();
+        let relay = match others.is_empty() {
+            true => *epoch.relays.first()?,
+            false => others[pick(rng, others.len())],
+        };
+        epoch.routes.push((*from, relay));
+        Some(relay)
+    }
+
+    fn stem(
+        &mut self,
+        id: EnvelopeID,
+        envelope: Envelope,
+        from: Option<&Entity>,
+        now: Instant,
+        rng: &mut dyn RngCore,
+    ) {
+        let jitter = self.embargo_jitter.as_nanoseconds();
+        let jitter = match jitter > 0 {
+            true => Duration::from_nanoseconds(below(rng, jitter as u64) as i64),
+            false => Duration::ZERO,
+        };
+        let embargo = now + self.embargo + jitter;
+        let relay = self.relay(from, rng);
+        self.track(id, envelope.clone(), Phase::Stem { embargo });
+        match relay.and_then(|relay| self.peer_mut(&relay).ok()) {
+            Some(peer) => peer.outbox.push(Message::StemEnvelope(envelope)),
+            None => self.fluff(&id, from, now),
+        }
+    }
+
+    /// Sends a tracked envelope to every peer except `except`, and stops its embargo timer.
+    fn fluff(&mut self, id: &EnvelopeID, except: Option<&Entity>, now: Instant) {
+        let forget = now + self.memory;
+        let raw = id.0.into_exact();
+        let Some(tracked) = self.envelopes.get_mut(&raw) else {
+            return;
+        };
+        self.timers.remove(&(tracked.phase.timer(), raw));
+        tracked.phase = Phase::Fluff { forget };
+        self.timers.insert((forget, raw));
+        for peer in &mut self.peers {
+            if Some(&peer.entity) != except {
+                peer.outbox.push(Message::Envelope(tracked.envelope.clone()));
+            }
+        }
+    }
+
+    /// Fluffs the envelope if it is still being stemmed, or else forgets it.
+    fn expire(&mut self, raw: RawDigest, now: Instant) {
+        match self.envelopes.get(&raw).map(|tracked| tracked.phase) {
+            Some(Phase::Stem { .. }) => self.fluff(&EnvelopeID(Digest::from_exact(raw)), None, now),
+            Some(Phase::Fluff { .. }) => self.forget(raw),
+            None => {},
+        }
+    }
+
+    fn forget(&mut self, raw: RawDigest) {
+        if let Some(tracked) = self.envelopes.remove(&raw) {
+            self.timers.remove(&(tracked.phase.timer(), raw));
+        }
+    }
+
+    fn track(&mut self, id: EnvelopeID, envelope: Envelope, phase: Phase) {
+        let raw = id.0.into_exact();
+        self.forget(raw);
+        // Make room by letting go of whatever is due soonest, so that a flood of new envelopes
+        // costs at most some stems fluffed early and some duplicates not recognized.
+        while self.envelopes.len() >= self.max_tracked.max(1) {
+            let Some(&(timer, oldest)) = self.timers.first() else {
+                break;
+            };
+            self.expire(oldest, timer);
+            self.forget(oldest);
+        }
+        self.timers.insert((phase.timer(), raw));
+        self.envelopes.insert(raw, Tracked { envelope, phase });
+    }
+
+    fn tracked(&self, id: &EnvelopeID) -> Option<&Tracked> {
+        self.envelopes.get(id.0.as_exact())
+    }
+
+    fn peer(&self, entity: &Entity) -> Result<&Peer> {
+        self.peers.iter().find(|peer| peer.entity == *entity).ok_or(ErrorKind::UnknownPeer.into())
+    }
+
+    fn peer_mut(&mut self, entity: &Entity) -> Result<&mut Peer> {
+        self.peers
+            .iter_mut()
+            .find(|peer| peer.entity == *entity)
+            .ok_or(ErrorKind::UnknownPeer.into())
+    }
+}
+
+fn pick(rng: &mut dyn RngCore, len: usize) -> usize {
+    below(rng, len as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use dandelion_wire::cryptography::sig::PublicKey;
+    use dandelion_wire::PublicBytes;
+
+    use super::*;
+    use crate::{EntityType, SplitMix64};
+
+    fn entity(fill: u8) -> Entity {
+        Entity { entity_type: EntityType::Node, public_key: PublicKey::from_exact([fill; 32]) }
+    }
+
+    fn envelope(fill: u8) -> Envelope {
+        Envelope {
+            sender: entity(100),
+            recipient: entity(101),
+            payload: dandelion_wire::Encrypted {
+                nonce: PublicBytes::from_exact([fill; 24]),
+                ciphertext: Default::default(),
+                tag: Default::default(),
+            },
+        }
+    }
+
+    fn router(peers: &[Entity], fluff_percent: u32) -> Router {
+        let mut router = Router::new();
+        router.fluff_percent = fluff_percent;
+        for peer in peers {
+            router.connect(*peer);
+        }
+        router
+    }
+
+    #[test]
+    fn router_stems_to_one_relay_per_epoch() {
+        let peers = [entity(1), entity(2), entity(3), entity(4)];
+        let mut rng = SplitMix64::new(1);
+        let mut router = router(&peers, 0);
+        let now = Instant::ZERO;
+        for fill in 0..4 {
+            router.originate(envelope(fill), now, &mut rng);
+        }
+        let counts: Vec<usize> =
+            peers.iter().map(|peer| router.poll(peer).unwrap().len()).collect();
+        assert_eq!(Vec::from([4]), counts.iter().copied().filter(|&n| n > 0).collect::<Vec<_>>());
+
+        // Stems from the same inbound peer follow the same route within an epoch.
+        let message = Message::StemEnvelope(envelope(9));
+        assert!(router.receive(&peers[0], &message, now, &mut rng).unwrap().is_some());
+        assert!(router.receive(&peers[0], &message, now, &mut rng).unwrap().is_none());
+        router.receive(&peers[0], &Message::StemEnvelope(envelope(10)), now, &mut rng).unwrap();
+        let routed: Vec<usize> =
+            peers.iter().map(|peer| router.poll(peer).unwrap().len()).collect();
+        assert_eq!(0, routed[0]);
+        assert_eq!(Vec::from([2]), routed.iter().copied().filter(|&n| n > 0).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn router_diffuser_fluffs() {
+        let peers = [entity(1), entity(2), entity(3)];
+        let mut rng = SplitMix64::new(1);
+        let mut router = router(&peers, 100);
+        let message = Message::StemEnvelope(envelope(1));
+        router.receive(&peers[0], &message, Instant::ZERO, &mut rng).unwrap();
+        assert!(router.is_diffuser());
+        assert!(router.poll(&peers[0]).unwrap().is_empty());
+        for peer in &peers[1..] {
+            let messages = router.poll(peer).unwrap();
+            assert!(matches!(messages.as_slice(), [Message::Envelope(_)]));
+        }
+        assert!(router.receive(&entity(9), &message, Instant::ZERO, &mut rng).is_err());
+    }
+
+    #[test]
+    fn router_embargo_fails_safe() {
+        let peers = [entity(1), entity(2)];
+        let mut rng = SplitMix64::new(7);
+        let mut router = router(&peers, 0);
+        let id = envelope(1).compute_id();
+        router.originate(envelope(1), Instant::ZERO, &mut rng);
+        assert!(router.is_embargoed(&id));
+        let stemmed: usize = peers.iter().map(|peer| router.poll(peer).unwrap().len()).sum();
+        assert_eq!(1, stemmed);
+
+        let deadline = router.next_deadline().unwrap();
+        assert!(deadline >= Instant::ZERO + DEFAULT_EMBARGO);
+        assert!(deadline < Instant::ZERO + DEFAULT_EMBARGO + DEFAULT_EMBARGO_JITTER);
+        router.tick(deadline - Duration::from_nanoseconds(1), &mut rng);
+        assert!(router.is_embargoed(&id));
+        router.tick(deadline, &mut rng);
+        assert!(!router.is_embargoed(&id));
+        for peer in &peers {
+            assert!(matches!(router.poll(peer).unwrap().as_slice(), [Message::Envelope(_)]));
+        }
+
+        // Seeing the fluff cancels the timer; the fluff is passed on to everyone else.
+        let id = envelope(2).compute_id();
+        router.originate(envelope(2), deadline, &mut rng);
+        router.receive(&peers[0], &Message::Envelope(envelope(2)), deadline, &mut rng).unwrap();
+        assert!(!router.is_embargoed(&id));
+        assert!(router.poll(&peers[0]).unwrap().len() <= 1);
+        assert!(matches!(router.poll(&peers[1]).unwrap().last(), Some(Message::Envelope(_))));
+
+        // Fluffed envelopes are forgotten once their memory runs out.
+        let later = deadline + DEFAULT_MEMORY;
+        router.tick(later, &mut rng);
+        let message = Message::Envelope(envelope(1));
+        assert!(router.receive(&peers[0], &message, later, &mut rng).unwrap().is_some());
+    }
+
+    #[test]
+    fn router_caps_tracked_envelopes() {
+        let peers = [entity(1), entity(2)];
+        let mut rng = SplitMix64::new(3);
+        let mut router = router(&peers, 0);
+        router.max_tracked = 2;
+        router.embargo_jitter = Duration::ZERO;
+        for (seconds, fill) in [(0, 1), (1, 2), (2, 3)] {
+            router.originate(
+                envelope(fill),
+                Instant::ZERO + Duration::from_seconds(seconds),
+                &mut rng,
+            );
+        }
+        assert_eq!(2, router.envelopes.len());
+        assert_eq!(2, router.timers.len());
+        assert!(!router.is_embargoed(&envelope(1).compute_id()));
+        assert!(router.is_embargoed(&envelope(3).compute_id()));
+
+        // The evicted stem was fluffed rather than dropped.
+        let fluffed = peers
+            .iter()
+            .flat_map(|peer| router.poll(peer).unwrap())
+            .filter(|message| matches!(message, Message::Envelope(_)))
+            .count();
+        assert_eq!(2, fluffed);
+    }
+}
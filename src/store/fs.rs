@@ -0,0 +1,251 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use dandelion_wire::bytes::Bytes;
+use dandelion_wire::cryptography::digest::{Digest, DIGEST_SIZE};
+use dandelion_wire::{Error, ErrorKind, PublicBytes, Result};
+
+use super::BlockStore;
+use crate::block::BLOCK_SIZE;
+use crate::{Block, BlockID};
+
+const SUFFIX: &str = ".tmp";
+
+/// Stores each block in its own file, named by the hex [`BlockID`] and fanned out into 256
+/// subdirectories by its first byte.  Writes go to a temporary file which is renamed into place,
+/// so readers never observe a partial block.  Blocks are re-verified on every read.
+pub struct FsBlockStore {
+    root: PathBuf,
+    counter: u32,
+}
+
+impl FsBlockStore {
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root).map_err(io_error)?;
+        Ok(Self { root, counter: 0 })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The fan-out directory holding `id`, and the file within it.
+    fn paths_of(&self, id: &BlockID) -> (PathBuf, PathBuf) {
+        let name = hex(id);
+        let dir = self.root.join(&name[..2]);
+        let path = dir.join(name);
+        (dir, path)
+    }
+
+    fn path_of(&self, id: &BlockID) -> PathBuf {
+        self.paths_of(id).1
+    }
+
+    fn next_counter(&mut self) -> u32 {
+        let result = self.counter;
+        self.counter = self.counter.wrapping_add(1);
+        result
+    }
+}
+
+impl BlockStore for FsBlockStore {
+    fn contains(&self, id: &BlockID) -> Result<bool> {
+        match fs::metadata(self.path_of(id)) {
+            Ok(meta) => Ok(meta.is_file()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(io_error(err)),
+        }
+    }
+
+    fn get(&self, id: &BlockID) -> Result<Option<Block>> {
+        let file = match File::open(self.path_of(id)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(io_error(err)),
+        };
+        let mut data = Vec::new();
+        let limit = BLOCK_SIZE.strict_add(1) as u64;
+        file.take(limit).read_to_end(&mut data).map_err(io_error)?;
+        let block = Block::new(Bytes::from(data))?;
+        if BlockID::compute_from(&block) != *id {
+            return Err(ErrorKind::DigestMismatch.into());
+        }
+        Ok(Some(block))
+    }
+
+    fn insert(&mut self, id: &BlockID, block: &Block) -> Result<()> {
+        if BlockID::compute_from(block) != *id {
+            return Err(ErrorKind::DigestMismatch.into());
+        }
+        let (dir, final_path) = self.paths_of(id);
+        if final_path.is_file() {
+            return Ok(());
+        }
+        if !dir.is_dir() {
+            fs::create_dir_all(&dir).map_err(io_error)?;
+            sync_dir(&self.root)?;
+        }
+
+        let pid = process::id();
+        let mut tmp_path;
+        let mut file = loop {
+            let counter = self.next_counter();
+            tmp_path = dir.join(format!(".{}-{pid}-{counter:08x}{SUFFIX}", hex(id)));
+            match fs::OpenOptions::new().write(true).create_new(true).open(&tmp_path) {
+                Ok(f) => break f,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {},
+                Err(err) => return Err(io_error(err)),
+            }
+        };
+
+        let mut unlink_guard = UnlinkGuard::new(&tmp_path);
+
+        file.write_all(block.as_slice()).map_err(io_error)?;
+        file.sync_all().map_err(io_error)?;
+
+        fs::rename(&tmp_path, &final_path).map_err(io_error)?;
+        unlink_guard.0.take();
+        // The rename itself is only durable once the directory entry reaches the disk.
+        sync_dir(&dir)
+    }
+
+    fn remove(&mut self, id: &BlockID) -> Result<bool> {
+        match fs::remove_file(self.path_of(id)) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(io_error(err)),
+        }
+    }
+
+    fn ids(&self) -> Result<Vec<BlockID>> {
+        let mut ids = Vec::new();
+        for dir in fs::read_dir(&self.root).map_err(io_error)? {
+            let dir = dir.map_err(io_error)?;
+            if !dir.file_type().map_err(io_error)?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(dir.path()).map_err(io_error)? {
+                let entry = entry.map_err(io_error)?;
+                if let Some(id) = entry.file_name().to_str().and_then(parse_hex) {
+                    ids.push(id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+}
+
+struct UnlinkGuard<'a>(Option<&'a Path>);
+
+impl<'a> UnlinkGuard<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self(Some(path))
+    }
+}
+
+impl Drop for UnlinkGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(path) = &self.0 {
+            fs::remove_file(path).ok();
+        }
+    }
+}
+
+/// Makes changes to the entries of `dir` durable.  Only Unix can open a directory to sync it.
+fn sync_dir(dir: &Path) -> Result<()> {
+    #[cfg(unix)]
+    File::open(dir).and_then(|dir| dir.sync_all()).map_err(io_error)?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+fn io_error(err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::NotFound => ErrorKind::NotFound.into(),
+        io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied.into(),
+        _ => ErrorKind::Io.into(),
+    }
+}
+
+fn hex(id: &BlockID) -> String {
+    let mut out = String::with_capacity(DIGEST_SIZE * 2);
+    for byte in id.0.as_slice() {
+        write!(out, "{:02x}", byte).unwrap();
+    }
+    out
+}
+
+fn parse_hex(name: &str) -> Option<BlockID> {
+    if name.len() != DIGEST_SIZE * 2 {
+        return None;
+    }
+    let mut digest = Digest::zero();
+    for (byte, pair) in digest.as_slice_mut().iter_mut().zip(name.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(core::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(BlockID(digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::filled_block;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("dandelion-{name}-{}", process::id()));
+            fs::remove_dir_all(&path).ok();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn fs_store_round_trip() {
+        let dir = TempDir::new("fs-store-round-trip");
+        let mut store = FsBlockStore::open(&dir.0).unwrap();
+        let block = filled_block(1);
+        let id = store.put(&block).unwrap();
+        assert!(store.contains(&id).unwrap());
+        assert_eq!(Vec::from([id]), store.ids().unwrap());
+        assert_eq!(Some(id), parse_hex(&hex(&id)));
+
+        let mut store = FsBlockStore::open(&dir.0).unwrap();
+        assert_eq!(Some(block), store.get(&id).unwrap());
+        assert!(store.insert(&id, &filled_block(2)).is_err());
+        assert!(store.remove(&id).unwrap());
+        assert!(!store.remove(&id).unwrap());
+        assert!(store.get(&id).unwrap().is_none());
+        assert!(store.ids().unwrap().is_empty());
+
+        let err = io_error(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(ErrorKind::PermissionDenied, err.kind());
+    }
+
+    #[test]
+    fn fs_store_detects_corruption() {
+        let dir = TempDir::new("fs-store-corruption");
+        let mut store = FsBlockStore::open(&dir.0).unwrap();
+        let id = store.put(&filled_block(3)).unwrap();
+
+        fs::write(store.path_of(&id), filled_block(4).as_slice()).unwrap();
+        assert!(store.get(&id).is_err());
+        fs::write(store.path_of(&id), b"short").unwrap();
+        assert!(store.get(&id).is_err());
+    }
+}
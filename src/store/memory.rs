@@ -0,0 +1,77 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use dandelion_wire::cryptography::digest::{Digest, RawDigest};
+use dandelion_wire::{ErrorKind, PublicBytes, Result};
+
+use super::BlockStore;
+use crate::{Block, BlockID};
+
+#[derive(Default)]
+pub struct MemoryBlockStore {
+    blocks: BTreeMap<RawDigest, Block>,
+}
+
+impl MemoryBlockStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl BlockStore for MemoryBlockStore {
+    fn contains(&self, id: &BlockID) -> Result<bool> {
+        Ok(self.blocks.contains_key(id.0.as_exact()))
+    }
+
+    fn get(&self, id: &BlockID) -> Result<Option<Block>> {
+        Ok(self.blocks.get(id.0.as_exact()).cloned())
+    }
+
+    fn insert(&mut self, id: &BlockID, block: &Block) -> Result<()> {
+        if BlockID::compute_from(block) != *id {
+            return Err(ErrorKind::DigestMismatch.into());
+        }
+        self.blocks.entry(id.0.into_exact()).or_insert_with(|| block.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, id: &BlockID) -> Result<bool> {
+        Ok(self.blocks.remove(id.0.as_exact()).is_some())
+    }
+
+    fn ids(&self) -> Result<Vec<BlockID>> {
+        Ok(self.blocks.keys().map(|raw| BlockID(Digest::from_exact(*raw))).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::filled_block;
+
+    #[test]
+    fn memory_store_round_trip() {
+        let mut store = MemoryBlockStore::new();
+        let block = filled_block(1);
+        let id = store.put(&block).unwrap();
+        assert!(store.contains(&id).unwrap());
+        assert_eq!(Some(block), store.get(&id).unwrap());
+        assert_eq!(Vec::from([id]), store.ids().unwrap());
+
+        let other = filled_block(2);
+        assert!(store.insert(&id, &other).is_err());
+        assert_eq!(1, store.len());
+
+        assert!(store.remove(&id).unwrap());
+        assert!(!store.remove(&id).unwrap());
+        assert!(store.get(&id).unwrap().is_none());
+    }
+}
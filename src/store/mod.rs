@@ -0,0 +1,36 @@
+use alloc::vec::Vec;
+
+use dandelion_wire::Result;
+
+use super::{Block, BlockID};
+
+#[cfg(feature = "std")]
+pub mod fs;
+pub mod memory;
+
+#[cfg(feature = "std")]
+pub use fs::FsBlockStore;
+pub use memory::MemoryBlockStore;
+
+/// Content-addressed storage for [`Block`]s.  Implementations never hand out a block whose
+/// contents do not hash to the [`BlockID`] it was requested by.
+pub trait BlockStore {
+    fn contains(&self, id: &BlockID) -> Result<bool>;
+
+    fn get(&self, id: &BlockID) -> Result<Option<Block>>;
+
+    /// Stores `block` under `id`.  Fails without storing anything unless `id` is
+    /// [`BlockID::compute_from`] of `block`.
+    fn insert(&mut self, id: &BlockID, block: &Block) -> Result<()>;
+
+    /// Returns `false` if the block was not present.
+    fn remove(&mut self, id: &BlockID) -> Result<bool>;
+
+    fn ids(&self) -> Result<Vec<BlockID>>;
+
+    fn put(&mut self, block: &Block) -> Result<BlockID> {
+        let id = block.compute_id();
+        self.insert(&id, block)?;
+        Ok(id)
+    }
+}
@@ -0,0 +1,291 @@
+use dandelion_wire::bytes::BytesMut;
+use dandelion_wire::{
+    util,
+    BaseSerializable,
+    DecodeContext,
+    DecodeLimits,
+    Error,
+    ErrorKind,
+    FixedSizeSerializable,
+    Result,
+    WireFormat,
+};
+
+use super::{Message, Messages};
+
+/// What [`MessageDecoder::next_message`] found.
+#[derive(Debug)]
+pub enum Decoded {
+    Message(Message),
+    /// No complete message is buffered yet.  At least this many more bytes are needed before
+    /// the next one can be decoded.
+    NeedMore(usize),
+}
+
+/// Decodes a stream of [`Message`]s, each written by `wire_write`, that arrives in arbitrary
+/// chunks, e.g. from a pipe or a file.  This is for plain message streams; links between peers
+/// carry encrypted batches instead, which [`crate::Channel::read_messages`] reassembles the same
+/// way.
+///
+/// Every message starts with its code and length, so the decoder can tell a message that is still
+/// arriving from one that is corrupt.  A length over the limits' `max_total_bytes` fails before
+/// any of the message is buffered, and the decoder is unusable from then on; a message that is
+/// complete but fails to decode is dropped, and decoding carries on with the next.
+pub struct MessageDecoder {
+    format: WireFormat,
+    limits: DecodeLimits,
+    buffer: BytesMut,
+    failed: Option<Error>,
+}
+
+impl MessageDecoder {
+    /// Uses [`Messages::DECODE_LIMITS`] for each message.
+    pub fn new(format: WireFormat) -> Self {
+        Self::with_limits(format, Messages::DECODE_LIMITS)
+    }
+
+    pub fn with_limits(format: WireFormat, limits: DecodeLimits) -> Self {
+        Self { format, limits, buffer: BytesMut::new(), failed: None }
+    }
+
+    pub fn format(&self) -> WireFormat {
+        self.format
+    }
+
+    /// Applies from the next message on, e.g. once a [`Message::Hello`] exchange has agreed on a
+    /// protocol version.
+    pub fn set_format(&mut self, format: WireFormat) {
+        self.format = format;
+    }
+
+    /// The bytes received but not yet decoded.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn extend(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Reads whatever `reader` has ready into the buffer, returning how many bytes that was.  Zero
+    /// means the end of the stream; see [`MessageDecoder::finish`].
+    ///
+    /// Interrupted reads are retried.  Other errors are returned as they are, so a non-blocking
+    /// reader's [`std::io::ErrorKind::WouldBlock`] can be told apart from a failure.
+    #[cfg(feature = "std")]
+    pub fn read_from(&mut self, reader: &mut impl std::io::Read) -> std::io::Result<usize> {
+        let mut chunk = [0u8; 8192];
+        let len = loop {
+            match reader.read(&mut chunk) {
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                result => break result?,
+            }
+        };
+        self.extend(&chunk[..len]);
+        Ok(len)
+    }
+
+    /// Whether a corrupt frame has stopped the stream, after which every call fails.
+    pub fn is_failed(&self) -> bool {
+        self.failed.is_some()
+    }
+
+    /// Decodes the next buffered message, if it has arrived in full.
+    ///
+    /// An error does not necessarily end the stream: a message that arrived in full but does not
+    /// decode is dropped, and the next call carries on after it.  Only a corrupt frame, whose
+    /// length cannot be trusted, stops the stream; check [`MessageDecoder::is_failed`] to tell
+    /// the two apart.
+    pub fn next_message(&mut self) -> Result<Decoded> {
+        if let Some(err) = self.failed {
+            return Err(err);
+        }
+        let len = match self.frame_len() {
+            Ok(Ok(len)) => len,
+            Ok(Err(needed)) => return Ok(Decoded::NeedMore(needed)),
+            Err(err) => {
+                self.failed = Some(err);
+                return Err(err);
+            },
+        };
+        let frame = self.buffer.split_to(len).freeze();
+        let mut context = DecodeContext::with_limits(self.format, self.limits);
+        Ok(Decoded::Message(util::deserialize_with(frame, &mut context)?))
+    }
+
+    /// Call at the end of the stream.  Fails unless every byte received has been decoded.
+    pub fn finish(&self) -> Result<()> {
+        match self.frame_len()? {
+            _ if self.buffer.is_empty() => Ok(()),
+            Ok(_) => Err(ErrorKind::TrailingBytes { remaining: self.buffer.len() }.into()),
+            Err(needed) => {
+                Err(ErrorKind::Truncated { needed, remaining: self.buffer.len() }.into())
+            },
+        }
+    }
+
+    /// The size of the next message if all of it is buffered, or else how many more bytes are
+    /// needed to make progress.
+    fn frame_len(&self) -> Result<core::result::Result<usize, usize>> {
+        let header = u16::WIRE_SIZE;
+        let prefix = self.buffer.get(header..).unwrap_or_default();
+        let prefix_len = match self.format {
+            WireFormat::V1 => u32::WIRE_SIZE,
+            WireFormat::V2 => prefix.first().map_or(1, |first| util::varint_prefix_size(*first)),
+        };
+        let header = header.strict_add(prefix_len);
+        if self.buffer.len() < header {
+            return Ok(Err(header.strict_sub(self.buffer.len())));
+        }
+        let mut context = DecodeContext::with_limits(self.format, self.limits);
+        let len = usize::wire_read(&mut &prefix[..prefix_len], &mut context)?;
+        let max = self.limits.max_total_bytes;
+        if len > max {
+            return Err(ErrorKind::TooLarge { len, max }.into());
+        }
+        let total = header.strict_add(len);
+        if self.buffer.len() < total {
+            return Ok(Err(total.strict_sub(self.buffer.len())));
+        }
+        Ok(Ok(total))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use dandelion_wire::cryptography::digest::Digest;
+    use dandelion_wire::{Printable, PublicBytes};
+
+    use super::*;
+    use crate::{Block, BlockID};
+
+    fn sample() -> Vec<Message> {
+        Vec::from([
+            Message::Padding(3),
+            Message::HaveBlock(Block::from_slice(&[7; 300]).unwrap()),
+            Message::DontWantBlock(BlockID(Digest::from_exact([9; 32]))),
+        ])
+    }
+
+    fn drain(decoder: &mut MessageDecoder) -> (Vec<Message>, usize) {
+        let mut messages = Vec::new();
+        loop {
+            match decoder.next_message().unwrap() {
+                Decoded::Message(message) => messages.push(message),
+                Decoded::NeedMore(needed) => return (messages, needed),
+            }
+        }
+    }
+
+    #[test]
+    fn decoder_reassembles_chunks() {
+        for format in [WireFormat::V1, WireFormat::V2] {
+            let mut raw = BytesMut::new();
+            for message in sample() {
+                message.wire_write(&mut raw, format);
+            }
+            for chunk_size in [1, 7, 64, raw.len()] {
+                let mut decoder = MessageDecoder::new(format);
+                let mut decoded = Vec::new();
+                for chunk in raw.chunks(chunk_size) {
+                    decoder.extend(chunk);
+                    decoded.extend(drain(&mut decoder).0);
+                }
+                assert_eq!(sample().as_printed(), decoded.as_printed());
+                assert_eq!(0, decoder.buffered());
+                decoder.finish().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn decoder_reports_needed_bytes() {
+        let raw =
+            util::serialize(&Message::HaveBlock(Block::from_slice(&[7; 300]).unwrap())).unwrap();
+        let mut decoder = MessageDecoder::new(WireFormat::V1);
+        assert_eq!(6, drain(&mut decoder).1);
+        decoder.extend(&raw[..3]);
+        assert_eq!(3, drain(&mut decoder).1);
+        decoder.extend(&raw[3..10]);
+        assert_eq!(raw.len() - 10, drain(&mut decoder).1);
+        assert!(matches!(decoder.finish().unwrap_err().kind(), ErrorKind::Truncated { .. }));
+        decoder.extend(&raw[10..]);
+        assert_eq!(1, drain(&mut decoder).0.len());
+
+        let mut decoder = MessageDecoder::new(WireFormat::V2);
+        decoder.extend(&[0x01, 0x00]);
+        assert_eq!(1, drain(&mut decoder).1);
+        decoder.extend(&[0x80]);
+        assert_eq!(3, drain(&mut decoder).1);
+    }
+
+    #[test]
+    fn decoder_tells_corrupt_from_incomplete() {
+        let limits = DecodeLimits { max_total_bytes: 100, ..Messages::DECODE_LIMITS };
+        let mut decoder = MessageDecoder::with_limits(WireFormat::V1, limits);
+        decoder.extend(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00]);
+        let err = decoder.next_message().unwrap_err();
+        assert_eq!(ErrorKind::TooLarge { len: 0x10000, max: 100 }, err.kind());
+        assert!(decoder.is_failed());
+        decoder.extend(&util::serialize(&Message::Padding(1)).unwrap());
+        assert_eq!(err, decoder.next_message().unwrap_err());
+
+        // A complete message with a bad body is dropped without losing the stream.
+        let mut decoder = MessageDecoder::new(WireFormat::V1);
+        let mut bad = util::serialize(&Message::DontWantBlock(BlockID(Digest::zero()))).unwrap();
+        bad.truncate(bad.len() - 1);
+        bad[5] -= 1;
+        decoder.extend(&bad);
+        decoder.extend(&util::serialize(&Message::Padding(1)).unwrap());
+        assert!(decoder.next_message().is_err());
+        assert!(!decoder.is_failed());
+        assert_eq!(1, drain(&mut decoder).0.len());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn decoder_reads_from_reader() {
+        let mut raw = BytesMut::new();
+        for message in sample() {
+            message.wire_write(&mut raw, WireFormat::V1);
+        }
+        let mut reader = std::io::Cursor::new(raw.to_vec());
+        let mut decoder = MessageDecoder::new(WireFormat::V1);
+        let mut decoded = Vec::new();
+        while decoder.read_from(&mut reader).unwrap() > 0 {
+            decoded.extend(drain(&mut decoder).0);
+        }
+        decoder.finish().unwrap();
+        assert_eq!(sample().as_printed(), decoded.as_printed());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn decoder_read_from_passes_would_block_through() {
+        use std::io::ErrorKind as IoErrorKind;
+
+        struct Flaky(Vec<IoErrorKind>);
+
+        impl std::io::Read for Flaky {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                match self.0.pop() {
+                    Some(kind) => Err(kind.into()),
+                    None => {
+                        buf[0] = 0xff;
+                        Ok(1)
+                    },
+                }
+            }
+        }
+
+        let mut reader = Flaky(Vec::from([IoErrorKind::WouldBlock, IoErrorKind::Interrupted]));
+        let mut decoder = MessageDecoder::new(WireFormat::V1);
+        let err = decoder.read_from(&mut reader).unwrap_err();
+        assert_eq!(IoErrorKind::WouldBlock, err.kind());
+        assert_eq!(0, decoder.buffered());
+        assert_eq!(1, decoder.read_from(&mut reader).unwrap());
+        assert_eq!(1, decoder.buffered());
+    }
+}
@@ -12,15 +12,20 @@ use core::ops::{
     Sub,
     SubAssign,
 };
+use core::str::FromStr;
 
-use dandelion_wire::Printable;
+use dandelion_wire::{Error, ErrorKind, Printable};
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+use super::civil::{Date, DateTime};
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serializable)]
 pub struct Duration {
     nanoseconds: i64,
 }
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+/// A point in time, counted from the Unix epoch (1970-01-01T00:00:00Z) without leap seconds.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serializable)]
+#[wire(fixed)]
 pub struct Instant {
     since_epoch: Duration,
 }
@@ -29,10 +34,10 @@ const MINUTES_PER_HOUR: i64 = 60;
 const HOURS_PER_DAY: i64 = 24;
 const SECONDS_PER_MINUTE: i64 = 60;
 const SECONDS_PER_HOUR: i64 = SECONDS_PER_MINUTE * MINUTES_PER_HOUR;
-const SECONDS_PER_DAY: i64 = SECONDS_PER_HOUR * HOURS_PER_DAY;
-const NANOS_PER_MICRO: i64 = 1_000;
-const NANOS_PER_MILLI: i64 = 1_000_000;
-const NANOS_PER_SECOND: i64 = 1_000_000_000;
+pub(crate) const SECONDS_PER_DAY: i64 = SECONDS_PER_HOUR * HOURS_PER_DAY;
+pub(crate) const NANOS_PER_MICRO: i64 = 1_000;
+pub(crate) const NANOS_PER_MILLI: i64 = 1_000_000;
+pub(crate) const NANOS_PER_SECOND: i64 = 1_000_000_000;
 const NANOS_PER_MINUTE: i64 = NANOS_PER_SECOND * SECONDS_PER_MINUTE;
 const NANOS_PER_HOUR: i64 = NANOS_PER_SECOND * SECONDS_PER_HOUR;
 const NANOS_PER_DAY: i64 = NANOS_PER_SECOND * SECONDS_PER_DAY;
@@ -189,6 +194,14 @@ impl Instant {
         Self { since_epoch }
     }
 
+    pub const fn from_since_epoch(since_epoch: Duration) -> Self {
+        Self::new(since_epoch)
+    }
+
+    pub const fn since_epoch(self) -> Duration {
+        self.since_epoch
+    }
+
     pub const fn add(self, rhs: Duration) -> Self {
         Self::new(self.since_epoch.add(rhs))
     }
@@ -202,6 +215,27 @@ impl Instant {
     }
 }
 
+#[cfg(feature = "std")]
+impl From<std::time::Duration> for Duration {
+    /// Saturates at [`Duration::MAX`].
+    fn from(duration: std::time::Duration) -> Self {
+        match i64::try_from(duration.as_nanos()) {
+            Ok(nanoseconds) => Self::from_nanoseconds(nanoseconds),
+            Err(_) => Self::MAX,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::time::SystemTime> for Instant {
+    fn from(time: std::time::SystemTime) -> Self {
+        match time.duration_since(std::time::UNIX_EPOCH) {
+            Ok(after) => Self::new(after.into()),
+            Err(before) => Self::new(Duration::from(before.duration()).neg()),
+        }
+    }
+}
+
 macro_rules! op {
     ($ty:ty, $trait:ty, $method:ident, out $out:ty) => {
         impl $trait for $ty {
@@ -319,15 +353,198 @@ impl Printable for Duration {
 impl Printable for Instant {
     fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
         self.since_epoch.print(writer)?;
-        writer.write_str(" since epoch")
+        writer.write_str(SINCE_EPOCH)
+    }
+}
+
+const SINCE_EPOCH: &str = " since epoch";
+
+const INVALID_DURATION: Error = Error::new(ErrorKind::Invalid { type_name: "Duration" });
+const INVALID_INSTANT: Error = Error::new(ErrorKind::Invalid { type_name: "Instant" });
+
+/// Parses exactly the canonical form written by [`Printable::print`], plus `infinity` as an
+/// alias for `+infinity`.
+impl FromStr for Duration {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "0" => return Ok(Self::ZERO),
+            "+infinity" | "infinity" => return Ok(Self::MAX),
+            "-infinity" => return Ok(Self::MIN),
+            _ => {},
+        }
+        match s.strip_prefix("-[") {
+            Some(rest) => {
+                Ok(Self::new(-parse_fields(rest.strip_suffix(']').ok_or(INVALID_DURATION)?)?))
+            },
+            None => Ok(Self::new(parse_fields(s)?)),
+        }
+    }
+}
+
+/// Each unit of a printed duration: suffix, scale, exclusive upper bound, and whether the value
+/// must not be a multiple of 1000 (because a coarser unit would have been printed instead).
+const FIELDS: [(&str, i64, i64, bool); 7] = [
+    ("d", NANOS_PER_DAY, i64::MAX, false),
+    ("h", NANOS_PER_HOUR, HOURS_PER_DAY, false),
+    ("m", NANOS_PER_MINUTE, MINUTES_PER_HOUR, false),
+    ("s", NANOS_PER_SECOND, SECONDS_PER_MINUTE, false),
+    ("ms", NANOS_PER_MILLI, NANOS_PER_SECOND / NANOS_PER_MILLI, false),
+    ("µs", NANOS_PER_MICRO, NANOS_PER_SECOND / NANOS_PER_MICRO, true),
+    ("ns", 1, NANOS_PER_SECOND, true),
+];
+
+/// Parses the fields of a positive, finite duration, e.g. `2h 20m 23s`.
+fn parse_fields(s: &str) -> Result<i64, Error> {
+    let mut next = 0;
+    let mut total = 0i64;
+    for field in s.split(' ') {
+        let digits = field.bytes().take_while(u8::is_ascii_digit).count();
+        let (number, suffix) = field.split_at(digits);
+        let index = FIELDS.iter().position(|(name, ..)| *name == suffix).ok_or(INVALID_DURATION)?;
+        let (_, scale, limit, sub_milli) = FIELDS[index];
+        if index < next || number.is_empty() || number.starts_with('0') {
+            return Err(INVALID_DURATION);
+        }
+        let value: i64 = number.parse().map_err(|_| INVALID_DURATION)?;
+        if value >= limit || (sub_milli && value % 1000 == 0) {
+            return Err(INVALID_DURATION);
+        }
+        total =
+            value.checked_mul(scale).and_then(|n| n.checked_add(total)).ok_or(INVALID_DURATION)?;
+        // Only one sub-second field is ever printed.
+        next = match index < 4 {
+            true => index + 1,
+            false => FIELDS.len(),
+        };
+    }
+    // Anything larger prints as infinity.
+    if total == i64::MAX {
+        return Err(INVALID_DURATION);
+    }
+    Ok(total)
+}
+
+/// Parses either an RFC 3339 timestamp or the form written by [`Printable::print`].
+impl FromStr for Instant {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s.strip_suffix(SINCE_EPOCH) {
+            Some(since_epoch) => Ok(Self::new(since_epoch.parse().map_err(|_| INVALID_INSTANT)?)),
+            None => Self::parse_rfc3339(s),
+        }
+    }
+}
+
+impl Instant {
+    /// Writes the instant in RFC 3339 form, in UTC, e.g. `2024-08-20T12:34:56.789Z`.  Fails for
+    /// infinite instants, which have no calendar date.
+    pub fn print_rfc3339(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        DateTime::from_instant(*self).ok_or(fmt::Error)?.print(writer)
+    }
+
+    /// Parses an RFC 3339 timestamp with up to nanosecond precision.  Timestamps outside the
+    /// representable range saturate to [`Instant::MIN`] or [`Instant::MAX`].  Leap seconds are
+    /// rejected.
+    pub fn parse_rfc3339(s: &str) -> Result<Self, Error> {
+        let mut parser = Parser(s.as_bytes());
+        let year = parser.number(4)?;
+        parser.expect(b"-")?;
+        let month = parser.number(2)?;
+        parser.expect(b"-")?;
+        let day = parser.number(2)?;
+        parser.expect(b"Tt ")?;
+        let hour = parser.number(2)?;
+        parser.expect(b":")?;
+        let minute = parser.number(2)?;
+        parser.expect(b":")?;
+        let second = parser.number(2)?;
+        let mut nanosecond = 0;
+        if parser.0.first() == Some(&b'.') {
+            parser.expect(b".")?;
+            let digits = parser.0.iter().take_while(|b| b.is_ascii_digit()).count();
+            if digits == 0 || digits > 9 {
+                return Err(INVALID_INSTANT);
+            }
+            nanosecond = parser.number(digits)? * 10i64.pow(9 - digits as u32);
+        }
+        let offset = match parser.0.first() {
+            Some(b'Z' | b'z') => {
+                parser.expect(b"Zz")?;
+                Duration::ZERO
+            },
+            Some(&sign @ (b'+' | b'-')) => {
+                parser.expect(b"+-")?;
+                let hours = parser.number(2)?;
+                parser.expect(b":")?;
+                let minutes = parser.number(2)?;
+                if hours >= HOURS_PER_DAY || minutes >= MINUTES_PER_HOUR {
+                    return Err(INVALID_INSTANT);
+                }
+                let offset = Duration::from_hours(hours) + Duration::from_minutes(minutes);
+                if sign == b'-' {
+                    -offset
+                } else {
+                    offset
+                }
+            },
+            _ => return Err(INVALID_INSTANT),
+        };
+        if !parser.0.is_empty() || second >= SECONDS_PER_MINUTE {
+            return Err(INVALID_INSTANT);
+        }
+        let date = Date::new(year, month as u8, day as u8)?;
+        let datetime =
+            DateTime::new(date, hour as u8, minute as u8, second as u8, nanosecond as u32)?;
+        let instant = datetime.to_instant();
+        match instant.since_epoch.is_infinity() {
+            true => Ok(instant),
+            false => Ok(instant - offset),
+        }
+    }
+
+    /// Rounds down to a whole multiple of `unit` since the epoch.  Since an [`Instant`] ignores
+    /// leap seconds, a unit of a day or an hour lands on a UTC day or hour boundary.  Infinite
+    /// instants, and non-positive units, are returned unchanged.
+    pub const fn truncate(self, unit: Duration) -> Self {
+        if self.since_epoch.is_infinity() || !unit.is_positive() {
+            return self;
+        }
+        let nanoseconds = self.since_epoch.as_nanoseconds();
+        let excess = nanoseconds.rem_euclid(unit.as_nanoseconds());
+        Self::new(Duration::from_nanoseconds(nanoseconds.saturating_sub(excess)))
+    }
+}
+
+struct Parser<'a>(&'a [u8]);
+
+impl Parser<'_> {
+    fn number(&mut self, digits: usize) -> Result<i64, Error> {
+        if self.0.len() < digits || !self.0[..digits].iter().all(u8::is_ascii_digit) {
+            return Err(INVALID_INSTANT);
+        }
+        let (number, rest) = self.0.split_at(digits);
+        self.0 = rest;
+        Ok(number.iter().fold(0, |n, digit| n * 10 + (digit - b'0') as i64))
+    }
+
+    /// Consumes one byte, which must be one of `allowed`.
+    fn expect(&mut self, allowed: &[u8]) -> Result<(), Error> {
+        match self.0.split_first() {
+            Some((byte, rest)) if allowed.contains(byte) => {
+                self.0 = rest;
+                Ok(())
+            },
+            _ => Err(INVALID_INSTANT),
+        }
     }
 }
 
-impl_serializable_for_struct!(Duration { nanoseconds: i64 }, fixed size);
 impl_debug_for_printable!(Duration);
 impl_display_for_printable!(Duration);
 
-impl_serializable_for_struct!(Instant { since_epoch: Duration }, fixed size);
 impl_debug_for_printable!(Instant);
 impl_display_for_printable!(Instant);
 
@@ -348,7 +565,10 @@ const fn divmod_i64(lhs: i64, rhs: i64) -> (i64, i64) {
 mod tests {
     use alloc::string::String;
 
+    use dandelion_wire::rand_core::RngCore;
+
     use super::*;
+    use crate::SplitMix64;
 
     #[test]
     fn duration_print() {
@@ -418,4 +638,126 @@ mod tests {
         duration_write_into_test_case!(Duration::from_days(131), "131d", "-[131d]");
         duration_write_into_test_case!(Duration::from_days(997), "997d", "-[997d]");
     }
+
+    fn samples() -> impl Iterator<Item = i64> {
+        let edges = [
+            0,
+            1,
+            -1,
+            i64::MAX,
+            i64::MAX - 1,
+            i64::MIN,
+            i64::MIN + 1,
+            i64::MIN + 2,
+            NANOS_PER_SECOND,
+            NANOS_PER_DAY - 1,
+            -NANOS_PER_DAY,
+        ];
+        let mut rng = SplitMix64::new(0x6475_7261_7469_6f6e);
+        let random = (0..2000).map(move |index| {
+            let z = rng.next_u64() as i64;
+            // Mix in values that are whole milliseconds, seconds and days.
+            match index % 4 {
+                0 => z,
+                1 => z / NANOS_PER_MILLI * NANOS_PER_MILLI,
+                2 => z / NANOS_PER_SECOND * NANOS_PER_SECOND,
+                _ => z / NANOS_PER_DAY * NANOS_PER_DAY,
+            }
+        });
+        edges.into_iter().chain(random)
+    }
+
+    #[test]
+    fn duration_parse() {
+        assert_eq!(Ok(Duration::ZERO), "0".parse());
+        assert_eq!(Ok(Duration::MAX), "infinity".parse());
+        assert_eq!(Ok(Duration::MIN), "-infinity".parse());
+        assert_eq!(Ok(Duration::from_seconds(8423)), "2h 20m 23s".parse());
+        assert_eq!(Ok(Duration::from_nanoseconds(-1)), "-[1ns]".parse());
+        assert_eq!(Ok(Duration::from_microseconds(-37)), "-[37µs]".parse());
+        for invalid in [
+            "",
+            "0s",
+            "60s",
+            "1s 2m",
+            "1s  2ms",
+            "1ms 2ns",
+            "1000µs",
+            "01s",
+            "+1s",
+            "-1s",
+            "-[1s",
+            "-[0]",
+            "1 s",
+            "1h ",
+            "-[+infinity]",
+            "106751d 23h 47m 16s 854775807ns",
+            "106752d",
+        ] {
+            assert_eq!(Err(INVALID_DURATION), invalid.parse::<Duration>(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn duration_round_trip() {
+        for nanoseconds in samples() {
+            let duration = Duration::from_nanoseconds(nanoseconds);
+            let printed = duration.as_printed();
+            assert_eq!(Ok(duration), printed.parse(), "{printed}");
+        }
+    }
+
+    #[test]
+    fn instant_rfc3339() {
+        let rfc3339 = |instant: Instant| {
+            let mut buffer = String::new();
+            instant.print_rfc3339(&mut buffer).map(|_| buffer)
+        };
+        assert_eq!(Ok("1970-01-01T00:00:00Z".into()), rfc3339(Instant::ZERO));
+        let instant = Instant::ZERO + Duration::from_seconds(1_724_157_296);
+        assert_eq!(Ok("2024-08-20T12:34:56Z".into()), rfc3339(instant));
+        let instant = instant + Duration::from_milliseconds(789);
+        assert_eq!(Ok("2024-08-20T12:34:56.789Z".into()), rfc3339(instant));
+        assert_eq!(Ok(instant), "2024-08-20T14:34:56.789+02:00".parse());
+        assert_eq!(Ok(instant), "2024-08-20t12:34:56.789000z".parse());
+        let instant = Instant::ZERO - Duration::from_nanoseconds(1);
+        assert_eq!(Ok("1969-12-31T23:59:59.999999999Z".into()), rfc3339(instant));
+        assert_eq!(
+            Ok("2000-02-29T00:00:00Z".into()),
+            rfc3339(Instant::ZERO + Duration::from_days(11016))
+        );
+        assert!(rfc3339(Instant::MAX).is_err());
+
+        // Out-of-range timestamps saturate.
+        assert_eq!(Ok(Instant::MAX), "9999-12-31T23:59:59Z".parse());
+        assert_eq!(Ok(Instant::MIN), "0000-01-01T00:00:00Z".parse());
+        for invalid in [
+            "2023-02-29T00:00:00Z",
+            "2024-13-01T00:00:00Z",
+            "2024-01-01T24:00:00Z",
+            "2024-12-31T23:59:60Z",
+            "2024-01-01T00:00:00",
+            "2024-01-01T00:00:00.Z",
+            "2024-01-01T00:00:00.1234567890Z",
+            "2024-01-01T00:00:00+0100",
+            "24-01-01T00:00:00Z",
+        ] {
+            let kind = invalid.parse::<Instant>().map_err(|err| err.kind());
+            assert!(matches!(kind, Err(ErrorKind::Invalid { .. })), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn instant_round_trip() {
+        for nanoseconds in samples() {
+            let instant = Instant::ZERO + Duration::from_nanoseconds(nanoseconds);
+            let printed = instant.as_printed();
+            assert_eq!(Ok(instant), printed.parse(), "{printed}");
+            if instant.since_epoch().is_finite() {
+                let mut buffer = String::new();
+                instant.print_rfc3339(&mut buffer).unwrap();
+                assert_eq!(Ok(instant), buffer.parse(), "{buffer}");
+            }
+        }
+    }
 }
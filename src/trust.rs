@@ -0,0 +1,419 @@
+use alloc::collections::BTreeMap;
+use alloc::fmt;
+use alloc::vec::Vec;
+
+use dandelion_wire::bytes::{Buf, BufMut};
+use dandelion_wire::cryptography::ecdh;
+use dandelion_wire::cryptography::sig::RawPublicKey;
+use dandelion_wire::{
+    BaseSerializable,
+    DecodeContext,
+    Printable,
+    PublicBytes,
+    Result,
+    Serializable,
+    Signable,
+    Signed,
+    WireFormat,
+};
+
+use super::claim::{AgreementKey, NodeServes, ReachableVia, TransportAddress, ZoneMember};
+use super::verify::{ClaimPolicy, Rejection};
+use super::{Attestation, AttestationVerifier, Claim, Duration, Entity, EntityType, Instant};
+
+pub const DEFAULT_MAX_ENTRIES: usize = 1 << 14;
+
+/// Holds the most recent verified [`Attestation`] from each attestor and answers questions about
+/// the Endpoint → Node → Zone hierarchy implied by their claims.
+///
+/// The signed form of each attestation is kept and persisted, so that a reloaded store can be
+/// checked again with [`TrustStore::retain_verified`].
+#[derive(Clone)]
+pub struct TrustStore {
+    /// At most this many attestors are kept.  Beyond that, a new attestor displaces the one whose
+    /// attestation was issued longest ago, unless its own is older still, in which case it is
+    /// turned away.
+    pub max_entries: usize,
+    entries: BTreeMap<(u16, RawPublicKey), Entry>,
+}
+
+#[derive(Clone)]
+struct Entry {
+    signed: Signed,
+    attestation: Attestation,
+}
+
+impl Default for TrustStore {
+    fn default() -> Self {
+        Self { max_entries: DEFAULT_MAX_ENTRIES, entries: BTreeMap::new() }
+    }
+}
+
+impl TrustStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Attestation> {
+        self.entries.values().map(|entry| &entry.attestation)
+    }
+
+    /// The attestations as they were signed, e.g. for passing on to a peer.
+    pub fn signed(&self) -> impl Iterator<Item = &Signed> {
+        self.entries.values().map(|entry| &entry.signed)
+    }
+
+    pub fn get(&self, attestor: &Entity) -> Option<&Attestation> {
+        self.entries.get(&key(attestor)).map(|entry| &entry.attestation)
+    }
+
+    /// Verifies `signed` and inserts it, replacing any older attestation from the same attestor.
+    /// Returns `false` if the store already holds an attestation from that attestor which is at
+    /// least as recent, or is full of attestations at least as recent; see
+    /// [`TrustStore::max_entries`].
+    pub fn insert<P: ClaimPolicy>(
+        &mut self,
+        verifier: &AttestationVerifier<P>,
+        signed: Signed,
+        now: Instant,
+    ) -> core::result::Result<bool, Rejection> {
+        let attestation = verifier.verify(&signed, now)?;
+        Ok(self.insert_entry(Entry { signed, attestation }))
+    }
+
+    pub fn remove(&mut self, attestor: &Entity) -> Option<Attestation> {
+        self.entries.remove(&key(attestor)).map(|entry| entry.attestation)
+    }
+
+    /// Drops every attestation issued more than `max_age` before `now`.  Returns the number of
+    /// attestations removed.
+    pub fn expire(&mut self, now: Instant, max_age: Duration) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| now.diff(entry.attestation.time) <= max_age);
+        before.strict_sub(self.entries.len())
+    }
+
+    /// Drops every attestation that `verifier` no longer accepts, e.g. after reloading the store
+    /// or changing policy.  Returns the number of attestations removed.
+    pub fn retain_verified<P: ClaimPolicy>(
+        &mut self,
+        verifier: &AttestationVerifier<P>,
+        now: Instant,
+    ) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| verifier.check(&entry.attestation, now).is_ok());
+        before.strict_sub(self.entries.len())
+    }
+
+    pub fn claims(&self) -> impl Iterator<Item = (&Entity, &Claim)> {
+        self.iter().flat_map(|att| att.claims.0.iter().map(move |claim| (&att.attestor, claim)))
+    }
+
+    /// Nodes that vouch for `endpoint` by serving it, directly or by serving a node that does.
+    pub fn nodes_serving(&self, endpoint: &Entity) -> Vec<Entity> {
+        self.walk(endpoint, |current, claim| match claim {
+            Claim::NodeServes(NodeServes { node, endpoint }) if endpoint == current => Some(*node),
+            _ => None,
+        })
+    }
+
+    /// Entities that are direct members of `zone`.
+    pub fn members_of(&self, zone: &Entity) -> Vec<Entity> {
+        let mut members = Vec::new();
+        for (_, claim) in self.claims() {
+            if let Claim::ZoneMember(ZoneMember { member, zone: parent }) = claim {
+                if parent == zone && !members.contains(member) {
+                    members.push(*member);
+                }
+            }
+        }
+        members
+    }
+
+    /// Zones that `entity` belongs to, directly, through any node that serves it, or through
+    /// zones nested inside others.
+    pub fn zones_of(&self, entity: &Entity) -> Vec<Entity> {
+        let mut found = self.walk(entity, |current, claim| match claim {
+            Claim::ZoneMember(ZoneMember { member, zone }) if member == current => Some(*zone),
+            Claim::NodeServes(NodeServes { node, endpoint }) if endpoint == current => Some(*node),
+            _ => None,
+        });
+        found.retain(|found| found.entity_type == EntityType::Zone && found != entity);
+        found
+    }
+
+    pub fn addresses_of(&self, entity: &Entity) -> Vec<TransportAddress> {
+        let mut addresses = Vec::new();
+        for (_, claim) in self.claims() {
+            if let Claim::ReachableVia(ReachableVia { entity: target, address }) = claim {
+                if target == entity && !addresses.contains(address) {
+                    addresses.push(address.clone());
+                }
+            }
+        }
+        addresses
+    }
+
+    /// The X25519 key that `entity` has published about itself, if any.
+    pub fn agreement_key_of(&self, entity: &Entity) -> Option<ecdh::PublicKey> {
+        self.get(entity)?.claims.0.iter().find_map(|claim| match claim {
+            Claim::AgreementKey(AgreementKey { entity: target, key }) if target == entity => {
+                Some(*key)
+            },
+            _ => None,
+        })
+    }
+
+    fn insert_entry(&mut self, entry: Entry) -> bool {
+        let key = key(&entry.attestation.attestor);
+        if let Some(existing) = self.entries.get_mut(&key) {
+            if existing.attestation.time >= entry.attestation.time {
+                return false;
+            }
+            *existing = entry;
+            return true;
+        }
+        // Make room by dropping the stalest attestation, so that gossip from ever more attestors
+        // costs at most the oldest knowledge rather than unbounded memory.
+        while self.entries.len() >= self.max_entries.max(1) {
+            let oldest = self.entries.iter().min_by_key(|(_, entry)| entry.attestation.time);
+            let Some((&oldest, existing)) = oldest else {
+                break;
+            };
+            if existing.attestation.time >= entry.attestation.time {
+                return false;
+            }
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(key, entry);
+        true
+    }
+
+    /// Every entity reachable from `start` by repeatedly following `step`, in the order found.
+    fn walk(
+        &self,
+        start: &Entity,
+        step: impl Fn(&Entity, &Claim) -> Option<Entity>,
+    ) -> Vec<Entity> {
+        let mut found = Vec::new();
+        let mut visited = Vec::from([*start]);
+        let mut next = 0;
+        while let Some(current) = visited.get(next).copied() {
+            next += 1;
+            for (_, claim) in self.claims() {
+                if let Some(entity) = step(&current, claim) {
+                    if !visited.contains(&entity) {
+                        visited.push(entity);
+                        found.push(entity);
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+fn key(entity: &Entity) -> (u16, RawPublicKey) {
+    (entity.entity_type as u16, entity.public_key.into_exact())
+}
+
+/// Written as the signed attestations; reading checks every signature again.
+impl BaseSerializable for TrustStore {
+    fn wire_write(&self, buffer: &mut dyn BufMut, format: WireFormat) {
+        self.len().wire_write(buffer, format);
+        for signed in self.signed() {
+            signed.wire_write(buffer, format);
+        }
+    }
+    fn wire_read(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<Self> {
+        let mut store = Self::new();
+        for signed in Vec::<Signed>::wire_read(buffer, context)? {
+            let attestation = Attestation::unseal(&signed)?;
+            store.insert_entry(Entry { signed, attestation });
+        }
+        Ok(store)
+    }
+    fn wire_skip(buffer: &mut dyn Buf, context: &mut DecodeContext) -> Result<()> {
+        Vec::<Signed>::wire_skip(buffer, context)
+    }
+}
+
+impl Serializable for TrustStore {
+    fn wire_size(&self, format: WireFormat) -> usize {
+        let mut sum = self.len().wire_size(format);
+        for signed in self.signed() {
+            sum = sum.strict_add(signed.wire_size(format));
+        }
+        sum
+    }
+}
+
+impl Printable for TrustStore {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        writer.write_str("{attestations: [")?;
+        for (index, attestation) in self.iter().enumerate() {
+            writer.write_str(if index == 0 { " " } else { ", " })?;
+            attestation.print(writer)?;
+        }
+        writer.write_str(if self.is_empty() { "]}" } else { " ]}" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dandelion_wire::cryptography::sig::PrivateKey;
+    use dandelion_wire::{util, ErrorKind, SecretBytes};
+
+    use super::*;
+    use crate::Claims;
+
+    fn key(fill: u8) -> PrivateKey {
+        PrivateKey::from_exposed([fill; 32])
+    }
+
+    fn entity(entity_type: EntityType, fill: u8) -> Entity {
+        Entity { entity_type, public_key: key(fill).public_key() }
+    }
+
+    fn at(seconds: i64) -> Instant {
+        Instant::ZERO.add(Duration::from_seconds(seconds))
+    }
+
+    /// Signs as `attestor`, which must have been made by `entity(_, fill)`.
+    fn attest(
+        store: &mut TrustStore,
+        (attestor, fill): (Entity, u8),
+        seconds: i64,
+        claims: Vec<Claim>,
+    ) -> bool {
+        let signed = Attestation { attestor, time: at(seconds), claims: Claims(claims) }
+            .seal(&key(fill))
+            .unwrap();
+        store.insert(&AttestationVerifier::default(), signed, at(100)).unwrap()
+    }
+
+    /// `zone` lies inside `outer` and holds `node`, which serves `endpoint` and is itself served
+    /// by `relay`.
+    fn sample() -> (TrustStore, [Entity; 5]) {
+        let outer = entity(EntityType::Zone, 1);
+        let zone = entity(EntityType::Zone, 2);
+        let node = entity(EntityType::Node, 3);
+        let endpoint = entity(EntityType::Endpoint, 4);
+        let relay = entity(EntityType::Node, 5);
+        let mut store = TrustStore::new();
+        let claims = Vec::from([Claim::ZoneMember(ZoneMember { member: zone, zone: outer })]);
+        attest(&mut store, (outer, 1), 10, claims);
+        let claims = Vec::from([Claim::ZoneMember(ZoneMember { member: node, zone })]);
+        attest(&mut store, (zone, 2), 10, claims);
+        let claims = Vec::from([Claim::NodeServes(NodeServes { node, endpoint })]);
+        attest(&mut store, (node, 3), 10, claims);
+        let claims = Vec::from([Claim::NodeServes(NodeServes { node: relay, endpoint: node })]);
+        attest(&mut store, (relay, 5), 10, claims);
+        (store, [outer, zone, node, endpoint, relay])
+    }
+
+    #[test]
+    fn trust_store_queries() {
+        let (store, [outer, zone, node, endpoint, relay]) = sample();
+        assert_eq!(Vec::from([node, relay]), store.nodes_serving(&endpoint));
+        assert_eq!(Vec::from([relay]), store.nodes_serving(&node));
+        assert_eq!(Vec::from([node]), store.members_of(&zone));
+        assert_eq!(Vec::from([zone, outer]), store.zones_of(&node));
+        assert_eq!(Vec::from([zone, outer]), store.zones_of(&endpoint));
+        assert_eq!(Vec::from([outer]), store.zones_of(&zone));
+        assert!(store.zones_of(&outer).is_empty());
+    }
+
+    #[test]
+    fn trust_store_walks_cycles() {
+        let (mut store, [outer, zone, node, endpoint, _]) = sample();
+        let claims = Vec::from([
+            Claim::ZoneMember(ZoneMember { member: node, zone }),
+            Claim::ZoneMember(ZoneMember { member: outer, zone }),
+        ]);
+        assert!(attest(&mut store, (zone, 2), 20, claims));
+        assert_eq!(Vec::from([zone, outer]), store.zones_of(&endpoint));
+        assert_eq!(Vec::from([outer]), store.zones_of(&zone));
+    }
+
+    #[test]
+    fn trust_store_rejects_unverified() {
+        let (mut store, [outer, _, node, ..]) = sample();
+        let claims =
+            Claims(Vec::from([Claim::ZoneMember(ZoneMember { member: node, zone: outer })]));
+        let signed = Attestation { attestor: node, time: at(20), claims }.seal(&key(3)).unwrap();
+        let verifier = AttestationVerifier::default();
+        let rejection = store.insert(&verifier, signed, at(100)).unwrap_err();
+        assert_eq!(Rejection::ClaimNotPermitted { index: 0 }, rejection);
+        assert_eq!(4, store.len());
+    }
+
+    #[test]
+    fn trust_store_supersedes_by_time() {
+        let (mut store, [outer, zone, node, endpoint, _]) = sample();
+        assert!(!attest(&mut store, (node, 3), 5, Vec::new()));
+        assert_eq!(Vec::from([zone, outer]), store.zones_of(&endpoint));
+
+        assert!(attest(&mut store, (node, 3), 20, Vec::new()));
+        assert_eq!(4, store.len());
+        assert!(store.nodes_serving(&endpoint).is_empty());
+        assert!(store.zones_of(&endpoint).is_empty());
+    }
+
+    #[test]
+    fn trust_store_evicts_oldest_past_cap() {
+        let [first, second, third, late] = [1, 2, 3, 4].map(|fill| entity(EntityType::Node, fill));
+        let mut store = TrustStore::new();
+        store.max_entries = 2;
+        assert!(attest(&mut store, (first, 1), 20, Vec::new()));
+        assert!(attest(&mut store, (second, 2), 10, Vec::new()));
+        assert!(attest(&mut store, (third, 3), 30, Vec::new()));
+        assert_eq!(2, store.len());
+        assert!(store.get(&second).is_none());
+
+        assert!(!attest(&mut store, (late, 4), 5, Vec::new()));
+        assert!(store.get(&late).is_none());
+
+        // Superseding an attestor already held displaces nobody.
+        assert!(attest(&mut store, (first, 1), 40, Vec::new()));
+        assert_eq!(2, store.len());
+        assert!(store.get(&third).is_some());
+    }
+
+    #[test]
+    fn trust_store_expires() {
+        let (mut store, [outer, _, node, ..]) = sample();
+        attest(&mut store, (outer, 1), 90, Vec::new());
+        assert_eq!(3, store.expire(at(150), Duration::from_seconds(60)));
+        assert!(store.get(&node).is_none());
+        assert!(store.get(&outer).is_some());
+    }
+
+    #[test]
+    fn trust_store_round_trip() {
+        let (store, [outer, zone, _, endpoint, _]) = sample();
+        let buffer = util::serialize(&store).unwrap();
+        assert_eq!(store.wire_size(WireFormat::V1), buffer.len());
+        let mut decoded = util::deserialize::<TrustStore>(buffer.clone().into()).unwrap();
+        assert_eq!(4, decoded.len());
+        assert_eq!(Vec::from([zone, outer]), decoded.zones_of(&endpoint));
+        let verifier = AttestationVerifier::default();
+        assert_eq!(0, decoded.retain_verified(&verifier, at(100)));
+        assert_eq!(4, decoded.retain_verified(&verifier, at(100).add(Duration::from_days(31))));
+
+        // A reloaded store checks every signature again.
+        let mut tampered = buffer;
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        let err = util::deserialize::<TrustStore>(tampered.into()).map(|_| ()).unwrap_err();
+        assert_eq!(ErrorKind::BadSignature, err.kind());
+    }
+}
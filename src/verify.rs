@@ -0,0 +1,237 @@
+use alloc::fmt;
+
+use dandelion_wire::{Error, Printable, Signable, Signed};
+
+use super::claim::{AgreementKey, KeySupersedes, NodeServes, ReachableVia, ZoneMember};
+use super::{Attestation, Claim, Duration, Entity, EntityType, Instant};
+
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_days(30);
+pub const DEFAULT_MAX_SKEW: Duration = Duration::from_minutes(5);
+
+pub trait ClaimPolicy {
+    fn permits(&self, attestor: &Entity, claim: &Claim) -> bool;
+}
+
+/// The baseline authorization rules: an entity may only speak for itself or for entities below
+/// it in the Endpoint → Node → Zone hierarchy.  A zone admits its members and a node takes on the
+/// endpoints it serves; nobody may publish an address for anyone but itself.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultClaimPolicy;
+
+impl ClaimPolicy for DefaultClaimPolicy {
+    fn permits(&self, attestor: &Entity, claim: &Claim) -> bool {
+        match claim {
+            Claim::ZoneMember(ZoneMember { zone, .. }) => {
+                attestor.entity_type == EntityType::Zone && attestor == zone
+            },
+            Claim::NodeServes(NodeServes { node, .. }) => attestor == node,
+            Claim::KeySupersedes(KeySupersedes { superseded, .. }) => {
+                attestor.public_key == *superseded
+            },
+            Claim::ReachableVia(ReachableVia { entity, .. }) => attestor == entity,
+            Claim::AgreementKey(AgreementKey { entity, .. }) => attestor == entity,
+        }
+    }
+}
+
+impl<F: Fn(&Entity, &Claim) -> bool> ClaimPolicy for F {
+    fn permits(&self, attestor: &Entity, claim: &Claim) -> bool {
+        self(attestor, claim)
+    }
+}
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Rejection {
+    Unsealable(Error),
+    NotYetValid { time: Instant, now: Instant },
+    Expired { time: Instant, now: Instant },
+    ClaimNotPermitted { index: usize },
+}
+
+impl Printable for Rejection {
+    fn print(&self, writer: &mut dyn fmt::Write) -> fmt::Result {
+        match self {
+            Self::Unsealable(err) => write!(writer, "Unsealable({:?})", err),
+            Self::NotYetValid { time, now } => {
+                write!(writer, "NotYetValid({{time: {}, now: {}}})", time, now)
+            },
+            Self::Expired { time, now } => {
+                write!(writer, "Expired({{time: {}, now: {}}})", time, now)
+            },
+            Self::ClaimNotPermitted { index } => {
+                write!(writer, "ClaimNotPermitted({{index: {}}})", index)
+            },
+        }
+    }
+}
+
+impl_debug_for_printable!(Rejection);
+impl_display_for_printable!(Rejection);
+
+impl From<Error> for Rejection {
+    fn from(err: Error) -> Self {
+        Self::Unsealable(err)
+    }
+}
+
+pub struct AttestationVerifier<P: ClaimPolicy = DefaultClaimPolicy> {
+    pub max_age: Duration,
+    pub max_skew: Duration,
+    pub policy: P,
+}
+
+impl AttestationVerifier {
+    pub fn with_default_policy() -> Self {
+        Self::new(DefaultClaimPolicy)
+    }
+}
+
+impl Default for AttestationVerifier {
+    fn default() -> Self {
+        Self::with_default_policy()
+    }
+}
+
+impl<P: ClaimPolicy> AttestationVerifier<P> {
+    pub fn new(policy: P) -> Self {
+        Self { max_age: DEFAULT_MAX_AGE, max_skew: DEFAULT_MAX_SKEW, policy }
+    }
+
+    pub fn verify(&self, signed: &Signed, now: Instant) -> Result<Attestation, Rejection> {
+        let attestation = Attestation::unseal(signed)?;
+        self.check(&attestation, now)?;
+        Ok(attestation)
+    }
+
+    pub fn check(&self, attestation: &Attestation, now: Instant) -> Result<(), Rejection> {
+        let time = attestation.time;
+        if time > now.add(self.max_skew) {
+            return Err(Rejection::NotYetValid { time, now });
+        }
+        if now.diff(time) > self.max_age {
+            return Err(Rejection::Expired { time, now });
+        }
+        for (index, claim) in attestation.claims.0.iter().enumerate() {
+            if !self.policy.permits(&attestation.attestor, claim) {
+                return Err(Rejection::ClaimNotPermitted { index });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use dandelion_wire::bytes::Bytes;
+    use dandelion_wire::cryptography::sig::{PrivateKey, PublicKey};
+    use dandelion_wire::{ErrorKind, PublicBytes, SecretBytes};
+
+    use super::*;
+    use crate::claim::TransportAddress;
+    use crate::Claims;
+
+    fn entity(entity_type: EntityType, key: &PrivateKey) -> Entity {
+        Entity { entity_type, public_key: key.public_key() }
+    }
+
+    fn other(entity_type: EntityType, fill: u8) -> Entity {
+        Entity { entity_type, public_key: PublicKey::from_exact([fill; 32]) }
+    }
+
+    fn attest(attestor: Entity, time: Instant, claims: Vec<Claim>) -> Attestation {
+        Attestation { attestor, time, claims: Claims(claims) }
+    }
+
+    #[test]
+    fn verify_accepts_zone_membership() {
+        let key = PrivateKey::from_exposed([1; 32]);
+        let zone = entity(EntityType::Zone, &key);
+        let now = Instant::ZERO.add(Duration::from_days(1000));
+        let claim = Claim::ZoneMember(ZoneMember { member: other(EntityType::Node, 9), zone });
+        let signed = attest(zone, now, Vec::from([claim.clone()])).seal(&key).unwrap();
+
+        let verifier = AttestationVerifier::default();
+        let attestation = verifier.verify(&signed, now).unwrap();
+        assert_eq!(attestation.claims.0, Vec::from([claim]));
+    }
+
+    #[test]
+    fn verify_rejects_bad_signature() {
+        let key = PrivateKey::from_exposed([1; 32]);
+        let zone = entity(EntityType::Zone, &key);
+        let mut signed = attest(zone, Instant::ZERO, Vec::new()).seal(&key).unwrap();
+        signed.signature.0[0] ^= 1;
+
+        let verifier = AttestationVerifier::default();
+        assert_eq!(
+            Err(Rejection::Unsealable(ErrorKind::BadSignature.into())),
+            verifier.verify(&signed, Instant::ZERO).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn verify_enforces_validity_window() {
+        let key = PrivateKey::from_exposed([2; 32]);
+        let node = entity(EntityType::Node, &key);
+        let now = Instant::ZERO.add(Duration::from_days(1000));
+        let verifier = AttestationVerifier::default();
+
+        let time = now.add(Duration::from_minutes(4));
+        let signed = attest(node, time, Vec::new()).seal(&key).unwrap();
+        assert!(verifier.verify(&signed, now).is_ok());
+
+        let time = now.add(Duration::from_minutes(6));
+        let signed = attest(node, time, Vec::new()).seal(&key).unwrap();
+        assert_eq!(
+            Err(Rejection::NotYetValid { time, now }),
+            verifier.verify(&signed, now).map(|_| ())
+        );
+
+        let time = now.sub(Duration::from_days(31));
+        let signed = attest(node, time, Vec::new()).seal(&key).unwrap();
+        assert_eq!(
+            Err(Rejection::Expired { time, now }),
+            verifier.verify(&signed, now).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn verify_applies_policy() {
+        let key = PrivateKey::from_exposed([3; 32]);
+        let node = entity(EntityType::Node, &key);
+        let zone = other(EntityType::Zone, 7);
+        let claims = Vec::from([
+            Claim::NodeServes(NodeServes { node, endpoint: other(EntityType::Endpoint, 8) }),
+            Claim::ZoneMember(ZoneMember { member: node, zone }),
+        ]);
+        let signed = attest(node, Instant::ZERO, claims).seal(&key).unwrap();
+
+        let verifier = AttestationVerifier::default();
+        assert_eq!(
+            Err(Rejection::ClaimNotPermitted { index: 1 }),
+            verifier.verify(&signed, Instant::ZERO).map(|_| ())
+        );
+
+        let verifier = AttestationVerifier::new(|_: &Entity, _: &Claim| true);
+        assert!(verifier.verify(&signed, Instant::ZERO).is_ok());
+    }
+
+    #[test]
+    fn default_policy_forbids_speaking_for_others() {
+        let policy = DefaultClaimPolicy;
+        let node = other(EntityType::Node, 1);
+        let endpoint = other(EntityType::Endpoint, 2);
+        let address = TransportAddress(Bytes::from_static(b"192.0.2.1:7000"));
+
+        let serves = Claim::NodeServes(NodeServes { node, endpoint });
+        assert!(policy.permits(&node, &serves));
+        assert!(!policy.permits(&endpoint, &serves));
+
+        let reachable = Claim::ReachableVia(ReachableVia { entity: endpoint, address });
+        assert!(policy.permits(&endpoint, &reachable));
+        assert!(!policy.permits(&node, &reachable));
+        assert!(!policy.permits(&other(EntityType::Zone, 3), &reachable));
+    }
+}